@@ -0,0 +1,783 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use log::debug;
+
+use rocket::mtls::x509::X509Name;
+use rocket::mtls::Certificate;
+use rocket::request::{FromRequest, Outcome};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi;
+use rocket_okapi::okapi::openapi3::{
+    Object, Responses, SecurityRequirement, SecurityScheme, SecuritySchemeData,
+};
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use serde::{Deserialize, Serialize};
+
+use crate::authn::jwt::JwtValidator;
+use crate::authn::secret::KeySecret;
+use crate::config::{ApiKeyConfig, Config};
+
+pub(crate) mod jwt;
+mod secret;
+
+const AUTHENTICATION_HEADER: &'static str = "Authorization";
+const BEARER_PREFIX: &str = "Bearer ";
+const DEFAULT_KEY_NAME: &str = "default";
+/// Kinds of credentials prefixing the caller identities, so that a token subject
+/// or a certificate subject cannot pass for the API key of the same name
+const KEY_IDENTITY: &str = "key";
+const TOKEN_IDENTITY: &str = "jwt";
+const CERTIFICATE_IDENTITY: &str = "cert";
+
+/// The identity of a caller authenticated by the given kind of credentials, `<kind>:<name>`
+fn identity(kind: &str, name: &str) -> String {
+    format!("{}:{}", kind, name)
+}
+
+/// Permission granted to an API key
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    #[serde(rename = "authorize")]
+    Authorize,
+    #[serde(rename = "policies:read")]
+    PoliciesRead,
+    #[serde(rename = "policies:write")]
+    PoliciesWrite,
+    #[serde(rename = "data:read")]
+    DataRead,
+    #[serde(rename = "data:write")]
+    DataWrite,
+    #[serde(rename = "audit:read")]
+    AuditRead,
+    #[serde(rename = "changes:read")]
+    ChangesRead,
+    #[serde(rename = "bundle:read")]
+    BundleRead,
+    #[serde(rename = "bundle:write")]
+    BundleWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 9] = [
+        Scope::Authorize,
+        Scope::PoliciesRead,
+        Scope::PoliciesWrite,
+        Scope::DataRead,
+        Scope::DataWrite,
+        Scope::AuditRead,
+        Scope::ChangesRead,
+        Scope::BundleRead,
+        Scope::BundleWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Authorize => "authorize",
+            Scope::PoliciesRead => "policies:read",
+            Scope::PoliciesWrite => "policies:write",
+            Scope::DataRead => "data:read",
+            Scope::DataWrite => "data:write",
+            Scope::AuditRead => "audit:read",
+            Scope::ChangesRead => "changes:read",
+            Scope::BundleRead => "bundle:read",
+            Scope::BundleWrite => "bundle:write",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("Unknown scope {}", s))
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Scope required by a route, given as the type parameter of the `ApiKey` guard
+pub trait RequiredScope: Send + Sync + 'static {
+    const SCOPE: Scope;
+}
+
+pub mod scopes {
+    use super::{RequiredScope, Scope};
+
+    pub struct Authorize;
+    pub struct PoliciesRead;
+    pub struct PoliciesWrite;
+    pub struct DataRead;
+    pub struct DataWrite;
+    pub struct AuditRead;
+    pub struct ChangesRead;
+    pub struct BundleRead;
+    pub struct BundleWrite;
+
+    impl RequiredScope for Authorize {
+        const SCOPE: Scope = Scope::Authorize;
+    }
+
+    impl RequiredScope for PoliciesRead {
+        const SCOPE: Scope = Scope::PoliciesRead;
+    }
+
+    impl RequiredScope for PoliciesWrite {
+        const SCOPE: Scope = Scope::PoliciesWrite;
+    }
+
+    impl RequiredScope for DataRead {
+        const SCOPE: Scope = Scope::DataRead;
+    }
+
+    impl RequiredScope for DataWrite {
+        const SCOPE: Scope = Scope::DataWrite;
+    }
+
+    impl RequiredScope for AuditRead {
+        const SCOPE: Scope = Scope::AuditRead;
+    }
+
+    impl RequiredScope for ChangesRead {
+        const SCOPE: Scope = Scope::ChangesRead;
+    }
+
+    impl RequiredScope for BundleRead {
+        const SCOPE: Scope = Scope::BundleRead;
+    }
+
+    impl RequiredScope for BundleWrite {
+        const SCOPE: Scope = Scope::BundleWrite;
+    }
+}
+
+struct Key {
+    name: String,
+    secret: KeySecret,
+    scopes: Vec<Scope>,
+}
+
+impl Key {
+    fn new(api_key: &ApiKeyConfig) -> Result<Self, Box<dyn Error>> {
+        let secret = match (api_key.key.as_ref(), api_key.key_hash.as_ref()) {
+            (_, Some(hash)) => KeySecret::parse_hash(hash)
+                .map_err(|err| format!("api key {}: {}", api_key.name, err))?,
+            (Some(key), None) => KeySecret::plain(key.expose()),
+            (None, None) => return Err(format!("api key {} has no key", api_key.name).into()),
+        };
+        Ok(Self {
+            name: api_key.name.clone(),
+            secret,
+            scopes: api_key.scopes.clone(),
+        })
+    }
+}
+
+/// The token of an `Authorization` header holding a key or token, with or without `Bearer `
+fn bearer_token(header: &str) -> &str {
+    header.strip_prefix(BEARER_PREFIX).unwrap_or(header)
+}
+
+/// The attributes of a distinguished name separated by bare commas, in their given order
+fn normalize_distinguished_name(name: &str) -> String {
+    name.split(',').map(str::trim).collect::<Vec<_>>().join(",")
+}
+
+/// Whether a bearer token is a compact JWT rather than an API key
+fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+struct ClientCert {
+    subject: String,
+    scopes: Vec<Scope>,
+}
+
+/// The identity of an authenticated caller and the scopes granted to it
+struct Credentials {
+    name: String,
+    scopes: Vec<Scope>,
+}
+
+/// The API keys, bearer tokens and client certificates accepted by the agent,
+/// authentication is disabled when none is configured
+pub struct KeyRing {
+    keys: Vec<Key>,
+    /// The keys stored as an unsalted digest, by digest
+    digests: HashMap<Vec<u8>, usize>,
+    /// The keys hashed with argon2, by name, sent as `<name>:<key>`
+    argon2_names: HashMap<String, usize>,
+    jwt: Option<JwtValidator>,
+    client_certs: Vec<ClientCert>,
+}
+
+impl KeyRing {
+    pub fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let mut keys = Vec::new();
+        if config.authentication.is_some() || config.authentication_hash.is_some() {
+            keys.push(Key::new(&ApiKeyConfig {
+                name: DEFAULT_KEY_NAME.to_owned(),
+                key: config.authentication.clone(),
+                key_hash: config.authentication_hash.clone(),
+                scopes: Scope::ALL.to_vec(),
+            })?);
+        }
+        for api_key in config.api_keys.iter().flatten() {
+            keys.push(Key::new(api_key)?);
+        }
+        let jwt = match config.jwt.as_ref() {
+            Some(jwt) => Some(JwtValidator::new(jwt)?),
+            None => None,
+        };
+        let client_certs = config
+            .client_certs
+            .iter()
+            .flatten()
+            .map(|client_cert| ClientCert {
+                subject: client_cert.subject.clone(),
+                scopes: client_cert.scopes.clone(),
+            })
+            .collect();
+        Ok(Self::from_parts(keys, jwt, client_certs))
+    }
+
+    /// Index the keys by what identifies them in a token
+    fn from_parts(
+        keys: Vec<Key>,
+        jwt: Option<JwtValidator>,
+        client_certs: Vec<ClientCert>,
+    ) -> Self {
+        let mut digests = HashMap::new();
+        let mut argon2_names = HashMap::new();
+        for (index, key) in keys.iter().enumerate() {
+            match &key.secret {
+                KeySecret::Sha256 { salt, digest } if salt.is_empty() => {
+                    digests.insert(digest.clone(), index);
+                }
+                KeySecret::Argon2(_) => {
+                    argon2_names.insert(key.name.clone(), index);
+                }
+                KeySecret::Sha256 { .. } => {}
+            }
+        }
+        Self {
+            keys,
+            digests,
+            argon2_names,
+            jwt,
+            client_certs,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        !self.keys.is_empty() || self.jwt.is_some() || !self.client_certs.is_empty()
+    }
+
+    /// Match the subject of a verified client certificate, by distinguished or common name.
+    /// The distinguished names are compared without the spaces following the separators,
+    /// so `CN=client,O=org` matches the certificate subject displayed as `CN=client, O=org`
+    fn authenticate_certificate(&self, subject: &X509Name<'_>) -> Option<Credentials> {
+        let distinguished_name = normalize_distinguished_name(&subject.to_string());
+        let common_name = subject
+            .iter_common_name()
+            .next()
+            .and_then(|common_name| common_name.as_str().ok());
+        let client_cert = self.client_certs.iter().find(|client_cert| {
+            normalize_distinguished_name(&client_cert.subject) == distinguished_name
+                || common_name == Some(client_cert.subject.as_str())
+        });
+        if client_cert.is_none() {
+            debug!("Unknown client certificate subject {}", distinguished_name);
+        }
+        client_cert.map(|client_cert| Credentials {
+            name: identity(CERTIFICATE_IDENTITY, &client_cert.subject),
+            scopes: client_cert.scopes.clone(),
+        })
+    }
+
+    /// Authenticate a key by its SHA-256 digest alone, `None` for the keys hashed with argon2
+    /// and for the JWTs, whose checks are slow enough to be rate limited first
+    fn authenticate_digest(&self, header: &str) -> Option<&Key> {
+        let (key, secret) = self.identify(bearer_token(header))?;
+        key.secret.matches_digest(secret).then_some(key)
+    }
+
+    /// Find the only key which may match a token, without verifying a slow hash:
+    /// by the digest of the token, by the salted digests, then by the name prefixing
+    /// the keys hashed with argon2, returning the key and the secret to verify
+    fn identify<'a>(&self, token: &'a str) -> Option<(&Key, &'a str)> {
+        if let Some(index) = self.digests.get(&secret::sha256("", token)) {
+            return Some((&self.keys[*index], token));
+        }
+        let salted = self.keys.iter().find(|key| {
+            matches!(&key.secret, KeySecret::Sha256 { salt, .. } if !salt.is_empty())
+                && key.secret.matches_digest(token)
+        });
+        if let Some(key) = salted {
+            return Some((key, token));
+        }
+        let (name, secret) = token.split_once(':')?;
+        let index = self.argon2_names.get(name)?;
+        Some((&self.keys[*index], secret))
+    }
+
+    /// Authenticate an `Authorization` header holding a key, `Bearer <key>` or `Bearer <jwt>`
+    async fn authenticate(&self, header: &str) -> Option<Credentials> {
+        let token = bearer_token(header);
+        if let Some(jwt) = self.jwt.as_ref().filter(|_| is_jwt(token)) {
+            match jwt.validate(token) {
+                Ok(claims) => {
+                    return Some(Credentials {
+                        name: identity(TOKEN_IDENTITY, &claims.subject),
+                        scopes: claims.scopes,
+                    })
+                }
+                Err(err) => debug!("Rejected bearer token: {}", err),
+            }
+        }
+        let (key, secret) = self.identify(token)?;
+        if !key.secret.matches(secret).await {
+            return None;
+        }
+        Some(Credentials {
+            name: identity(KEY_IDENTITY, &key.name),
+            scopes: key.scopes.clone(),
+        })
+    }
+}
+
+/// Authenticate the caller of a request once, for the guards and the rate limiter,
+/// `None` when the caller is unknown or authentication is disabled
+async fn credentials<'r>(request: &'r rocket::Request<'_>) -> &'r Option<Credentials> {
+    request
+        .local_cache_async(async {
+            let key_ring = match request.rocket().state::<KeyRing>() {
+                Some(key_ring) if key_ring.is_enabled() => key_ring,
+                _ => return None,
+            };
+            // An explicit key or token takes precedence over the client certificate
+            match request.headers().get_one(AUTHENTICATION_HEADER) {
+                Some(header) => key_ring.authenticate(header).await,
+                None => match request.guard::<Certificate<'_>>().await {
+                    Outcome::Success(certificate) => {
+                        key_ring.authenticate_certificate(certificate.subject())
+                    }
+                    _ => None,
+                },
+            }
+        })
+        .await
+}
+
+/// The name of the authenticated caller of a request
+pub async fn caller<'r>(request: &'r rocket::Request<'_>) -> Option<&'r str> {
+    credentials(request)
+        .await
+        .as_ref()
+        .map(|credentials| credentials.name.as_str())
+}
+
+/// The caller of a request as known to the rate limiter, before any argon2 hash or JWT
+/// signature is verified: a key matching its SHA-256 digest or a client certificate.
+/// `None` when authentication is disabled or the credential is not verified yet
+pub async fn limited_caller(request: &rocket::Request<'_>) -> Option<String> {
+    let key_ring = request
+        .rocket()
+        .state::<KeyRing>()
+        .filter(|key_ring| key_ring.is_enabled())?;
+    match request.headers().get_one(AUTHENTICATION_HEADER) {
+        Some(header) => key_ring
+            .authenticate_digest(header)
+            .map(|key| identity(KEY_IDENTITY, &key.name)),
+        None => credentials(request)
+            .await
+            .as_ref()
+            .map(|credentials| credentials.name.clone()),
+    }
+}
+
+/// Request guard accepting the requests holding an API key with the scope `S`
+pub struct ApiKey<S: RequiredScope> {
+    name: Option<String>,
+    scope: PhantomData<S>,
+}
+
+impl<S: RequiredScope> ApiKey<S> {
+    fn new(name: Option<String>) -> Self {
+        Self {
+            name,
+            scope: PhantomData,
+        }
+    }
+
+    /// The name of the key used by the caller, `None` when authentication is disabled
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, S: RequiredScope> FromRequest<'r> for ApiKey<S> {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.rocket().state::<KeyRing>() {
+            Some(key_ring) if key_ring.is_enabled() => {}
+            _ => return Outcome::Success(ApiKey::new(None)),
+        };
+        match credentials(request).await {
+            None => Outcome::Failure((rocket::http::Status::Unauthorized, ())),
+            Some(credentials) if !credentials.scopes.contains(&S::SCOPE) => {
+                Outcome::Failure((rocket::http::Status::Forbidden, ()))
+            }
+            Some(credentials) => Outcome::Success(ApiKey::new(Some(credentials.name.clone()))),
+        }
+    }
+}
+
+impl<'a, S: RequiredScope> OpenApiFromRequest<'a> for ApiKey<S> {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        // Setup global requirement for Security scheme
+        let security_scheme = SecurityScheme {
+            description: Some(
+                r#"Optional API key to access, 
+            used if the agent was started with authentication configuration.
+            Each key is granted a set of scopes, each required by some of the endpoints:
+            `authorize` by `/is_authorized`,
+            `policies:read` and `policies:write` by `/policies`,
+            `data:read` and `data:write` by `/data`,
+            `audit:read` by `/audit`,
+            `changes:read` by `/changes`,
+            `bundle:read` and `bundle:write` by `/bundle` and `/snapshot`.
+            The scope required by each operation is listed in its security requirement.
+            A key lacking the scope of an endpoint is answered with 403.
+            When configured, `Bearer <jwt>` tokens are accepted as well,
+            granting the scopes listed in their scope claim.
+            With mutual TLS, known client certificate subjects are
+            accepted when no key is given."#
+                    .to_owned(),
+            ),
+            // Setup data requirements.
+            // This can be part of the `header`, `query` or `cookie`.
+            // In this case the header `authorization:` needs to be set.
+            data: SecuritySchemeData::ApiKey {
+                name: "authorization".to_owned(),
+                location: "header".to_owned(),
+            },
+            extensions: Object::default(),
+        };
+        // Add the requirement for this route/endpoint
+        // This can change between routes.
+        let mut security_req = SecurityRequirement::new();
+        // Each security requirement needs to be met before access is allowed.
+        // The scope required by the route is listed as the role of the key
+        security_req.insert("ApiKeyAuth".to_owned(), vec![S::SCOPE.to_string()]);
+        // These vvvvvvv-----^^^^^^^^^^ values need to match exactly!
+        Ok(RequestHeaderInput::Security(
+            "ApiKeyAuth".to_owned(),
+            security_scheme,
+            security_req,
+        ))
+    }
+
+    // Optionally add responses
+    // Also see `main.rs` part of this.
+    fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        use rocket_okapi::okapi::openapi3::RefOr;
+        // Can switch between to the but both are checked if they compile correctly
+        Ok(Responses {
+            // Recommended and most strait forward.
+            // And easy to add or remove new responses.
+            responses: okapi::map! {
+                "400".to_owned() => RefOr::Object(crate::errors::schemas::bad_request_response(gen)),
+                "401".to_owned() => RefOr::Object(crate::errors::schemas::unauthorized_response(gen)),
+                "403".to_owned() => RefOr::Object(crate::errors::schemas::forbidden_response(gen)),
+            },
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::password_hash::{PasswordHasher, SaltString};
+    use argon2::Argon2;
+    use rocket::serde::json::serde_json::{self, json};
+
+    use super::*;
+
+    fn key(name: &str, key: Option<&str>, key_hash: Option<String>, scopes: &[Scope]) -> Key {
+        Key::new(&ApiKeyConfig {
+            name: name.to_owned(),
+            key: key.map(|key| key.parse().unwrap()),
+            key_hash,
+            scopes: scopes.to_vec(),
+        })
+        .unwrap()
+    }
+
+    fn key_ring() -> KeyRing {
+        let salt = SaltString::encode_b64(b"fixed test salt").unwrap();
+        let argon2_hash = Argon2::default()
+            .hash_password(b"admin-key", &salt)
+            .unwrap()
+            .to_string();
+        // printf '%s' 'pepperKEY' | sha256sum
+        let sha256_hash =
+            "sha256$pepper$c692503ab4900dc475e205203e4e358b230224386024cf510ada8f47e49fb631";
+        KeyRing::from_parts(
+            vec![
+                key("plain", Some("plain-key"), None, &[Scope::Authorize]),
+                key(
+                    "salted",
+                    None,
+                    Some(sha256_hash.to_owned()),
+                    &[Scope::DataRead],
+                ),
+                key("admin", None, Some(argon2_hash), &Scope::ALL),
+            ],
+            None,
+            Vec::new(),
+        )
+    }
+
+    mod routes {
+        #![allow(unused_imports)]
+        use crate::authn::{scopes, ApiKey};
+
+        #[rocket::get("/data")]
+        pub fn read(_auth: ApiKey<scopes::DataRead>) -> &'static str {
+            "read"
+        }
+
+        #[rocket::put("/data")]
+        pub fn write(_auth: ApiKey<scopes::DataWrite>) -> &'static str {
+            "written"
+        }
+    }
+
+    #[test]
+    fn bearer_token_tests() {
+        assert_eq!(bearer_token("Bearer key"), "key");
+        assert_eq!(bearer_token("key"), "key");
+        assert_eq!(bearer_token("bearer key"), "bearer key");
+        assert_eq!(bearer_token("Bearer  key"), " key");
+        assert!(is_jwt("header.claims.signature"));
+        assert!(!is_jwt("admin:key"));
+    }
+
+    #[tokio::test]
+    async fn authenticate_tests() {
+        let key_ring = key_ring();
+        let name = |credentials: Option<Credentials>| credentials.map(|c| c.name);
+        assert_eq!(
+            name(key_ring.authenticate("plain-key").await).as_deref(),
+            Some("key:plain")
+        );
+        assert_eq!(
+            name(key_ring.authenticate("Bearer plain-key").await).as_deref(),
+            Some("key:plain")
+        );
+        assert_eq!(
+            name(key_ring.authenticate("KEY").await).as_deref(),
+            Some("key:salted")
+        );
+        assert_eq!(
+            name(key_ring.authenticate("Bearer admin:admin-key").await).as_deref(),
+            Some("key:admin")
+        );
+        assert!(key_ring.authenticate("unknown").await.is_none());
+        assert!(key_ring.authenticate("admin:other-key").await.is_none());
+        // An argon2 key is only identified by its name
+        assert!(key_ring.authenticate("admin-key").await.is_none());
+        assert!(key_ring.authenticate("plain:admin-key").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn scope_tests() {
+        use rocket::http::{Header, Status};
+        use rocket::local::asynchronous::{Client, LocalRequest};
+
+        let rocket = rocket::build()
+            .manage(key_ring())
+            .mount("/", rocket::routes![routes::read, routes::write]);
+        let client = Client::untracked(rocket).await.unwrap();
+        async fn status(request: LocalRequest<'_>, key: &str) -> Status {
+            let request = request.header(Header::new("Authorization", key.to_owned()));
+            request.dispatch().await.status()
+        }
+
+        assert_eq!(status(client.get("/data"), "KEY").await, Status::Ok);
+        assert_eq!(status(client.put("/data"), "KEY").await, Status::Forbidden);
+        assert_eq!(
+            status(client.put("/data"), "admin:admin-key").await,
+            Status::Ok
+        );
+        assert_eq!(
+            status(client.get("/data"), "plain-key").await,
+            Status::Forbidden
+        );
+        assert_eq!(
+            status(client.get("/data"), "unknown").await,
+            Status::Unauthorized
+        );
+        assert_eq!(
+            client.get("/data").dispatch().await.status(),
+            Status::Unauthorized
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limit_tests() {
+        use rocket::http::{Header, Status};
+        use rocket::local::asynchronous::Client;
+
+        use crate::services::limits::{self, BodyLimits, LimitsFairing, RateLimiter};
+
+        let rocket = rocket::build()
+            .manage(key_ring())
+            .attach(LimitsFairing::new(
+                Some(RateLimiter::new(1, 2)),
+                BodyLimits::default(),
+            ))
+            .mount(
+                "/v1",
+                limits::with_limits(rocket::routes![routes::read, routes::write]),
+            );
+        let client = Client::untracked(rocket).await.unwrap();
+        let status = |key: &str| {
+            let request = client
+                .get("/v1/data")
+                .header(Header::new("Authorization", key.to_owned()));
+            async move { request.dispatch().await.status() }
+        };
+
+        // Unknown callers share the bucket of their IP
+        assert_eq!(status("unknown").await, Status::Unauthorized);
+        assert_eq!(status("unknown").await, Status::Unauthorized);
+        // Made-up argon2 keys are limited by IP before their hash is verified
+        for _ in 0..10 {
+            assert_eq!(status("admin:wrong-key").await, Status::TooManyRequests);
+        }
+        // So is the valid key, which would have a bucket of its own once verified
+        assert_eq!(status("admin:admin-key").await, Status::TooManyRequests);
+        // A key matching its digest is verified first and limited on its own
+        assert_eq!(status("KEY").await, Status::Ok);
+    }
+
+    #[test]
+    fn openapi_security_tests() {
+        let mut gen = OpenApiGenerator::new(&Default::default());
+        let input = <ApiKey<scopes::DataWrite> as OpenApiFromRequest>::from_request_input(
+            &mut gen,
+            "auth".to_owned(),
+            true,
+        )
+        .unwrap();
+        let RequestHeaderInput::Security(name, scheme, requirement) = input else {
+            panic!("expected a security requirement");
+        };
+        assert_eq!(name, "ApiKeyAuth");
+        assert!(matches!(scheme.data, SecuritySchemeData::ApiKey { .. }));
+        assert!(scheme
+            .description
+            .unwrap()
+            .contains("`data:write` by `/data`"));
+        assert_eq!(
+            requirement.get("ApiKeyAuth"),
+            Some(&vec!["data:write".to_owned()])
+        );
+    }
+
+    #[test]
+    fn openapi_scopes_tests() {
+        let spec = rocket_okapi::openapi_get_spec![
+            crate::routes::policies::get_policies,
+            crate::routes::policies::create_policy,
+            crate::routes::data::update_entities,
+            crate::routes::authorization::is_authorized,
+            crate::routes::audit::get_audit,
+        ];
+        let spec = serde_json::to_value(spec).unwrap();
+        let scopes = |path: &str, method: &str| {
+            spec["paths"][path][method]["security"][0]["ApiKeyAuth"].clone()
+        };
+        assert_eq!(scopes("/policies", "get"), json!(["policies:read"]));
+        assert_eq!(scopes("/policies", "post"), json!(["policies:write"]));
+        assert_eq!(scopes("/data", "put"), json!(["data:write"]));
+        assert_eq!(scopes("/is_authorized", "post"), json!(["authorize"]));
+        assert_eq!(scopes("/audit", "get"), json!(["audit:read"]));
+    }
+
+    /// A self-signed certificate of the subject `CN=client, O=org`, in DER
+    const CLIENT_CERTIFICATE: [&str; 9] = [
+        "308201953082013ba00302010202141651e25c1f690bc87b105cc6fb46920b30cb70e1300a06082a8648ce3d04030230",
+        "1f310f300d06035504030c06636c69656e74310c300a060355040a0c036f72673020170d323631303139303330343035",
+        "5a180f32313236303932353033303430355a301f310f300d06035504030c06636c69656e74310c300a060355040a0c03",
+        "6f72673059301306072a8648ce3d020106082a8648ce3d03010703420004ee3bb0cdf1f4029d690865b00cc166c5970f",
+        "fdcd4c82f939867cd6794f96743ef275c3f8abe58f0049ec636478e231328802c06a6094515b1b8346c849a2d92ea353",
+        "3051301d0603551d0e04160414793975e4e8aa3b945dc68cbdf4effe7b566a49a6301f0603551d230418301680147939",
+        "75e4e8aa3b945dc68cbdf4effe7b566a49a6300f0603551d130101ff040530030101ff300a06082a8648ce3d04030203",
+        "48003045022100dd107d70c9646657f38391dd5953423c0c461c2a81e5342a630be02d6b823541022026073ee3260116",
+        "fbde2d4a342eaf4e98fb3c516fac9eb380aed89d07e4d7062d",
+    ];
+
+    #[test]
+    fn certificate_tests() {
+        use rocket::mtls::x509::{FromDer, X509Certificate};
+
+        let hex = CLIENT_CERTIFICATE.concat();
+        let der: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap())
+            .collect();
+        let (_, certificate) = X509Certificate::from_der(&der).unwrap();
+        let subject = certificate.subject();
+        assert_eq!(subject.to_string(), "CN=client, O=org");
+
+        let client_cert = |subject: &str| ClientCert {
+            subject: subject.to_owned(),
+            scopes: vec![Scope::Authorize],
+        };
+        let name = |subjects: &[&str]| {
+            let key_ring = KeyRing::from_parts(
+                Vec::new(),
+                None,
+                subjects
+                    .iter()
+                    .map(|subject| client_cert(subject))
+                    .collect(),
+            );
+            key_ring
+                .authenticate_certificate(subject)
+                .map(|credentials| credentials.name)
+        };
+        assert_eq!(
+            name(&["CN=client,O=org"]).as_deref(),
+            Some("cert:CN=client,O=org")
+        );
+        assert_eq!(
+            name(&["CN=client, O=org"]).as_deref(),
+            Some("cert:CN=client, O=org")
+        );
+        assert_eq!(name(&["other", "client"]).as_deref(), Some("cert:client"));
+        assert!(name(&["O=org,CN=client"]).is_none());
+        assert!(name(&["CN=client"]).is_none());
+        assert!(name(&["CN=other,O=org", "org"]).is_none());
+    }
+}
@@ -0,0 +1,138 @@
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+const ARGON2_PREFIX: &str = "$argon2";
+const SHA256_PREFIX: &str = "sha256$";
+
+/// The stored form of an API key, never holding the key itself
+pub(crate) enum KeySecret {
+    /// `sha256$<salt>$<hex digest>`, the digest of the salt followed by the key
+    Sha256 { salt: String, digest: Vec<u8> },
+    /// A PHC string as produced by argon2, e.g. `$argon2id$v=19$m=19456,t=2,p=1$...`
+    Argon2(String),
+}
+
+pub(crate) fn sha256(salt: &str, key: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(key.as_bytes());
+    hasher.finalize().to_vec()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => {
+                Some((char::from(*high).to_digit(16)? * 16 + char::from(*low).to_digit(16)?) as u8)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+impl KeySecret {
+    /// Keep only the digest of a plaintext key, so it is compared in constant time
+    pub fn plain(key: &str) -> Self {
+        KeySecret::Sha256 {
+            salt: String::new(),
+            digest: sha256("", key),
+        }
+    }
+
+    pub fn parse_hash(hash: &str) -> Result<Self, String> {
+        if hash.starts_with(ARGON2_PREFIX) {
+            PasswordHash::new(hash).map_err(|err| format!("invalid argon2 hash: {}", err))?;
+            return Ok(KeySecret::Argon2(hash.to_owned()));
+        }
+        let Some((salt, digest)) = hash
+            .strip_prefix(SHA256_PREFIX)
+            .and_then(|hash| hash.split_once('$'))
+        else {
+            return Err("expected an argon2 hash or sha256$<salt>$<hex digest>".to_owned());
+        };
+        match decode_hex(digest) {
+            Some(digest) if digest.len() == 32 => Ok(KeySecret::Sha256 {
+                salt: salt.to_owned(),
+                digest,
+            }),
+            _ => Err("invalid sha256 digest, expected 64 hexadecimal characters".to_owned()),
+        }
+    }
+
+    /// Whether the key matches a SHA-256 digest, `false` for the other hashes
+    pub fn matches_digest(&self, key: &str) -> bool {
+        match self {
+            KeySecret::Sha256 { salt, digest } => sha256(salt, key).ct_eq(digest).into(),
+            KeySecret::Argon2(_) => false,
+        }
+    }
+
+    /// Whether the key matches, verifying an argon2 hash on the blocking thread pool
+    pub async fn matches(&self, key: &str) -> bool {
+        match self {
+            KeySecret::Sha256 { .. } => self.matches_digest(key),
+            KeySecret::Argon2(hash) => {
+                let (hash, key) = (hash.clone(), key.to_owned());
+                rocket::tokio::task::spawn_blocking(move || match PasswordHash::new(&hash) {
+                    Ok(hash) => Argon2::default()
+                        .verify_password(key.as_bytes(), &hash)
+                        .is_ok(),
+                    Err(_) => false,
+                })
+                .await
+                .unwrap_or(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::password_hash::{PasswordHasher, SaltString};
+
+    use super::*;
+
+    #[test]
+    fn decode_hex_tests() {
+        assert_eq!(decode_hex("00ff7A"), Some(vec![0x00, 0xff, 0x7a]));
+        assert_eq!(decode_hex(""), Some(Vec::new()));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+
+    #[tokio::test]
+    async fn sha256_tests() {
+        // printf '%s' 'pepperKEY' | sha256sum
+        let hash = "sha256$pepper$c692503ab4900dc475e205203e4e358b230224386024cf510ada8f47e49fb631";
+        let secret = KeySecret::parse_hash(hash).unwrap();
+        assert!(matches!(&secret, KeySecret::Sha256 { salt, .. } if salt == "pepper"));
+        assert!(secret.matches("KEY").await);
+        assert!(!secret.matches("other").await);
+
+        let secret = KeySecret::plain("key");
+        assert!(secret.matches("key").await);
+        assert!(!secret.matches("key ").await);
+
+        assert!(KeySecret::parse_hash("sha256$pepper").is_err());
+        assert!(KeySecret::parse_hash("sha256$pepper$abcd").is_err());
+        assert!(KeySecret::parse_hash(&format!("sha256$pepper${}", "zz".repeat(32))).is_err());
+        assert!(KeySecret::parse_hash("md5$pepper$abcd").is_err());
+    }
+
+    #[tokio::test]
+    async fn argon2_tests() {
+        let salt = SaltString::encode_b64(b"fixed test salt").unwrap();
+        let hash = Argon2::default()
+            .hash_password(b"key", &salt)
+            .unwrap()
+            .to_string();
+        let secret = KeySecret::parse_hash(&hash).unwrap();
+        assert!(matches!(secret, KeySecret::Argon2(_)));
+        assert!(secret.matches("key").await);
+        assert!(!secret.matches("other").await);
+
+        assert!(KeySecret::parse_hash("$argon2id$v=19$m=19456,t=2,p=1$not base64!$hash").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use cedar_policy::{Authorizer, Entities, PolicySet};
+use clap::Args;
+use rocket::serde::json::serde_json;
+
+use crate::schemas::authorization::{AuthorizationAnswer, AuthorizationCall};
+use crate::services::data::load_from_file::load_entities_from_file;
+use crate::services::data::memory::MemoryDataStore;
+use crate::services::policies::load_from_file::load_policies_from_file;
+use crate::services::policies::memory::MemoryPolicyStore;
+use crate::services::{DataStore, PolicyStore};
+
+/// Evaluate authorization requests offline, as `POST /v1/is_authorized` would
+#[derive(Args, Debug)]
+pub struct AuthorizeArgs {
+    /// Policies file, in the format of `/v1/policies`
+    #[arg(long)]
+    pub policies: PathBuf,
+    /// Entities file, in the format of `/v1/data`
+    #[arg(long)]
+    pub data: Option<PathBuf>,
+    /// Request file, in the format of `/v1/is_authorized`.
+    /// Without it, requests are read from the standard input as JSON lines
+    #[arg(long)]
+    pub request: Option<PathBuf>,
+}
+
+/// The policies and entities the requests are evaluated against
+pub struct OfflineAuthorizer {
+    policies: PolicySet,
+    entities: Entities,
+    authorizer: Authorizer,
+}
+
+impl OfflineAuthorizer {
+    /// Load the files through the memory stores, so they are parsed as the server parses them
+    pub async fn load(policies: PathBuf, data: Option<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let policy_store = MemoryPolicyStore::new();
+        let policies_path = policies.display().to_string();
+        let policies = load_policies_from_file(policies)
+            .await
+            .map_err(|err| format!("{}: {}", policies_path, err))?;
+        policy_store
+            .update_policies(policies.into_inner())
+            .await
+            .map_err(|err| format!("{}: {}", policies_path, err))?;
+        let data_store = MemoryDataStore::new();
+        if let Some(data) = data {
+            let data_path = data.display().to_string();
+            let entities = load_entities_from_file(data)
+                .await
+                .map_err(|err| format!("{}: {}", data_path, err))?;
+            data_store
+                .update_entities(entities)
+                .await
+                .map_err(|err| format!("{}: {}", data_path, err))?;
+        }
+        Ok(Self {
+            policies: policy_store.policy_set().await,
+            entities: data_store.entities().await,
+            authorizer: Authorizer::new(),
+        })
+    }
+
+    pub fn is_authorized(
+        &self,
+        call: AuthorizationCall,
+    ) -> Result<AuthorizationAnswer, Box<dyn Error>> {
+        let request: cedar_policy::Request = call.try_into()?;
+        Ok(AuthorizationAnswer::from(self.authorizer.is_authorized(
+            &request,
+            &self.policies,
+            &self.entities,
+        )))
+    }
+
+    /// Answer the JSON request of every line, writing the answers as JSON lines
+    /// and reporting the invalid requests by line number, returning whether all were answered
+    pub fn answer_lines(
+        &self,
+        input: impl BufRead,
+        mut output: impl Write,
+        mut errors: impl Write,
+    ) -> io::Result<bool> {
+        let mut answered = true;
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let answer = serde_json::from_str::<AuthorizationCall>(&line)
+                .map_err(|err| err.into())
+                .and_then(|call| self.is_authorized(call));
+            match answer {
+                Ok(answer) => writeln!(output, "{}", serde_json::to_string(&answer)?)?,
+                Err(err) => {
+                    answered = false;
+                    writeln!(errors, "line {}: {}", index + 1, err)?;
+                }
+            }
+        }
+        Ok(answered)
+    }
+}
+
+/// Print the answer to the request file, or to every request of the standard input,
+/// returning whether all the requests were answered
+pub async fn run(args: AuthorizeArgs) -> bool {
+    let authorizer = match OfflineAuthorizer::load(args.policies, args.data).await {
+        Ok(authorizer) => authorizer,
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        }
+    };
+    let path = match args.request {
+        Some(path) => path,
+        None => {
+            return authorizer
+                .answer_lines(io::stdin().lock(), io::stdout().lock(), io::stderr())
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    false
+                })
+        }
+    };
+    let answer = std::fs::read_to_string(&path)
+        .map_err(|err| err.into())
+        .and_then(|content| Ok(serde_json::from_str::<AuthorizationCall>(&content)?))
+        .and_then(|call| authorizer.is_authorized(call))
+        .and_then(|answer| Ok(serde_json::to_string_pretty(&answer)?));
+    match answer {
+        Ok(answer) => {
+            println!("{}", answer);
+            true
+        }
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            false
+        }
+    }
+}
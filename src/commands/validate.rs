@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cedar_policy::{PolicySet, Schema, ValidationMode, Validator};
+use clap::{ArgGroup, Args};
+use rocket::serde::json::{serde_json, Value};
+
+use crate::services::data::load_from_file::load_entities_from_file;
+use crate::services::policies::load_from_file::load_policies_from_file;
+
+/// Check policies, entities and a schema offline
+#[derive(Args, Debug)]
+#[command(group(
+    ArgGroup::new("files")
+        .required(true)
+        .multiple(true)
+        .args(["policies", "data", "schema"])
+))]
+pub struct ValidateArgs {
+    /// Policies file, in the format of `/v1/policies`
+    #[arg(long)]
+    pub policies: Option<PathBuf>,
+    /// Entities file, in the format of `/v1/data`
+    #[arg(long)]
+    pub data: Option<PathBuf>,
+    /// Cedar schema the policies and entities must conform to
+    #[arg(long)]
+    pub schema: Option<PathBuf>,
+}
+
+/// An error found in a file, at the line of the policy or entity in error when known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// The message is kept on a single line, as the Cedar errors span several
+    fn new(path: &Path, line: Option<usize>, message: impl fmt::Display) -> Self {
+        let mut single_line = String::new();
+        for part in message.to_string().lines().map(str::trim) {
+            if part.is_empty() {
+                continue;
+            }
+            if single_line.ends_with(':') {
+                single_line.push(' ');
+            } else if !single_line.is_empty() {
+                single_line.push_str("; ");
+            }
+            single_line.push_str(part);
+        }
+        Self {
+            path: path.to_path_buf(),
+            line,
+            message: single_line,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// The line of every element of the top level array of a JSON document, from 1
+fn element_lines(content: &str) -> Vec<usize> {
+    let mut lines = Vec::new();
+    let (mut line, mut depth) = (1, 0);
+    let (mut in_string, mut escaped, mut expecting) = (false, false, false);
+    for character in content.chars() {
+        if in_string {
+            match character {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if !character.is_whitespace() {
+            if expecting && depth == 1 && character != ']' {
+                lines.push(line);
+            }
+            expecting = false;
+            match character {
+                '"' => in_string = true,
+                '[' | '{' => {
+                    depth += 1;
+                    expecting = depth == 1;
+                }
+                ']' | '}' => depth -= 1,
+                ',' => expecting = depth == 1,
+                _ => {}
+            }
+        }
+        if character == '\n' {
+            line += 1;
+        }
+    }
+    lines
+}
+
+/// Check the files, returning every error found
+pub async fn validate(args: &ValidateArgs) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let schema = args
+        .schema
+        .as_ref()
+        .and_then(|path| validate_schema(path, &mut diagnostics));
+    if let Some(path) = args.policies.as_ref() {
+        validate_policies(path, schema.as_ref(), &mut diagnostics).await;
+    }
+    if let Some(path) = args.data.as_ref() {
+        validate_entities(path, schema.as_ref(), &mut diagnostics).await;
+    }
+    diagnostics
+}
+
+fn validate_schema(path: &Path, diagnostics: &mut Vec<Diagnostic>) -> Option<Schema> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => {
+            diagnostics.push(Diagnostic::new(path, None, err));
+            return None;
+        }
+    };
+    let value: Value = match serde_json::from_str(&content) {
+        Ok(value) => value,
+        Err(err) => {
+            diagnostics.push(Diagnostic::new(path, Some(err.line()), err));
+            return None;
+        }
+    };
+    match Schema::from_json_value(value) {
+        Ok(schema) => Some(schema),
+        Err(err) => {
+            diagnostics.push(Diagnostic::new(path, None, err));
+            None
+        }
+    }
+}
+
+async fn validate_policies(
+    path: &Path,
+    schema: Option<&Schema>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let policies = match load_policies_from_file(path.to_path_buf()).await {
+        Ok(policies) => policies.into_inner(),
+        Err(err) => {
+            diagnostics.push(Diagnostic::new(path, None, err));
+            return;
+        }
+    };
+    let lines = element_lines(&fs::read_to_string(path).unwrap_or_default());
+    let mut ids = HashSet::new();
+    let mut policy_lines = Vec::new();
+    let mut policy_set = PolicySet::new();
+    for (index, policy) in policies.iter().enumerate() {
+        let line = lines.get(index).copied();
+        if !ids.insert(policy.id.as_str()) {
+            diagnostics.push(Diagnostic::new(
+                path,
+                line,
+                format!("policy {}: the id is not unique", policy.id),
+            ));
+            continue;
+        }
+        let parsed: Result<cedar_policy::Policy, _> = policy.try_into();
+        match parsed {
+            Ok(parsed) => {
+                policy_lines.push((policy.id.clone(), line));
+                if let Err(err) = policy_set.add(parsed) {
+                    diagnostics.push(Diagnostic::new(
+                        path,
+                        line,
+                        format!("policy {}: {}", policy.id, err),
+                    ));
+                }
+            }
+            Err(err) => diagnostics.push(Diagnostic::new(
+                path,
+                line,
+                format!("policy {}: {}", policy.id, err),
+            )),
+        }
+    }
+    let schema = match schema {
+        Some(schema) => schema.clone(),
+        None => return,
+    };
+    let validator = Validator::new(schema);
+    let result = validator.validate(&policy_set, ValidationMode::default());
+    for err in result.validation_errors() {
+        let id = err.location().policy_id().to_string();
+        let line = policy_lines
+            .iter()
+            .find(|(policy_id, _)| *policy_id == id)
+            .and_then(|(_, line)| *line);
+        diagnostics.push(Diagnostic::new(
+            path,
+            line,
+            format!("policy {}: {}", id, err.error_kind()),
+        ));
+    }
+}
+
+async fn validate_entities(
+    path: &Path,
+    schema: Option<&Schema>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let entities = match load_entities_from_file(path.to_path_buf()).await {
+        Ok(entities) => entities,
+        Err(err) => {
+            diagnostics.push(Diagnostic::new(path, None, err));
+            return;
+        }
+    };
+    let entities = match serde_json::to_value(&entities) {
+        Ok(Value::Array(entities)) => entities,
+        _ => return,
+    };
+    let lines = element_lines(&fs::read_to_string(path).unwrap_or_default());
+    let mut valid = true;
+    for (index, entity) in entities.iter().enumerate() {
+        if let Err(err) =
+            cedar_policy::Entities::from_json_value(Value::Array(vec![entity.clone()]), schema)
+        {
+            valid = false;
+            let uid = entity.get("uid").map(Value::to_string).unwrap_or_default();
+            diagnostics.push(Diagnostic::new(
+                path,
+                lines.get(index).copied(),
+                format!("entity {}: {}", uid, err),
+            ));
+        }
+    }
+    // Such as duplicate entities, only found across the whole file
+    if valid {
+        if let Err(err) = cedar_policy::Entities::from_json_value(Value::Array(entities), schema) {
+            diagnostics.push(Diagnostic::new(path, None, err));
+        }
+    }
+}
+
+/// Print the errors found in the files, returning whether there were none
+pub async fn run(args: &ValidateArgs) -> bool {
+    let diagnostics = validate(args).await;
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{}", diagnostic);
+    }
+    if diagnostics.is_empty() {
+        for path in [&args.schema, &args.policies, &args.data]
+            .into_iter()
+            .flatten()
+        {
+            println!("{}: valid", path.display());
+        }
+    }
+    diagnostics.is_empty()
+}
@@ -1,11 +1,22 @@
+use std::error::Error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::path::Path;
 use std::str::FromStr;
 
 use async_trait::async_trait;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::encode::Encode;
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::{ContentType, Header};
-use rocket::{Data, Request};
+use rocket::request::{FromRequest, Outcome};
+use rocket::route::{self, Handler};
+use rocket::{Data, Request, Response, Route};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -17,6 +28,27 @@ impl Display for EmptyError {
     }
 }
 
+/// Default size in bytes over which a rolling file is rotated
+pub(crate) const DEFAULT_ROLLING_MAX_SIZE: u64 = 10 * 1024 * 1024;
+/// Default number of rotated files kept next to a rolling file
+pub(crate) const DEFAULT_ROLLING_MAX_FILES: u32 = 5;
+
+/// Build a file appender rotated once the file grows over `max_size` bytes,
+/// keeping at most `max_files` rotated files (`<path>.0`, `<path>.1`, ...) next to it
+pub(crate) fn rolling_file_appender(
+    path: &Path,
+    max_size: u64,
+    max_files: u32,
+    encoder: Box<dyn Encode>,
+) -> Result<RollingFileAppender, Box<dyn Error>> {
+    let pattern = format!("{}.{{}}", path.display());
+    let roller = FixedWindowRoller::builder().build(&pattern, max_files)?;
+    let policy = CompoundPolicy::new(Box::new(SizeTrigger::new(max_size)), Box::new(roller));
+    Ok(RollingFileAppender::builder()
+        .encoder(encoder)
+        .build(path, Box::new(policy))?)
+}
+
 pub(crate) struct DefaultContentType(ContentType);
 
 impl DefaultContentType {
@@ -74,3 +106,117 @@ impl Fairing for DefaultContentType {
         }
     }
 }
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    /// Correlation id of the request handled by the current task
+    pub(crate) static CURRENT_REQUEST_ID: RequestId;
+}
+
+/// Correlation id of a request, taken from the `X-Request-Id` header or generated
+#[derive(Clone, Debug)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let is_valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LENGTH
+            && value.chars().all(|c| c.is_ascii_graphic());
+        is_valid.then(|| Self(value.to_owned()))
+    }
+
+    /// The id of the request, generated on first access if the fairing did not run
+    pub(crate) fn of(req: &Request<'_>) -> Self {
+        req.local_cache(RequestId::generate).clone()
+    }
+
+    /// The id of the request handled by the current task, if any
+    pub(crate) fn current() -> Option<Self> {
+        CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId::of(request))
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for RequestId {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+/// Accept or generate the `X-Request-Id` of every request and echo it on the response
+pub struct RequestIdFairing;
+
+#[async_trait]
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "RequestId",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let request_id = req
+            .headers()
+            .get_one(REQUEST_ID_HEADER)
+            .and_then(RequestId::parse)
+            .unwrap_or_else(RequestId::generate);
+        req.local_cache(|| request_id);
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        res.set_header(Header::new(REQUEST_ID_HEADER, RequestId::of(req).0));
+    }
+}
+
+/// Route handler running the wrapped handler with the request id of the task set,
+/// so every log line written while handling the request carries it
+#[derive(Clone)]
+struct RequestIdHandler(Box<dyn Handler>);
+
+#[async_trait]
+impl Handler for RequestIdHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        CURRENT_REQUEST_ID
+            .scope(RequestId::of(req), self.0.handle(req, data))
+            .await
+    }
+}
+
+pub fn with_request_id(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(RequestIdHandler(route.handler));
+            route
+        })
+        .collect()
+}
@@ -1,36 +1,329 @@
 use fmt::Debug;
 use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::ffi::OsStr;
 use std::fmt;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use jsonwebtoken::Algorithm;
 use log::LevelFilter;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+use thiserror::Error;
 
-#[derive(Parser, Serialize, Deserialize, Debug)]
-#[command(author, version, about, long_about = None)]
+use crate::authn::Scope;
+use crate::commands::authorize::AuthorizeArgs;
+use crate::commands::validate::ValidateArgs;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Invalid environment variable: {0}")]
+    Env(#[from] envy::Error),
+    #[error("Unable to load config file {}: {}", path.display(), reason)]
+    File { path: PathBuf, reason: String },
+    #[error("Unknown keys in config file {}: {}", path.display(), keys.join(", "))]
+    UnknownKeys { path: PathBuf, keys: Vec<String> },
+    #[error("Invalid configuration: {}", .0.join("; "))]
+    Invalid(Vec<String>),
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+const REDACTED: &str = "****";
+/// Prefix of the environment variables naming the options, e.g. `CEDAR_AGENT_LOG_LEVEL`
+const ENV_PREFIX: &str = "CEDAR_AGENT_";
+/// Unprefixed environment variables of the options read by earlier versions, still accepted
+const LEGACY_ENV_VARS: &[&str] = &[
+    "AUTHENTICATION",
+    "ADDR",
+    "PORT",
+    "LOG_LEVEL",
+    "DATA",
+    "POLICIES",
+];
+const STORE_SCHEME_SEPARATOR: &str = "://";
+const DEFAULT_STORE: &str = "memory://";
+/// Rocket limit of raw request bodies, used by the bundle uploads
+const DEFAULT_BUNDLE_BODY_LIMIT: u64 = 32 * 1024 * 1024;
+
+/// Location of a policy or data store, as `<scheme>://<location>`
+/// where the scheme names the backend, e.g. `file:///var/lib/cedar`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreUri {
+    pub scheme: String,
+    pub location: String,
+}
+
+impl FromStr for StoreUri {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(STORE_SCHEME_SEPARATOR) {
+            Some((scheme, location)) if !scheme.is_empty() => Ok(StoreUri {
+                scheme: scheme.to_owned(),
+                location: location.to_owned(),
+            }),
+            _ => Err(format!(
+                "invalid store {}, expected <scheme>://<location> such as memory:// or file:///var/lib/cedar",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for StoreUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            self.scheme, STORE_SCHEME_SEPARATOR, self.location
+        )
+    }
+}
+
+/// A secret value, redacted from the debug and serialized output of the configuration
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Secret {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Secret(s.to_owned()))
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// A named API key granted a set of scopes, given as plaintext `key` or as `key_hash`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeyConfig {
+    pub name: String,
+    pub key: Option<Secret>,
+    /// An argon2 PHC string or `sha256$<salt>$<hex digest>`
+    pub key_hash: Option<String>,
+    pub scopes: Vec<Scope>,
+}
+
+/// A client certificate subject, accepted as a caller identity when using mutual TLS
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientCertConfig {
+    /// The full distinguished name (`CN=client,O=org`) or the common name of the subject
+    pub subject: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// An endpoint notified of the changes of the policies and data
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Key of the HMAC-SHA256 signature of the payloads
+    pub secret: Option<Secret>,
+    /// The stores whose changes are sent, `policies` and `data` by default
+    pub stores: Option<Vec<String>>,
+}
+
+/// Validation of JWT bearer tokens
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JwtConfig {
+    /// Required `iss` claim
+    pub issuer: Option<String>,
+    /// Required `aud` claim
+    pub audience: Option<String>,
+    /// JSON Web Key Set files holding the public keys of the issuer
+    #[serde(default)]
+    pub jwks_files: Vec<PathBuf>,
+    /// Public keys of the issuer in PEM format
+    #[serde(default)]
+    pub public_keys: Vec<PathBuf>,
+    /// Accepted signing algorithms, defaults to `RS256`
+    pub algorithms: Option<Vec<Algorithm>>,
+    /// Claim holding the granted scopes, defaults to `scope`
+    pub scopes_claim: Option<String>,
+    /// Scopes granted by the values of the scopes claim, in addition to the scope names
+    #[serde(default)]
+    pub scope_mapping: HashMap<String, Vec<Scope>>,
+    /// Allowed clock skew in seconds when checking `exp`, defaults to 60
+    pub leeway: Option<u64>,
+}
+
+/// The command line, running the server unless another command is given
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub serve: Config,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the server, the default command
+    Serve(Box<Config>),
+    Validate(ValidateArgs),
+    Authorize(AuthorizeArgs),
+}
+
+impl Cli {
+    /// The command to run, `serve` with the top level arguments when none is given
+    pub fn command(self) -> Command {
+        self.command
+            .unwrap_or_else(|| Command::Serve(Box::new(self.serve)))
+    }
+}
+
+#[derive(Args, Serialize, Deserialize, Debug)]
 pub struct Config {
     #[arg(short, long)]
-    pub authentication: Option<String>,
+    pub config: Option<PathBuf>,
+    #[arg(short, long)]
+    pub authentication: Option<Secret>,
+    #[arg(long)]
+    pub authentication_hash: Option<String>,
+    #[arg(skip)]
+    pub api_keys: Option<Vec<ApiKeyConfig>>,
+    #[arg(skip)]
+    pub jwt: Option<JwtConfig>,
+    #[arg(skip)]
+    pub client_certs: Option<Vec<ClientCertConfig>>,
     #[arg(long)]
     pub addr: Option<String>,
     #[arg(short, long)]
     pub port: Option<u16>,
+    #[arg(long)]
+    pub tls_cert: Option<PathBuf>,
+    #[arg(long)]
+    pub tls_key: Option<PathBuf>,
+    #[arg(long)]
+    pub tls_client_ca: Option<PathBuf>,
+    #[arg(long)]
+    pub rate_limit: Option<u32>,
+    #[arg(long)]
+    pub rate_limit_burst: Option<u32>,
+    #[arg(long)]
+    pub data_body_limit: Option<u64>,
+    #[arg(long)]
+    pub policies_body_limit: Option<u64>,
+    #[arg(long)]
+    pub authorization_body_limit: Option<u64>,
+    #[arg(long)]
+    pub bundle_body_limit: Option<u64>,
     #[arg(short, long, value_enum)]
     pub log_level: Option<LevelFilter>,
+    #[arg(long, value_enum)]
+    pub log_format: Option<LogFormat>,
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+    #[arg(long)]
+    pub log_file_max_size: Option<u64>,
+    #[arg(long)]
+    pub log_file_max_files: Option<u32>,
+    #[arg(long)]
+    pub policy_store: Option<String>,
+    #[arg(long)]
+    pub data_store: Option<String>,
     #[arg(short, long)]
     pub data: Option<PathBuf>,
     #[arg(long)]
     pub policies: Option<PathBuf>,
+    /// A tar.gz bundle of policies, data and schema loaded at startup
+    #[arg(long)]
+    pub bundle: Option<PathBuf>,
+    /// Public key in PEM format the bundle manifests must be signed with
+    #[arg(long)]
+    pub bundle_public_key: Option<PathBuf>,
+    #[arg(long)]
+    pub bundle_signing_algorithm: Option<Algorithm>,
+    /// URL the bundle is periodically fetched from
+    #[arg(long)]
+    pub bundle_url: Option<String>,
+    #[arg(long)]
+    pub bundle_url_authentication: Option<Secret>,
+    /// Seconds between the fetches of the bundle URL
+    #[arg(long)]
+    pub bundle_poll_interval: Option<u64>,
+    /// URL or local path of a Git repository the policies and data are read from
+    #[arg(long)]
+    pub git_repository: Option<String>,
+    #[arg(long)]
+    pub git_ref: Option<String>,
+    /// Directory of the repository holding the policies and data
+    #[arg(long)]
+    pub git_path: Option<String>,
+    /// Seconds between the fetches of the Git repository
+    #[arg(long)]
+    pub git_poll_interval: Option<u64>,
+    /// Restore the policies and data from this snapshot file at startup
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+    /// Write a snapshot of the policies and data to this file on graceful shutdown
+    #[arg(long)]
+    pub snapshot_on_shutdown: Option<PathBuf>,
+    #[arg(long)]
+    pub admin_policies: Option<PathBuf>,
+    #[arg(long)]
+    pub admin_data: Option<PathBuf>,
+    #[arg(long)]
+    pub audit_log: Option<PathBuf>,
+    #[arg(long)]
+    pub audit_log_hash_chain: Option<bool>,
+    #[arg(skip)]
+    pub webhooks: Option<Vec<WebhookConfig>>,
+    #[arg(long)]
+    pub webhook_max_attempts: Option<u32>,
+    #[arg(long)]
+    pub webhook_dead_letter_log: Option<PathBuf>,
+    /// Base URL of the agent to follow, making this agent a read-only replica
+    #[arg(long)]
+    pub leader: Option<String>,
+    #[arg(long)]
+    pub leader_authentication: Option<Secret>,
+    #[arg(long)]
+    pub decision_log: Option<String>,
+    #[arg(long)]
+    pub decision_log_max_size: Option<u64>,
+    #[arg(long)]
+    pub decision_log_max_files: Option<u32>,
+    #[arg(long)]
+    pub decision_log_sample_rate: Option<f64>,
+    #[arg(long, value_delimiter = ',')]
+    pub decision_log_mask: Option<Vec<String>>,
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+    #[arg(long)]
+    pub otlp_service_name: Option<String>,
 }
 
 impl Into<rocket::figment::Figment> for &Config {
     fn into(self) -> rocket::figment::Figment {
         let mut config = rocket::Config::figment();
-        if let Some(authentication) = self.authentication.borrow() {
-            config = config.merge(("authentication", authentication));
-        }
         if let Some(addr) = self.addr.borrow() {
             config = config.merge(("address", addr));
         }
@@ -39,6 +332,28 @@ impl Into<rocket::figment::Figment> for &Config {
         } else {
             config = config.merge(("port", 8180))
         }
+        // The JSON bodies are limited as they are read by the limit of their route family
+        for (family, limit) in [
+            ("data", self.data_body_limit),
+            ("policies", self.policies_body_limit),
+            ("authorization", self.authorization_body_limit),
+        ] {
+            if let Some(limit) = limit {
+                config = config.merge((format!("limits.json/{}", family), limit));
+            }
+        }
+        config = config.merge(("limits.bytes", self.bundle_max_size()));
+        if let (Some(tls_cert), Some(tls_key)) = (self.tls_cert.borrow(), self.tls_key.borrow()) {
+            config = config
+                .merge(("tls.certs", tls_cert))
+                .merge(("tls.key", tls_key));
+            // Every client must present a certificate, including those using an API key or token
+            if let Some(tls_client_ca) = self.tls_client_ca.borrow() {
+                config = config
+                    .merge(("tls.mutual.ca_certs", tls_client_ca))
+                    .merge(("tls.mutual.mandatory", true));
+            }
+        }
         if let Some(data) = self.data.borrow() {
             config = config.merge(("data", data));
         }
@@ -51,46 +366,486 @@ impl Into<rocket::figment::Figment> for &Config {
 }
 
 impl Config {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Config {
+            config: None,
             authentication: None,
+            authentication_hash: None,
+            api_keys: None,
+            jwt: None,
+            client_certs: None,
             addr: None,
             port: None,
+            tls_cert: None,
+            tls_key: None,
+            tls_client_ca: None,
+            rate_limit: None,
+            rate_limit_burst: None,
+            data_body_limit: None,
+            policies_body_limit: None,
+            authorization_body_limit: None,
+            bundle_body_limit: None,
             log_level: None,
+            log_format: None,
+            log_file: None,
+            log_file_max_size: None,
+            log_file_max_files: None,
+            policy_store: None,
+            data_store: None,
             data: None,
             policies: None,
+            bundle: None,
+            bundle_public_key: None,
+            bundle_signing_algorithm: None,
+            bundle_url: None,
+            bundle_url_authentication: None,
+            bundle_poll_interval: None,
+            git_repository: None,
+            git_ref: None,
+            git_path: None,
+            git_poll_interval: None,
+            snapshot: None,
+            snapshot_on_shutdown: None,
+            admin_policies: None,
+            admin_data: None,
+            audit_log: None,
+            audit_log_hash_chain: None,
+            webhooks: None,
+            webhook_max_attempts: None,
+            webhook_dead_letter_log: None,
+            leader: None,
+            leader_authentication: None,
+            decision_log: None,
+            decision_log_max_size: None,
+            decision_log_max_files: None,
+            decision_log_sample_rate: None,
+            decision_log_mask: None,
+            otlp_endpoint: None,
+            otlp_service_name: None,
         }
     }
 
     fn merge(configs: Vec<Config>) -> Config {
         let mut config = Config::new();
         for c in configs {
+            config.config = c.config.or(config.config);
             config.authentication = c.authentication.or(config.authentication);
+            config.authentication_hash = c.authentication_hash.or(config.authentication_hash);
+            config.api_keys = c.api_keys.or(config.api_keys);
+            config.jwt = c.jwt.or(config.jwt);
             config.addr = c.addr.or(config.addr);
+            config.client_certs = c.client_certs.or(config.client_certs);
             config.port = c.port.or(config.port);
+            config.tls_cert = c.tls_cert.or(config.tls_cert);
+            config.tls_key = c.tls_key.or(config.tls_key);
+            config.tls_client_ca = c.tls_client_ca.or(config.tls_client_ca);
+            config.rate_limit = c.rate_limit.or(config.rate_limit);
+            config.rate_limit_burst = c.rate_limit_burst.or(config.rate_limit_burst);
+            config.data_body_limit = c.data_body_limit.or(config.data_body_limit);
+            config.policies_body_limit = c.policies_body_limit.or(config.policies_body_limit);
+            config.authorization_body_limit = c
+                .authorization_body_limit
+                .or(config.authorization_body_limit);
+            config.bundle_body_limit = c.bundle_body_limit.or(config.bundle_body_limit);
             config.log_level = c.log_level.or(config.log_level);
+            config.log_format = c.log_format.or(config.log_format);
+            config.log_file = c.log_file.or(config.log_file);
+            config.log_file_max_size = c.log_file_max_size.or(config.log_file_max_size);
+            config.log_file_max_files = c.log_file_max_files.or(config.log_file_max_files);
+            config.policy_store = c.policy_store.or(config.policy_store);
+            config.data_store = c.data_store.or(config.data_store);
             config.data = c.data.or(config.data);
             config.policies = c.policies.or(config.policies);
+            config.bundle = c.bundle.or(config.bundle);
+            config.bundle_public_key = c.bundle_public_key.or(config.bundle_public_key);
+            config.bundle_signing_algorithm = c
+                .bundle_signing_algorithm
+                .or(config.bundle_signing_algorithm);
+            config.bundle_url = c.bundle_url.or(config.bundle_url);
+            config.bundle_url_authentication = c
+                .bundle_url_authentication
+                .or(config.bundle_url_authentication);
+            config.bundle_poll_interval = c.bundle_poll_interval.or(config.bundle_poll_interval);
+            config.git_repository = c.git_repository.or(config.git_repository);
+            config.git_ref = c.git_ref.or(config.git_ref);
+            config.git_path = c.git_path.or(config.git_path);
+            config.git_poll_interval = c.git_poll_interval.or(config.git_poll_interval);
+            config.snapshot = c.snapshot.or(config.snapshot);
+            config.snapshot_on_shutdown = c.snapshot_on_shutdown.or(config.snapshot_on_shutdown);
+            config.admin_policies = c.admin_policies.or(config.admin_policies);
+            config.admin_data = c.admin_data.or(config.admin_data);
+            config.audit_log = c.audit_log.or(config.audit_log);
+            config.audit_log_hash_chain = c.audit_log_hash_chain.or(config.audit_log_hash_chain);
+            config.webhooks = c.webhooks.or(config.webhooks);
+            config.webhook_max_attempts = c.webhook_max_attempts.or(config.webhook_max_attempts);
+            config.webhook_dead_letter_log =
+                c.webhook_dead_letter_log.or(config.webhook_dead_letter_log);
+            config.leader = c.leader.or(config.leader);
+            config.leader_authentication = c.leader_authentication.or(config.leader_authentication);
+            config.decision_log = c.decision_log.or(config.decision_log);
+            config.decision_log_max_size = c.decision_log_max_size.or(config.decision_log_max_size);
+            config.decision_log_max_files =
+                c.decision_log_max_files.or(config.decision_log_max_files);
+            config.decision_log_sample_rate = c
+                .decision_log_sample_rate
+                .or(config.decision_log_sample_rate);
+            config.decision_log_mask = c.decision_log_mask.or(config.decision_log_mask);
+            config.otlp_endpoint = c.otlp_endpoint.or(config.otlp_endpoint);
+            config.otlp_service_name = c.otlp_service_name.or(config.otlp_service_name);
         }
 
         config
     }
 
-    fn from_args() -> Self {
-        Self::parse()
+    /// Read the prefixed environment variables, and the unprefixed ones of earlier versions
+    /// unless the prefixed variable is set too
+    fn from_env<I>(vars: I) -> Result<Self, ConfigError>
+    where
+        I: Iterator<Item = (String, String)>,
+    {
+        let vars: Vec<(String, String)> = vars.collect();
+        let legacy = vars
+            .iter()
+            .filter(|(name, _)| LEGACY_ENV_VARS.contains(&name.as_str()))
+            .cloned();
+        let legacy: Config = envy::from_iter(legacy)?;
+        let prefixed: Config = envy::prefixed(ENV_PREFIX).from_iter(vars)?;
+        Ok(Config::merge(vec![legacy, prefixed]))
+    }
+
+    /// Load the configuration from a TOML or YAML file, rejecting unknown keys
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let file_error = |reason: String| ConfigError::File {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let contents = fs::read_to_string(path).map_err(|err| file_error(err.to_string()))?;
+        let mut unknown_keys = Vec::new();
+        let track_unknown = |key: serde_ignored::Path| unknown_keys.push(key.to_string());
+        let config: Config = match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => {
+                serde_ignored::deserialize(toml::Deserializer::new(&contents), track_unknown)
+                    .map_err(|err| file_error(err.to_string()))?
+            }
+            Some("yaml") | Some("yml") => serde_ignored::deserialize(
+                serde_yaml::Deserializer::from_str(&contents),
+                track_unknown,
+            )
+            .map_err(|err| file_error(err.to_string()))?,
+            _ => {
+                return Err(file_error(
+                    "expected a .toml, .yaml or .yml file".to_owned(),
+                ))
+            }
+        };
+        if !unknown_keys.is_empty() {
+            return Err(ConfigError::UnknownKeys {
+                path: path.to_path_buf(),
+                keys: unknown_keys,
+            });
+        }
+
+        Ok(config)
+    }
+
+    pub fn policy_store_uri(&self) -> StoreUri {
+        StoreUri::from_str(self.policy_store.as_deref().unwrap_or(DEFAULT_STORE))
+            .unwrap_or_else(|_| StoreUri::from_str(DEFAULT_STORE).unwrap())
+    }
+
+    pub fn data_store_uri(&self) -> StoreUri {
+        StoreUri::from_str(self.data_store.as_deref().unwrap_or(DEFAULT_STORE))
+            .unwrap_or_else(|_| StoreUri::from_str(DEFAULT_STORE).unwrap())
     }
 
-    fn from_env() -> Self {
-        match envy::from_env() {
-            Ok(env) => env,
-            Err(_) => Self::new(),
+    /// The size limit of a bundle, whether uploaded or polled
+    pub fn bundle_max_size(&self) -> u64 {
+        self.bundle_body_limit.unwrap_or(DEFAULT_BUNDLE_BODY_LIMIT)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        let mut key_names = Vec::new();
+        for api_key in self.api_keys.iter().flatten() {
+            if key_names.contains(&&api_key.name) {
+                errors.push(format!(
+                    "api key name {} is used more than once",
+                    api_key.name
+                ));
+            }
+            key_names.push(&api_key.name);
+            match (api_key.key.as_ref(), api_key.key_hash.as_ref()) {
+                (Some(key), None) if key.expose().is_empty() => {
+                    errors.push(format!("api key {} has an empty key", api_key.name));
+                }
+                (Some(_), Some(_)) | (None, None) => {
+                    errors.push(format!(
+                        "api key {} requires exactly one of key and key_hash",
+                        api_key.name
+                    ));
+                }
+                _ => {}
+            }
+        }
+        if self.authentication.is_some() && self.authentication_hash.is_some() {
+            errors.push("authentication and authentication_hash are mutually exclusive".to_owned());
+        }
+        if let Some(jwt) = self.jwt.as_ref() {
+            if jwt.jwks_files.is_empty() && jwt.public_keys.is_empty() {
+                errors.push("jwt requires jwks_files or public_keys".to_owned());
+            }
+            for path in jwt.jwks_files.iter().chain(jwt.public_keys.iter()) {
+                if !path.is_file() {
+                    errors.push(format!("jwt key file {} does not exist", path.display()));
+                }
+            }
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            errors.push("tls_cert and tls_key must be configured together".to_owned());
+        }
+        if self.tls_client_ca.is_some() && self.tls_cert.is_none() {
+            errors.push("tls_client_ca requires tls_cert and tls_key".to_owned());
+        }
+        if self.client_certs.is_some() && self.tls_client_ca.is_none() {
+            errors.push("client_certs requires tls_client_ca".to_owned());
+        }
+        if self.admin_data.is_some() && self.admin_policies.is_none() {
+            errors.push("admin_data requires admin_policies".to_owned());
+        }
+        for (name, path) in [
+            ("data", &self.data),
+            ("policies", &self.policies),
+            ("bundle", &self.bundle),
+            ("bundle_public_key", &self.bundle_public_key),
+            ("admin_data", &self.admin_data),
+            ("admin_policies", &self.admin_policies),
+            ("tls_cert", &self.tls_cert),
+            ("tls_key", &self.tls_key),
+            ("tls_client_ca", &self.tls_client_ca),
+        ] {
+            if let Some(path) = path {
+                if !path.is_file() {
+                    errors.push(format!("{} file {} does not exist", name, path.display()));
+                }
+            }
+        }
+        if let Some(rate) = self.decision_log_sample_rate {
+            if !(0.0..=1.0).contains(&rate) {
+                errors.push(format!(
+                    "decision_log_sample_rate must be between 0.0 and 1.0, got {}",
+                    rate
+                ));
+            }
+        }
+        for (name, max_files) in [
+            ("decision_log_max_files", self.decision_log_max_files),
+            ("log_file_max_files", self.log_file_max_files),
+        ] {
+            if max_files == Some(0) {
+                errors.push(format!("{} must be greater than 0", name));
+            }
+        }
+        for store in [self.policy_store.as_deref(), self.data_store.as_deref()] {
+            if let Some(Err(err)) = store.map(StoreUri::from_str) {
+                errors.push(err);
+            }
+        }
+        if self.audit_log_hash_chain.is_some() && self.audit_log.is_none() {
+            errors.push("audit_log_hash_chain requires audit_log".to_owned());
+        }
+        for webhook in self.webhooks.iter().flatten() {
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                errors.push(format!(
+                    "webhook url {} must be an http or https url",
+                    webhook.url
+                ));
+            }
+            for store in webhook.stores.iter().flatten() {
+                if store != "policies" && store != "data" {
+                    errors.push(format!(
+                        "unknown webhook store {}, expected policies or data",
+                        store
+                    ));
+                }
+            }
+        }
+        if self.webhook_max_attempts == Some(0) {
+            errors.push("webhook_max_attempts must be greater than 0".to_owned());
+        }
+        if self.webhook_dead_letter_log.is_some() && self.webhooks.is_none() {
+            errors.push("webhook_dead_letter_log requires webhooks".to_owned());
+        }
+        for (name, source) in [
+            ("bundle", self.bundle.is_some()),
+            ("bundle_url", self.bundle_url.is_some()),
+            ("git_repository", self.git_repository.is_some()),
+            ("snapshot", self.snapshot.is_some()),
+        ] {
+            if source && (self.policies.is_some() || self.data.is_some()) {
+                errors.push(format!("{} cannot be combined with policies or data", name));
+            }
+        }
+        if let Some(url) = self.bundle_url.as_ref() {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                errors.push(format!("bundle_url {} must be an http or https url", url));
+            }
+        } else {
+            for (name, value) in [
+                (
+                    "bundle_url_authentication",
+                    self.bundle_url_authentication.is_some(),
+                ),
+                ("bundle_poll_interval", self.bundle_poll_interval.is_some()),
+            ] {
+                if value {
+                    errors.push(format!("{} requires bundle_url", name));
+                }
+            }
+        }
+        if self.bundle_poll_interval == Some(0) {
+            errors.push("bundle_poll_interval must be greater than 0".to_owned());
+        }
+        if self.git_repository.is_some() {
+            if self.bundle.is_some() || self.bundle_url.is_some() {
+                errors
+                    .push("git_repository cannot be combined with bundle or bundle_url".to_owned());
+            }
+        } else {
+            for (name, value) in [
+                ("git_ref", self.git_ref.is_some()),
+                ("git_path", self.git_path.is_some()),
+                ("git_poll_interval", self.git_poll_interval.is_some()),
+            ] {
+                if value {
+                    errors.push(format!("{} requires git_repository", name));
+                }
+            }
+        }
+        if self.git_poll_interval == Some(0) {
+            errors.push("git_poll_interval must be greater than 0".to_owned());
+        }
+        // Either would be read by Git as an option rather than as a repository or reference
+        for (name, value) in [
+            ("git_repository", self.git_repository.as_ref()),
+            ("git_ref", self.git_ref.as_ref()),
+        ] {
+            if value.is_some_and(|value| value.starts_with('-')) {
+                errors.push(format!("{} must not start with '-'", name));
+            }
+        }
+        if self
+            .git_ref
+            .as_ref()
+            .is_some_and(|reference| reference.contains(':'))
+        {
+            errors.push("git_ref must not contain ':'".to_owned());
+        }
+        if self.snapshot.is_some() && self.bundle.is_some() {
+            errors.push("snapshot cannot be combined with bundle".to_owned());
+        }
+        // The snapshot written on shutdown does not exist before the first shutdown
+        if let Some(path) = self.snapshot.as_ref() {
+            if !path.is_file() && self.snapshot_on_shutdown.as_ref() != Some(path) {
+                errors.push(format!("snapshot file {} does not exist", path.display()));
+            }
+        }
+        if self.bundle_signing_algorithm.is_some() && self.bundle_public_key.is_none() {
+            errors.push("bundle_signing_algorithm requires bundle_public_key".to_owned());
+        }
+        if let Some(algorithm @ (Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)) =
+            self.bundle_signing_algorithm
+        {
+            errors.push(format!(
+                "bundle_signing_algorithm {:?} is not a public key algorithm",
+                algorithm
+            ));
+        }
+        if let Some(leader) = self.leader.as_ref() {
+            if !leader.starts_with("http://") && !leader.starts_with("https://") {
+                errors.push(format!("leader {} must be an http or https url", leader));
+            }
+            // A follower only holds the policies and data of its leader
+            if self.policies.is_some()
+                || self.data.is_some()
+                || self.bundle.is_some()
+                || self.bundle_url.is_some()
+                || self.git_repository.is_some()
+                || self.snapshot.is_some()
+            {
+                errors.push(
+                    "leader cannot be combined with policies, data, bundle, bundle_url, git_repository or snapshot"
+                        .to_owned(),
+                );
+            }
+        } else if self.leader_authentication.is_some() {
+            errors.push("leader_authentication requires leader".to_owned());
+        }
+        if self.rate_limit_burst.is_some() && self.rate_limit.is_none() {
+            errors.push("rate_limit_burst requires rate_limit".to_owned());
+        }
+        for (name, value) in [
+            ("rate_limit", self.rate_limit),
+            ("rate_limit_burst", self.rate_limit_burst),
+        ] {
+            if value == Some(0) {
+                errors.push(format!("{} must be greater than 0", name));
+            }
+        }
+        for (name, max_size) in [
+            ("decision_log_max_size", self.decision_log_max_size),
+            ("log_file_max_size", self.log_file_max_size),
+            ("data_body_limit", self.data_body_limit),
+            ("policies_body_limit", self.policies_body_limit),
+            ("authorization_body_limit", self.authorization_body_limit),
+            ("bundle_body_limit", self.bundle_body_limit),
+        ] {
+            if max_size == Some(0) {
+                errors.push(format!("{} must be greater than 0", name));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
         }
     }
 }
 
-pub fn init() -> Config {
-    let args = Config::from_args();
-    let env = Config::from_env();
+/// Merge the command line arguments of the server with the environment and the config file
+pub fn init(args: Config) -> Result<Config, ConfigError> {
+    load(args, std::env::vars())
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The unprefixed environment variables of earlier versions that are set
+pub fn legacy_env_vars<I>(vars: I) -> Vec<String>
+where
+    I: Iterator<Item = (String, String)>,
+{
+    vars.map(|(name, _)| name)
+        .filter(|name| LEGACY_ENV_VARS.contains(&name.as_str()))
+        .collect()
+}
+
+/// Merge the config file, the command line arguments and the environment variables,
+/// each taking precedence over the previous ones
+pub fn load<I>(args: Config, vars: I) -> Result<Config, ConfigError>
+where
+    I: Iterator<Item = (String, String)>,
+{
+    let env = Config::from_env(vars)?;
+    let file = match env.config.as_ref().or(args.config.as_ref()) {
+        Some(path) => Config::from_file(path)?,
+        None => Config::new(),
+    };
 
-    Config::merge(vec![args, env])
+    let config = Config::merge(vec![file, args, env]);
+    config.validate()?;
+    Ok(config)
 }
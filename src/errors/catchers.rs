@@ -12,6 +12,7 @@ pub fn handle_500(status: Status, req: &Request<'_>) -> ErrorResponse {
         reason: format!("An error occurred during handling {req_url}"),
         description: "An unexpected error has occurred".to_owned(),
         code: status.code,
+        request_id: None,
     };
 }
 
@@ -22,6 +23,7 @@ pub fn handle_400(_req: &Request<'_>) -> ErrorResponse {
             .to_owned(),
         reason: "The request content is not valid".to_owned(),
         code: 400,
+        request_id: None,
     };
 }
 
@@ -32,5 +34,50 @@ pub fn handle_404(req: &Request<'_>) -> ErrorResponse {
         description: format!("The requested resource {req_url} was not found"),
         reason: "The requested resource was not found".to_owned(),
         code: 404,
+        request_id: None,
     };
 }
+
+#[catch(401)]
+pub fn handle_401(_req: &Request<'_>) -> ErrorResponse {
+    ErrorResponse {
+        description: "The request is missing a valid API key in the Authorization header"
+            .to_owned(),
+        reason: "You are not authenticated".to_owned(),
+        code: 401,
+        request_id: None,
+    }
+}
+
+#[catch(403)]
+pub fn handle_403(req: &Request<'_>) -> ErrorResponse {
+    let req_url = req.uri();
+    ErrorResponse {
+        description: format!("The API key is not granted the scope required by {req_url}"),
+        reason: "You are not authorized to perform this action".to_owned(),
+        code: 403,
+        request_id: None,
+    }
+}
+
+#[catch(413)]
+pub fn handle_413(req: &Request<'_>) -> ErrorResponse {
+    let req_url = req.uri();
+    ErrorResponse {
+        description: format!("The request body exceeds the size limit of {req_url}"),
+        reason: "The request body is too large".to_owned(),
+        code: 413,
+        request_id: None,
+    }
+}
+
+#[catch(422)]
+pub fn handle_422(req: &Request<'_>) -> ErrorResponse {
+    let req_url = req.uri();
+    ErrorResponse {
+        description: format!("The request body does not match the format expected by {req_url}"),
+        reason: "The request content is not valid".to_owned(),
+        code: 422,
+        request_id: None,
+    }
+}
@@ -1,6 +1,4 @@
-use std::borrow::Borrow;
-
-use rocket::http::{ContentType, Status};
+use rocket::http::{ContentType, Header, Status};
 use rocket::response::Responder;
 use rocket::serde::json::serde_json;
 use rocket::{response, Request, Response};
@@ -13,8 +11,12 @@ use rocket_okapi::{okapi, OpenApiError};
 use serde::Serialize;
 use thiserror::Error;
 
-use schemas::{bad_request_response, unauthorized_response};
+use schemas::{
+    bad_request_response, conflict_response, forbidden_response, payload_too_large_response,
+    too_many_requests_response, unauthorized_response,
+};
 
+use crate::common::RequestId;
 use crate::errors::schemas;
 
 /// Error messages returned to user
@@ -26,10 +28,16 @@ pub struct ErrorResponse {
     pub description: String,
     // HTTP Status Code returned
     pub code: u16,
+    /// The correlation id of the request, as returned in the `X-Request-Id` header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl<'r> Responder<'r, 'static> for ErrorResponse {
-    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    fn respond_to(mut self, req: &'r Request<'_>) -> response::Result<'static> {
+        if self.request_id.is_none() {
+            self.request_id = Some(RequestId::of(req).to_string());
+        }
         // Convert object to json
         let body = serde_json::to_string(&self).unwrap();
         Response::build()
@@ -51,6 +59,27 @@ pub enum AgentError {
         reason
     )]
     BadRequest { reason: String },
+    #[error("{} is not allowed to {} {}", caller, action, object)]
+    Forbidden {
+        caller: String,
+        action: String,
+        object: String,
+    },
+    #[error("Too many requests, retry after {} seconds", retry_after)]
+    TooManyRequests { retry_after: u64 },
+    #[error(
+        "The request body of {} bytes exceeds the limit of {} bytes",
+        size,
+        limit
+    )]
+    PayloadTooLarge { size: u64, limit: u64 },
+    #[error(
+        "This agent follows the leader {}, which must receive the changes",
+        leader
+    )]
+    ReadOnly { leader: String },
+    #[error("{}", reason)]
+    Internal { reason: String },
 }
 
 impl AgentError {
@@ -60,10 +89,18 @@ impl AgentError {
             NotFound { object: _, id: _ } => Status::NotFound,
             Duplicate { object: _, id: _ } => Status::Conflict,
             BadRequest { reason: _ } => Status::BadRequest,
+            Forbidden { .. } => Status::Forbidden,
+            TooManyRequests { .. } => Status::TooManyRequests,
+            PayloadTooLarge { .. } => Status::PayloadTooLarge,
+            ReadOnly { .. } => Status::Conflict,
+            Internal { .. } => Status::InternalServerError,
         }
     }
 
     fn title(&self) -> String {
+        if let AgentError::ReadOnly { .. } = self {
+            return "The agent is a read-only follower".to_owned();
+        }
         let status = self.status();
         // use if else if because
         // the traits must be derived, manual `impl`s are not sufficient
@@ -72,10 +109,16 @@ impl AgentError {
             "You have malformed a bad request".to_owned()
         } else if status == Status::Unauthorized {
             "You are not authorized to perform this action".to_owned()
+        } else if status == Status::Forbidden {
+            "You are not allowed to perform this action".to_owned()
         } else if status == Status::NotFound {
             "The requested resource was not found".to_owned()
         } else if status == Status::Conflict {
             "The requested resource already exists".to_owned()
+        } else if status == Status::TooManyRequests {
+            "You have sent too many requests".to_owned()
+        } else if status == Status::PayloadTooLarge {
+            "The request body is too large".to_owned()
         } else if status.code >= 400 && status.code < 500 {
             "An unexpected client error has occurred".to_owned()
         } else {
@@ -89,19 +132,18 @@ impl AgentError {
 }
 
 impl<'r> Responder<'r, 'static> for AgentError {
-    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
-        let res = ErrorResponse {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = ErrorResponse {
             code: self.status().code,
             reason: self.title(),
             description: self.message(),
-        };
-        // Convert object to json
-        let body = serde_json::to_string(res.borrow()).unwrap();
-        Response::build()
-            .sized_body(body.len(), std::io::Cursor::new(body))
-            .header(ContentType::JSON)
-            .status(Status::new(res.code))
-            .ok()
+            request_id: None,
+        }
+        .respond_to(req)?;
+        if let AgentError::TooManyRequests { retry_after } = self {
+            response.set_header(Header::new("Retry-After", retry_after.to_string()));
+        }
+        Ok(response)
     }
 }
 
@@ -112,6 +154,10 @@ impl OpenApiResponderInner for AgentError {
             responses: okapi::map! {
                 "400".to_owned() => RefOr::Object(bad_request_response(gen)),
                 "401".to_owned() => RefOr::Object(unauthorized_response(gen)),
+                "403".to_owned() => RefOr::Object(forbidden_response(gen)),
+                "409".to_owned() => RefOr::Object(conflict_response(gen)),
+                "413".to_owned() => RefOr::Object(payload_too_large_response(gen)),
+                "429".to_owned() => RefOr::Object(too_many_requests_response(gen)),
             },
             ..Default::default()
         })
@@ -42,3 +42,75 @@ pub fn unauthorized_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Res
         ..Default::default()
     }
 }
+
+pub fn forbidden_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<ErrorResponse>();
+    okapi::openapi3::Response {
+        description: "\
+        # 403 Forbidden\n\
+        The authentication given does not grant the scope required by the route. \
+        "
+        .to_owned(),
+        content: okapi::map! {
+            "application/json".to_owned() => MediaType {
+                schema: Some(schema),
+                ..Default::default()
+            }
+        },
+        ..Default::default()
+    }
+}
+
+pub fn payload_too_large_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<ErrorResponse>();
+    okapi::openapi3::Response {
+        description: "\
+        # 413 Payload Too Large\n\
+        The request body exceeds the size limit of the route. \
+        "
+        .to_owned(),
+        content: okapi::map! {
+            "application/json".to_owned() => MediaType {
+                schema: Some(schema),
+                ..Default::default()
+            }
+        },
+        ..Default::default()
+    }
+}
+
+pub fn conflict_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<ErrorResponse>();
+    okapi::openapi3::Response {
+        description: "\
+        # 409 Conflict\n\
+        The resource already exists, or the agent follows a leader which must receive the changes instead. \
+        "
+        .to_owned(),
+        content: okapi::map! {
+            "application/json".to_owned() => MediaType {
+                schema: Some(schema),
+                ..Default::default()
+            }
+        },
+        ..Default::default()
+    }
+}
+
+pub fn too_many_requests_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<ErrorResponse>();
+    okapi::openapi3::Response {
+        description: "\
+        # 429 Too Many Requests\n\
+        The client exceeded its rate limit, retry after the delay given in the `Retry-After` header. \
+        "
+        .to_owned(),
+        content: okapi::map! {
+            "application/json".to_owned() => MediaType {
+                schema: Some(schema),
+                ..Default::default()
+            }
+        },
+        ..Default::default()
+    }
+}
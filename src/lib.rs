@@ -1,9 +1,11 @@
 #![allow(dead_code)]
 
 mod authn;
-mod common;
-mod config;
-mod errors;
+pub mod commands;
+pub mod common;
+pub mod config;
+pub mod errors;
+pub mod logger;
 mod routes;
 pub mod schemas;
 mod services;
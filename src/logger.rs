@@ -1,17 +1,133 @@
+use crate::common::{
+    rolling_file_appender, RequestId, DEFAULT_ROLLING_MAX_FILES, DEFAULT_ROLLING_MAX_SIZE,
+};
 use crate::config;
-use log::LevelFilter;
+use crate::config::{ConfigError, LogFormat};
+use log::kv::{Error, Key, Source, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
 use log4rs::append::console::{ConsoleAppender, Target};
+use log4rs::append::Append;
 use log4rs::config::{Appender, Root};
+use log4rs::encode::json::JsonEncoder;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::{Encode, Write};
 use log4rs::Config;
 
-pub(crate) fn init(conf: &config::Config) {
+/// Text encoder appending the structured fields of the record as `key=value` pairs
+#[derive(Debug)]
+struct KeyValueEncoder(PatternEncoder);
+
+impl KeyValueEncoder {
+    fn new() -> Self {
+        Self(PatternEncoder::new("{d} {l} {t} - {m}"))
+    }
+}
+
+struct KeyValueWriter<'a>(&'a mut dyn Write);
+
+impl<'kvs> VisitSource<'kvs> for KeyValueWriter<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        write!(self.0, " {}={}", key, value).map_err(Error::boxed)
+    }
+}
+
+impl Encode for KeyValueEncoder {
+    fn encode(&self, w: &mut dyn Write, record: &Record) -> anyhow::Result<()> {
+        self.0.encode(w, record)?;
+        record.key_values().visit(&mut KeyValueWriter(w))?;
+        writeln!(w)?;
+        Ok(())
+    }
+}
+
+/// Structured fields of a record extended with the request id
+struct WithRequestId<'a> {
+    request_id: &'a RequestId,
+    source: &'a dyn Source,
+}
+
+impl Source for WithRequestId<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        self.source.visit(visitor)?;
+        visitor.visit_pair(
+            Key::from("request_id"),
+            Value::from(self.request_id.as_str()),
+        )
+    }
+}
+
+/// Logger adding the id of the handled request to every record written while handling it
+struct RequestIdLogger(log4rs::Logger);
+
+impl Log for RequestIdLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        match RequestId::current() {
+            Some(request_id) => self.0.log(
+                &record
+                    .to_builder()
+                    .key_values(&WithRequestId {
+                        request_id: &request_id,
+                        source: record.key_values(),
+                    })
+                    .build(),
+            ),
+            None => self.0.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        Log::flush(&self.0)
+    }
+}
+
+fn encoder(format: LogFormat) -> Box<dyn Encode> {
+    match format {
+        LogFormat::Text => Box::new(KeyValueEncoder::new()),
+        LogFormat::Json => Box::new(JsonEncoder::new()),
+    }
+}
+
+/// Build the logger writing in the configured format to the console or the log file,
+/// failing when the log file cannot be opened
+pub fn logger(conf: &config::Config) -> Result<Box<dyn Log>, ConfigError> {
     let log_level = conf.log_level.unwrap_or(LevelFilter::Info);
-    let stderr = ConsoleAppender::builder().target(Target::Stderr).build();
+    let log_format = conf.log_format.unwrap_or(LogFormat::Text);
+    let appender: Box<dyn Append> = match conf.log_file.as_ref() {
+        Some(path) => Box::new(
+            rolling_file_appender(
+                path,
+                conf.log_file_max_size.unwrap_or(DEFAULT_ROLLING_MAX_SIZE),
+                conf.log_file_max_files.unwrap_or(DEFAULT_ROLLING_MAX_FILES),
+                encoder(log_format),
+            )
+            .map_err(|err| {
+                ConfigError::Invalid(vec![format!("log_file {}: {}", path.display(), err)])
+            })?,
+        ),
+        None => Box::new(
+            ConsoleAppender::builder()
+                .target(Target::Stderr)
+                .encoder(encoder(log_format))
+                .build(),
+        ),
+    };
 
     let config = Config::builder()
-        .appender(Appender::builder().build("stderr", Box::new(stderr)))
-        .build(Root::builder().appender("stderr").build(log_level))
+        .appender(Appender::builder().build("main", appender))
+        .build(Root::builder().appender("main").build(log_level))
         .unwrap();
 
-    let _handle = log4rs::init_config(config).unwrap();
+    Ok(Box::new(RequestIdLogger(log4rs::Logger::new(config))))
+}
+
+/// Install the logger, failing when the log file cannot be opened
+pub(crate) fn init(conf: &config::Config) -> Result<(), ConfigError> {
+    let logger = logger(conf)?;
+    log::set_max_level(conf.log_level.unwrap_or(LevelFilter::Info));
+    log::set_boxed_logger(logger).unwrap();
+    Ok(())
 }
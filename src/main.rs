@@ -2,18 +2,16 @@ extern crate core;
 extern crate rocket;
 
 use std::borrow::Borrow;
+use std::sync::Arc;
 
+use clap::Parser;
 use rocket::catchers;
 use rocket::http::ContentType;
 use rocket_okapi::settings::UrlObject;
 use rocket_okapi::{openapi_get_routes, rapidoc::*, swagger_ui::*};
 
-use crate::services::data::memory::MemoryDataStore;
-use crate::services::data::DataStore;
-use crate::services::policies::memory::MemoryPolicyStore;
-use crate::services::policies::PolicyStore;
-
 mod authn;
+mod commands;
 mod common;
 mod config;
 mod errors;
@@ -24,16 +22,167 @@ mod services;
 
 #[rocket::main]
 async fn main() {
-    let config = config::init();
-    logger::init(&config);
+    let args = match config::Cli::parse().command() {
+        config::Command::Serve(args) => *args,
+        config::Command::Validate(args) => {
+            let valid = commands::validate::run(&args).await;
+            std::process::exit(if valid { 0 } else { 1 });
+        }
+        config::Command::Authorize(args) => {
+            let answered = commands::authorize::run(args).await;
+            std::process::exit(if answered { 0 } else { 1 });
+        }
+    };
+    serve(args).await;
+}
+
+async fn serve(args: config::Config) {
+    let config = match config::init(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    if let Err(err) = logger::init(&config) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    for name in config::legacy_env_vars(std::env::vars()) {
+        let prefixed = format!("CEDAR_AGENT_{}", name);
+        if std::env::var_os(&prefixed).is_some() {
+            log::warn!(variable = name.as_str(); "The {} environment variable is ignored, {} is set", name, prefixed);
+        } else {
+            log::warn!(variable = name.as_str(); "The {} environment variable is deprecated, use {} instead", name, prefixed);
+        }
+    }
+    let tracer_provider = services::telemetry::init(&config);
+    let key_ring = match authn::KeyRing::new(&config) {
+        Ok(key_ring) => key_ring,
+        Err(err) => {
+            eprintln!("Failed to load the authentication keys: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let admin_authorizer = match services::admin::init(&config).await {
+        Ok(admin_authorizer) => admin_authorizer,
+        Err(err) => {
+            eprintln!("Failed to load the admin policies: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let audit_log = match services::audit::init(&config) {
+        Ok(audit_log) => audit_log,
+        Err(err) => {
+            eprintln!("Failed to open the audit log: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let decision_logger = match services::decision_log::init(&config) {
+        Ok(decision_logger) => decision_logger,
+        Err(err) => {
+            eprintln!("Failed to open the decision log: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let registry = services::registry::Registry::new();
+    let policy_store = match registry.open_policy_store(&config.policy_store_uri()).await {
+        Ok(policy_store) => policy_store,
+        Err(err) => {
+            eprintln!("Failed to open the policy store: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let data_store = match registry.open_data_store(&config.data_store_uri()).await {
+        Ok(data_store) => data_store,
+        Err(err) => {
+            eprintln!("Failed to open the data store: {}", err);
+            std::process::exit(1);
+        }
+    };
+    // Every change of the stores is recorded, whether made through the API or by the agent itself
+    let audit_log = Arc::new(audit_log);
+    let (policy_store, data_store) = match audit_log.is_enabled() {
+        true => (
+            Box::new(services::audit::stores::AuditedPolicyStore::new(
+                policy_store,
+                audit_log.clone(),
+            )) as Box<dyn services::PolicyStore>,
+            Box::new(services::audit::stores::AuditedDataStore::new(
+                data_store,
+                audit_log.clone(),
+            )) as Box<dyn services::DataStore>,
+        ),
+        false => (policy_store, data_store),
+    };
+    let change_feed = services::changes::ChangeFeed::new();
+    let policy_store: Arc<dyn services::PolicyStore> = Arc::new(
+        services::changes::stores::ObservedPolicyStore::new(policy_store, change_feed.clone()),
+    );
+    let data_store: Arc<dyn services::DataStore> = Arc::new(
+        services::changes::stores::ObservedDataStore::new(data_store, change_feed.clone()),
+    );
+    if let Err(err) = services::webhooks::init(&config, &change_feed) {
+        eprintln!("Failed to start the webhooks: {}", err);
+        std::process::exit(1);
+    }
+    let bundles = match services::bundles::init(
+        &config,
+        policy_store.clone(),
+        data_store.clone(),
+        change_feed.clone(),
+    )
+    .await
+    {
+        Ok(bundles) => bundles,
+        Err(err) => {
+            eprintln!("Failed to load the bundle: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let snapshot_on_shutdown = match services::snapshots::init(&config, &bundles).await {
+        Ok(snapshot_on_shutdown) => snapshot_on_shutdown,
+        Err(err) => {
+            eprintln!("Failed to restore the snapshot: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let git_source = match services::git::init(&config, bundles.clone()) {
+        Ok(git_source) => git_source,
+        Err(err) => {
+            eprintln!("Failed to track the Git repository: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let replication =
+        match services::replication::init(&config, policy_store.clone(), data_store.clone()) {
+            Ok(replication) => replication,
+            Err(err) => {
+                eprintln!("Failed to follow the leader: {}", err);
+                std::process::exit(1);
+            }
+        };
     let server_config: rocket::figment::Figment = config.borrow().into();
     let launch_result = rocket::custom(server_config)
         .attach(common::DefaultContentType::new(ContentType::JSON))
+        .attach(common::RequestIdFairing)
+        .attach(services::telemetry::TracingFairing)
+        .attach(services::limits::init(&config))
         .attach(services::data::load_from_file::InitDataFairing)
         .attach(services::policies::load_from_file::InitPoliciesFairing)
+        .attach(snapshot_on_shutdown)
+        .attach(git_source.clone())
+        .manage(decision_logger)
+        .manage(key_ring)
+        .manage(admin_authorizer)
+        .manage(audit_log)
+        .manage(change_feed)
+        .manage(replication)
+        .manage(bundles)
+        .manage(git_source)
         .manage(config)
-        .manage(Box::new(MemoryPolicyStore::new()) as Box<dyn PolicyStore>)
-        .manage(Box::new(MemoryDataStore::new()) as Box<dyn DataStore>)
+        .manage(policy_store)
+        .manage(data_store)
         .manage(cedar_policy::Authorizer::new())
         .register(
             "/",
@@ -41,23 +190,35 @@ async fn main() {
                 errors::catchers::handle_500,
                 errors::catchers::handle_404,
                 errors::catchers::handle_400,
+                errors::catchers::handle_401,
+                errors::catchers::handle_403,
+                errors::catchers::handle_413,
+                errors::catchers::handle_422,
             ],
         )
         .mount(
             "/v1",
-            openapi_get_routes![
-                routes::healthy,
-                routes::policies::get_policies,
-                routes::policies::get_policy,
-                routes::policies::create_policy,
-                routes::policies::update_policies,
-                routes::policies::update_policy,
-                routes::policies::delete_policy,
-                routes::data::get_entities,
-                routes::data::update_entities,
-                routes::data::delete_entities,
-                routes::authorization::is_authorized,
-            ],
+            common::with_request_id(services::audit::with_caller(services::limits::with_limits(
+                services::replication::with_replication(openapi_get_routes![
+                    routes::healthy,
+                    routes::policies::get_policies,
+                    routes::policies::get_policy,
+                    routes::policies::create_policy,
+                    routes::policies::update_policies,
+                    routes::policies::update_policy,
+                    routes::policies::delete_policy,
+                    routes::data::get_entities,
+                    routes::data::update_entities,
+                    routes::data::delete_entities,
+                    routes::authorization::is_authorized,
+                    routes::audit::get_audit,
+                    routes::changes::get_changes,
+                    routes::bundles::get_bundle,
+                    routes::bundles::update_bundle,
+                    routes::snapshots::get_snapshot,
+                    routes::snapshots::update_snapshot,
+                ]),
+            ))),
         )
         .mount(
             "/swagger-ui/",
@@ -87,4 +248,9 @@ async fn main() {
         Ok(_) => println!("Rocket shut down gracefully."),
         Err(err) => println!("Rocket had an error: {}", err),
     };
+    if let Some(tracer_provider) = tracer_provider {
+        if let Err(err) = tracer_provider.shutdown() {
+            println!("Failed to flush the traces: {}", err);
+        }
+    }
 }
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use rocket::serde::json::Json;
+use rocket::{get, State};
+use rocket_okapi::openapi;
+
+use crate::authn::{scopes, ApiKey};
+use crate::errors::response::AgentError;
+use crate::routes::authorize_admin;
+use crate::services::admin::{AdminAction, AdminAuthorizer, AdminResource};
+use crate::services::audit::{AuditLog, AuditQuery, AuditRecord};
+
+#[openapi]
+#[get("/audit?<since>&<limit>&<caller>&<object>")]
+pub async fn get_audit(
+    auth: ApiKey<scopes::AuditRead>,
+    since: Option<u64>,
+    limit: Option<usize>,
+    caller: Option<String>,
+    object: Option<String>,
+    audit_log: &State<Arc<AuditLog>>,
+    admin_authorizer: &State<AdminAuthorizer>,
+) -> Result<Json<Vec<AuditRecord>>, AgentError> {
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::ReadAudit,
+        &AdminResource::Store("audit"),
+    )?;
+    let query = AuditQuery {
+        since,
+        limit,
+        caller,
+        object,
+    };
+    match audit_log.query(&query).await {
+        Ok(records) => Ok(Json::from(records)),
+        Err(err) => Err(AgentError::Internal {
+            reason: format!("Unable to read the audit log: {}", err),
+        }),
+    }
+}
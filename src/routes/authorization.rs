@@ -1,36 +1,96 @@
+use std::sync::Arc;
+use std::time::Instant;
+
 use cedar_policy::Authorizer;
 
 use log::info;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::KeyValue;
 
 use rocket::serde::json::Json;
 use rocket::{post, State};
 use rocket_okapi::openapi;
 
-use crate::authn::ApiKey;
+use crate::authn::{scopes, ApiKey};
+use crate::common::RequestId;
 use crate::errors::response::AgentError;
 use crate::schemas::authorization::{AuthorizationAnswer, AuthorizationCall};
-use crate::{DataStore, PolicyStore};
+use crate::services::bundles::Bundles;
+use crate::services::changes::ChangeFeed;
+use crate::services::decision_log::{DecisionLogger, DecisionRecord};
+use crate::services::limits::LimitedJson;
+use crate::services::telemetry::TraceContext;
+use crate::services::{DataStore, PolicyStore};
 
+#[allow(clippy::too_many_arguments)]
 #[openapi]
 #[post("/is_authorized", format = "json", data = "<authorization_call>")]
 pub async fn is_authorized(
-    _auth: ApiKey,
-    policy_store: &State<Box<dyn PolicyStore>>,
-    data_store: &State<Box<dyn DataStore>>,
+    _auth: ApiKey<scopes::Authorize>,
+    trace_context: TraceContext,
+    request_id: RequestId,
+    policy_store: &State<Arc<dyn PolicyStore>>,
+    data_store: &State<Arc<dyn DataStore>>,
+    bundles: &State<Arc<Bundles>>,
     authorizer: &State<Authorizer>,
-    authorization_call: Json<AuthorizationCall>,
+    decision_logger: &State<DecisionLogger>,
+    change_feed: &State<ChangeFeed>,
+    authorization_call: LimitedJson<AuthorizationCall>,
 ) -> Result<Json<AuthorizationAnswer>, AgentError> {
-    let entities: cedar_policy::Entities = data_store.entities().await;
-    let policies = policy_store.policy_set().await;
-    let query: cedar_policy::Request = match authorization_call.into_inner().try_into() {
+    let start = Instant::now();
+    let authorization_call = authorization_call.into_inner();
+    let record = decision_logger.sample().then(|| {
+        DecisionRecord::new(
+            request_id.to_string(),
+            change_feed.epoch(),
+            &authorization_call,
+        )
+    });
+    // The policies and entities are read from the same bundle
+    let active_bundle = bundles.active().await;
+    let entities: cedar_policy::Entities = trace_context
+        .in_span("DataStore::entities", data_store.entities())
+        .await;
+    let (policies, revision) = trace_context
+        .in_span(
+            "PolicyStore::revised_policy_set",
+            policy_store.revised_policy_set(),
+        )
+        .await;
+    let bundle_revision = active_bundle
+        .as_ref()
+        .map(|active| active.manifest.revision.clone());
+    drop(active_bundle);
+    let query: cedar_policy::Request = match authorization_call.try_into() {
         Ok(query) => query,
         Err(err) => {
-            return Err(AgentError::BadRequest {
-                reason: err.to_string(),
-            })
+            let reason = err.to_string();
+            if let Some(record) = record {
+                decision_logger.log(
+                    record
+                        .with_rejection(&reason, revision, start.elapsed())
+                        .with_bundle_revision(bundle_revision),
+                );
+            }
+            return Err(AgentError::BadRequest { reason });
         }
     };
-    info!("Querying cedar using {}", query);
-    let answer = authorizer.is_authorized(&query, &policies, &entities);
-    Ok(Json::from(AuthorizationAnswer::from(answer)))
+    info!(route = "is_authorized"; "Querying cedar using {}", query);
+    let answer = trace_context.in_span_sync("Authorizer::is_authorized", |cx| {
+        let answer =
+            AuthorizationAnswer::from(authorizer.is_authorized(&query, &policies, &entities));
+        cx.span().set_attribute(KeyValue::new(
+            "cedar.decision",
+            format!("{:?}", answer.decision()),
+        ));
+        answer
+    });
+    if let Some(record) = record {
+        decision_logger.log(
+            record
+                .with_answer(&answer, revision, start.elapsed())
+                .with_bundle_revision(bundle_revision),
+        );
+    }
+    Ok(Json::from(answer))
 }
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use rocket::http::ContentType;
+use rocket::serde::json::{serde_json, Json};
+use rocket::{get, put, State};
+use rocket_okapi::openapi;
+
+use crate::authn::{scopes, ApiKey};
+use crate::errors::response::AgentError;
+use crate::routes::{authorize_admin, store_error};
+use crate::services::admin::{AdminAction, AdminAuthorizer, AdminResource};
+use crate::services::audit::AuditLog;
+use crate::services::bundles::{Bundles, Manifest};
+
+/// Export the policies, data and schema as an unsigned tar.gz bundle
+#[openapi]
+#[get("/bundle")]
+pub async fn get_bundle(
+    auth: ApiKey<scopes::BundleRead>,
+    bundles: &State<Arc<Bundles>>,
+    admin_authorizer: &State<AdminAuthorizer>,
+) -> Result<(ContentType, Vec<u8>), AgentError> {
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::ExportBundle,
+        &AdminResource::Store("bundle"),
+    )?;
+    match bundles.export().await.pack() {
+        Ok(archive) => Ok((ContentType::GZIP, archive)),
+        Err(err) => Err(AgentError::Internal {
+            reason: err.to_string(),
+        }),
+    }
+}
+
+/// Verify a tar.gz bundle and swap its policies and data into the stores at once
+#[openapi]
+#[put("/bundle", data = "<archive>")]
+pub async fn update_bundle(
+    auth: ApiKey<scopes::BundleWrite>,
+    archive: Vec<u8>,
+    bundles: &State<Arc<Bundles>>,
+    admin_authorizer: &State<AdminAuthorizer>,
+    audit_log: &State<Arc<AuditLog>>,
+) -> Result<Json<Manifest>, AgentError> {
+    let resource = AdminResource::Store("bundle");
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::ActivateBundle,
+        &resource,
+    )?;
+    let bundle = match bundles.unpack(archive).await {
+        Ok(bundle) => bundle,
+        Err(err) => {
+            return Err(AgentError::BadRequest {
+                reason: err.to_string(),
+            })
+        }
+    };
+    // The manifest of the bundle could not be recorded otherwise
+    if let Err(err) = audit_log.ready().await {
+        return Err(AgentError::Internal {
+            reason: err.to_string(),
+        });
+    }
+    let active_manifest = bundles
+        .active()
+        .await
+        .as_ref()
+        .map(|active| active.manifest.clone());
+    let manifest = match bundles.activate(bundle).await {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            return Err(store_error(err, |err| AgentError::BadRequest {
+                reason: err.to_string(),
+            }))
+        }
+    };
+    audit_log
+        .record(
+            auth.name(),
+            AdminAction::ActivateBundle,
+            &resource,
+            active_manifest.and_then(|before| serde_json::to_value(before).ok()),
+            serde_json::to_value(&manifest).ok(),
+        )
+        .await;
+    Ok(Json::from(manifest))
+}
@@ -0,0 +1,79 @@
+use rocket::futures::stream::BoxStream;
+use rocket::response::stream::{stream, Event, EventStream};
+use rocket::serde::json::serde_json::json;
+use rocket::tokio::select;
+use rocket::{get, Shutdown, State};
+use rocket_okapi::openapi;
+
+use crate::authn::{scopes, ApiKey};
+use crate::errors::response::AgentError;
+use crate::routes::authorize_admin;
+use crate::services::admin::{AdminAction, AdminAuthorizer, AdminResource};
+use crate::services::changes::{event_id, Change, ChangeFeed, LastEventId};
+
+fn change_event(change: &Change) -> Event {
+    Event::json(change).event("change").id(change.event_id())
+}
+
+/// Stream the changes of the policies and data as Server-Sent Events,
+/// starting after the revision `since` of the `epoch` when given,
+/// or after the event of the `Last-Event-ID` header sent by a reconnecting client.
+/// A `subscribed` event holding the current revision and epoch is sent first, or a `reset`
+/// event when the changes after `since` are no longer known, in which case the whole state
+/// must be reloaded. A `since` without its `epoch` is answered with a `reset`,
+/// as the revisions start over when the agent restarts.
+#[openapi]
+#[get("/changes?<since>&<epoch>")]
+pub async fn get_changes(
+    auth: ApiKey<scopes::ChangesRead>,
+    since: Option<u64>,
+    epoch: Option<String>,
+    last_event_id: LastEventId,
+    change_feed: &State<ChangeFeed>,
+    admin_authorizer: &State<AdminAuthorizer>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream<BoxStream<'static, Event>>, AgentError> {
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::ReadChanges,
+        &AdminResource::Store("changes"),
+    )?;
+    let mut subscription = match (since, epoch, last_event_id.0) {
+        (Some(since), Some(epoch), _) => change_feed.resume(since, &epoch),
+        (Some(_), None, _) => change_feed.reset(),
+        (None, _, Some(last_event_id)) => change_feed.resume_after(&last_event_id),
+        (None, _, None) => change_feed.subscribe(None),
+    };
+    let epoch = change_feed.epoch().to_owned();
+    let position = json!({ "revision": subscription.revision, "epoch": epoch });
+    let events: BoxStream<'static, Event> = Box::pin(stream! {
+        match subscription.reset {
+            Some(revision) => {
+                yield Event::json(&position)
+                    .event("reset")
+                    .id(event_id(&epoch, revision))
+            }
+            None => {
+                yield Event::json(&position)
+                    .event("subscribed")
+                    .id(event_id(&epoch, subscription.revision))
+            }
+        }
+        for change in subscription.missed.iter() {
+            yield change_event(change);
+        }
+        loop {
+            // A subscriber lagging behind is disconnected, to resume from its last revision
+            let change = select! {
+                change = subscription.receiver.recv() => match change {
+                    Ok(change) => change,
+                    Err(_) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            yield change_event(&change);
+        }
+    });
+    Ok(EventStream::from(events))
+}
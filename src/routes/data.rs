@@ -1,44 +1,86 @@
+use std::sync::Arc;
+
 use rocket::response::status;
 
 use rocket::serde::json::Json;
 use rocket::{delete, get, put, State};
 use rocket_okapi::openapi;
 
-use crate::authn::ApiKey;
+use crate::authn::{scopes, ApiKey};
 use crate::errors::response::AgentError;
+use crate::routes::{authorize_admin, store_error};
 use crate::schemas::data as schemas;
-use crate::DataStore;
+use crate::services::admin::{AdminAction, AdminAuthorizer, AdminResource};
+use crate::services::limits::LimitedJson;
+use crate::services::DataStore;
 
 #[openapi]
 #[get("/data")]
 pub async fn get_entities(
-    _auth: ApiKey,
-    data_store: &State<Box<dyn DataStore>>,
+    auth: ApiKey<scopes::DataRead>,
+    data_store: &State<Arc<dyn DataStore>>,
+    admin_authorizer: &State<AdminAuthorizer>,
 ) -> Result<Json<schemas::Entities>, AgentError> {
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::GetEntities,
+        &AdminResource::Store("data"),
+    )?;
     Ok(Json::from(data_store.get_entities().await))
 }
 
 #[openapi]
 #[put("/data", format = "json", data = "<entities>")]
 pub async fn update_entities(
-    _auth: ApiKey,
-    data_store: &State<Box<dyn DataStore>>,
-    entities: Json<schemas::Entities>,
+    auth: ApiKey<scopes::DataWrite>,
+    data_store: &State<Arc<dyn DataStore>>,
+    admin_authorizer: &State<AdminAuthorizer>,
+    entities: LimitedJson<schemas::Entities>,
 ) -> Result<Json<schemas::Entities>, AgentError> {
-    match data_store.update_entities(entities.into_inner()).await {
+    let entities = entities.into_inner();
+    if admin_authorizer.is_enabled() {
+        let stored_entities = data_store.get_entities().await;
+        // The stored entities are replaced, so the caller must be allowed
+        // to update both the stored and the new entity types
+        for resource in AdminResource::entity_types(&stored_entities)
+            .iter()
+            .chain(AdminResource::entity_types(&entities).iter())
+        {
+            authorize_admin(
+                admin_authorizer,
+                auth.name(),
+                AdminAction::UpdateEntities,
+                resource,
+            )?;
+        }
+    }
+    match data_store.update_entities(entities).await {
         Ok(entities) => Ok(Json::from(entities)),
-        Err(err) => Err(AgentError::BadRequest {
+        Err(err) => Err(store_error(err, |err| AgentError::BadRequest {
             reason: err.to_string(),
-        }),
+        })),
     }
 }
 
 #[openapi]
 #[delete("/data")]
 pub async fn delete_entities(
-    _auth: ApiKey,
-    data_store: &State<Box<dyn DataStore>>,
+    auth: ApiKey<scopes::DataWrite>,
+    data_store: &State<Arc<dyn DataStore>>,
+    admin_authorizer: &State<AdminAuthorizer>,
 ) -> Result<status::NoContent, AgentError> {
-    data_store.delete_entities().await;
-    Ok(status::NoContent)
+    let resource = AdminResource::Store("data");
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::DeleteEntities,
+        &resource,
+    )?;
+    match data_store.delete_entities().await {
+        Ok(_) => Ok(status::NoContent),
+        Err(err) => Err(store_error(err, |err| AgentError::BadRequest {
+            reason: err.to_string(),
+        })),
+    }
 }
@@ -1,13 +1,78 @@
-use rocket::get;
+use std::error::Error;
+
 use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{get, Either, State};
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
 use rocket_okapi::openapi;
+use serde::Serialize;
+
+use crate::errors::response::AgentError;
+use crate::services::admin::{AdminAction, AdminAuthorizer, AdminResource, ANONYMOUS_CALLER};
+use crate::services::git::{GitSource, GitStatus};
+use crate::services::replication::{Replication, ReplicationStatus};
+use crate::services::storage::StorageError;
 
+pub mod audit;
 pub mod authorization;
+pub mod bundles;
+pub mod changes;
 pub mod data;
 pub mod policies;
+pub mod snapshots;
+
+#[derive(Serialize, JsonSchema)]
+pub struct Health {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replication: Option<ReplicationStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitStatus>,
+}
 
+/// Report that the agent is up, with the state of its replication when it follows a leader
+/// and of its synchronization when it tracks a Git repository
 #[openapi]
 #[get("/")]
-pub async fn healthy() -> status::NoContent {
-    status::NoContent
+pub async fn healthy(
+    replication: &State<Replication>,
+    git_source: &State<GitSource>,
+) -> Either<status::NoContent, Json<Health>> {
+    match (replication.status(), git_source.status()) {
+        (None, None) => Either::Left(status::NoContent),
+        (replication, git) => Either::Right(Json(Health { replication, git })),
+    }
+}
+
+/// Reject the call unless the admin policies allow the caller to perform the action
+pub(crate) fn authorize_admin(
+    admin_authorizer: &AdminAuthorizer,
+    caller: Option<&str>,
+    action: AdminAction,
+    resource: &AdminResource,
+) -> Result<(), AgentError> {
+    if admin_authorizer.is_allowed(caller, action, resource) {
+        Ok(())
+    } else {
+        Err(AgentError::Forbidden {
+            caller: caller.unwrap_or(ANONYMOUS_CALLER).to_owned(),
+            action: action.to_string(),
+            object: resource.to_string(),
+        })
+    }
+}
+
+/// Report a change the store failed to persist as an internal error,
+/// and the other store errors as the given client error
+pub(crate) fn store_error(
+    err: Box<dyn Error>,
+    client_error: impl FnOnce(Box<dyn Error>) -> AgentError,
+) -> AgentError {
+    if err.is::<StorageError>() {
+        AgentError::Internal {
+            reason: err.to_string(),
+        }
+    } else {
+        client_error(err)
+    }
 }
@@ -1,34 +1,59 @@
 use std::borrow::Borrow;
+use std::error::Error;
+use std::sync::Arc;
 
 use rocket::response::status;
 use rocket::serde::json::Json;
 use rocket::{delete, get, post, put, State};
 use rocket_okapi::openapi;
 
-use crate::authn::ApiKey;
+use crate::authn::{scopes, ApiKey};
 use crate::errors::response::AgentError;
+use crate::routes::{authorize_admin, store_error};
 use crate::schemas::policies as schemas;
+use crate::services::admin::{AdminAction, AdminAuthorizer, AdminResource};
+use crate::services::limits::LimitedJson;
 use crate::services::policies::PolicyStore;
 
 #[openapi]
 #[get("/policies")]
 pub async fn get_policies(
-    _auth: ApiKey,
-    policy_store: &State<Box<dyn PolicyStore>>,
+    auth: ApiKey<scopes::PoliciesRead>,
+    policy_store: &State<Arc<dyn PolicyStore>>,
+    admin_authorizer: &State<AdminAuthorizer>,
 ) -> Result<Json<Vec<schemas::Policy>>, AgentError> {
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::ListPolicies,
+        &AdminResource::Store("policies"),
+    )?;
     Ok(Json::from(policy_store.get_policies().await))
 }
 
 #[openapi]
 #[get("/policies/<id>")]
 pub async fn get_policy(
-    _auth: ApiKey,
+    auth: ApiKey<scopes::PoliciesRead>,
     id: String,
-    policy_store: &State<Box<dyn PolicyStore>>,
+    policy_store: &State<Arc<dyn PolicyStore>>,
+    admin_authorizer: &State<AdminAuthorizer>,
 ) -> Result<Json<schemas::Policy>, AgentError> {
-    match policy_store.get_policy(id.borrow()).await {
-        Ok(policy) => Ok(Json::from(policy)),
-        Err(_) => Err(AgentError::NotFound {
+    let policy = policy_store.get_policy(id.borrow()).await.ok();
+    // A missing policy is checked as well, so that the callers denied access do not learn which ids exist
+    let resource = match policy.as_ref() {
+        Some(policy) => AdminResource::policy(policy),
+        None => AdminResource::missing_policy(&id),
+    };
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::GetPolicy,
+        &resource,
+    )?;
+    match policy {
+        Some(policy) => Ok(Json::from(policy)),
+        None => Err(AgentError::NotFound {
             id,
             object: "policy",
         }),
@@ -38,66 +63,134 @@ pub async fn get_policy(
 #[openapi]
 #[post("/policies", format = "json", data = "<policy>")]
 pub async fn create_policy(
-    _auth: ApiKey,
-    policy: Json<schemas::Policy>,
-    policy_store: &State<Box<dyn PolicyStore>>,
+    auth: ApiKey<scopes::PoliciesWrite>,
+    policy: LimitedJson<schemas::Policy>,
+    policy_store: &State<Arc<dyn PolicyStore>>,
+    admin_authorizer: &State<AdminAuthorizer>,
 ) -> Result<Json<schemas::Policy>, AgentError> {
     let policy = policy.into_inner();
+    let resource = AdminResource::policy(&policy);
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::CreatePolicy,
+        &resource,
+    )?;
     let added_policy = policy_store.create_policy(policy.borrow()).await;
     match added_policy {
         Ok(p) => Ok(Json::from(p)),
-        Err(_) => Err(AgentError::Duplicate {
+        Err(err) => Err(store_error(err, |_| AgentError::Duplicate {
             id: policy.id,
             object: "policy",
-        }),
+        })),
     }
 }
 
 #[openapi]
 #[put("/policies", format = "json", data = "<policy>")]
 pub async fn update_policies(
-    _auth: ApiKey,
-    policy: Json<Vec<schemas::Policy>>,
-    policy_store: &State<Box<dyn PolicyStore>>,
+    auth: ApiKey<scopes::PoliciesWrite>,
+    policy: LimitedJson<Vec<schemas::Policy>>,
+    policy_store: &State<Arc<dyn PolicyStore>>,
+    admin_authorizer: &State<AdminAuthorizer>,
 ) -> Result<Json<Vec<schemas::Policy>>, AgentError> {
+    let resource = AdminResource::Store("policies");
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::ReplacePolicies,
+        &resource,
+    )?;
     let updated_policy = policy_store.update_policies(policy.into_inner()).await;
     match updated_policy {
         Ok(p) => Ok(Json::from(p)),
-        Err(e) => Err(AgentError::BadRequest {
+        Err(e) => Err(store_error(e, |e| AgentError::BadRequest {
             reason: e.to_string(),
-        }),
+        })),
     }
 }
 
 #[openapi]
 #[put("/policies/<id>", format = "json", data = "<policy>")]
 pub async fn update_policy(
-    _auth: ApiKey,
+    auth: ApiKey<scopes::PoliciesWrite>,
     id: String,
-    policy: Json<schemas::PolicyUpdate>,
-    policy_store: &State<Box<dyn PolicyStore>>,
+    policy: LimitedJson<schemas::PolicyUpdate>,
+    policy_store: &State<Arc<dyn PolicyStore>>,
+    admin_authorizer: &State<AdminAuthorizer>,
 ) -> Result<Json<schemas::Policy>, AgentError> {
-    let updated_policy = policy_store.update_policy(id, policy.into_inner()).await;
+    let policy = policy.into_inner();
+    let updated = AdminResource::policy(&schemas::Policy {
+        id: id.clone(),
+        content: policy.content.clone(),
+    });
+    // The caller must be allowed to edit both the stored and the updated policy,
+    // checked against the stored one as it is written
+    let check = |stored: Option<&schemas::Policy>| -> Result<(), Box<dyn Error>> {
+        if let Some(stored) = stored {
+            authorize_admin(
+                admin_authorizer,
+                auth.name(),
+                AdminAction::UpdatePolicy,
+                &AdminResource::policy(stored),
+            )?;
+        }
+        authorize_admin(
+            admin_authorizer,
+            auth.name(),
+            AdminAction::UpdatePolicy,
+            &updated,
+        )?;
+        Ok(())
+    };
+    let updated_policy = policy_store.update_policy_checked(id, policy, &check).await;
     match updated_policy {
         Ok(p) => Ok(Json::from(p)),
-        Err(err) => Err(AgentError::BadRequest {
+        Err(err) => Err(admin_error(err, |err| AgentError::BadRequest {
             reason: err.to_string(),
-        }),
+        })),
     }
 }
 
 #[openapi]
 #[delete("/policies/<id>")]
 pub async fn delete_policy(
-    _auth: ApiKey,
+    auth: ApiKey<scopes::PoliciesWrite>,
     id: String,
-    policy_store: &State<Box<dyn PolicyStore>>,
+    policy_store: &State<Arc<dyn PolicyStore>>,
+    admin_authorizer: &State<AdminAuthorizer>,
 ) -> Result<status::NoContent, AgentError> {
-    match policy_store.delete_policy(id.borrow()).await {
-        Ok(_p) => Ok(status::NoContent),
-        Err(_err) => Err(AgentError::NotFound {
-            id,
+    // Checked against the stored policy as it is deleted, or against its id when missing
+    let check = |stored: Option<&schemas::Policy>| -> Result<(), Box<dyn Error>> {
+        let resource = match stored {
+            Some(stored) => AdminResource::policy(stored),
+            None => AdminResource::missing_policy(&id),
+        };
+        authorize_admin(
+            admin_authorizer,
+            auth.name(),
+            AdminAction::DeletePolicy,
+            &resource,
+        )?;
+        Ok(())
+    };
+    let deleted_policy = policy_store.delete_policy_checked(&id, &check).await;
+    match deleted_policy {
+        Ok(_) => Ok(status::NoContent),
+        Err(err) => Err(admin_error(err, |_| AgentError::NotFound {
+            id: id.clone(),
             object: "Policy",
-        }),
+        })),
+    }
+}
+
+/// Report the refusal of a check as is, and the other errors as the store errors
+fn admin_error(
+    err: Box<dyn Error>,
+    client_error: impl FnOnce(Box<dyn Error>) -> AgentError,
+) -> AgentError {
+    match err.downcast::<AgentError>() {
+        Ok(err) => *err,
+        Err(err) => store_error(err, client_error),
     }
 }
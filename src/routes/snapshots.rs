@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use rocket::serde::json::{serde_json, Json};
+use rocket::{get, put, State};
+use rocket_okapi::openapi;
+
+use crate::authn::{scopes, ApiKey};
+use crate::errors::response::AgentError;
+use crate::routes::{authorize_admin, store_error};
+use crate::services::admin::{AdminAction, AdminAuthorizer, AdminResource};
+use crate::services::audit::AuditLog;
+use crate::services::bundles::Bundles;
+use crate::services::snapshots::Snapshot;
+
+/// Export the policies, data and schema in a single document with a revision and checksum,
+/// along with the position of the change feed they are at
+#[openapi]
+#[get("/snapshot")]
+pub async fn get_snapshot(
+    auth: ApiKey<scopes::BundleRead>,
+    bundles: &State<Arc<Bundles>>,
+    admin_authorizer: &State<AdminAuthorizer>,
+) -> Result<Json<Snapshot>, AgentError> {
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::ExportBundle,
+        &AdminResource::Store("snapshot"),
+    )?;
+    let (bundle, position) = bundles.export_at().await;
+    Ok(Json(Snapshot::from(bundle).at(position)))
+}
+
+/// Verify a snapshot and restore its policies and data into the stores at once
+#[openapi]
+#[put("/snapshot", data = "<document>")]
+pub async fn update_snapshot(
+    auth: ApiKey<scopes::BundleWrite>,
+    document: Vec<u8>,
+    bundles: &State<Arc<Bundles>>,
+    admin_authorizer: &State<AdminAuthorizer>,
+    audit_log: &State<Arc<AuditLog>>,
+) -> Result<Json<Snapshot>, AgentError> {
+    let resource = AdminResource::Store("snapshot");
+    authorize_admin(
+        admin_authorizer,
+        auth.name(),
+        AdminAction::ActivateBundle,
+        &resource,
+    )?;
+    // Read as bytes, so a snapshot is bounded by the bundle limit rather than the JSON one
+    let bundle = match serde_json::from_slice::<Snapshot>(&document)
+        .map_err(|err| err.to_string())
+        .and_then(|snapshot| snapshot.into_bundle().map_err(|err| err.to_string()))
+    {
+        Ok(bundle) => bundle,
+        Err(reason) => return Err(AgentError::BadRequest { reason }),
+    };
+    // The manifest of the bundle could not be recorded otherwise
+    if let Err(err) = audit_log.ready().await {
+        return Err(AgentError::Internal {
+            reason: err.to_string(),
+        });
+    }
+    let active_manifest = bundles
+        .active()
+        .await
+        .as_ref()
+        .map(|active| active.manifest.clone());
+    let manifest = match bundles.activate(bundle).await {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            return Err(store_error(err, |err| AgentError::BadRequest {
+                reason: err.to_string(),
+            }))
+        }
+    };
+    audit_log
+        .record(
+            auth.name(),
+            AdminAction::ActivateBundle,
+            &resource,
+            active_manifest.and_then(|before| serde_json::to_value(before).ok()),
+            serde_json::to_value(&manifest).ok(),
+        )
+        .await;
+    let (bundle, position) = bundles.export_at().await;
+    Ok(Json(Snapshot::from(bundle).at(position)))
+}
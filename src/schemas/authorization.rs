@@ -31,6 +31,24 @@ pub struct AuthorizationCall {
     entities: Option<serde_json::Value>,
 }
 
+impl AuthorizationCall {
+    pub fn principal(&self) -> Option<&str> {
+        self.principal.as_deref()
+    }
+
+    pub fn action(&self) -> Option<&str> {
+        self.action.as_deref()
+    }
+
+    pub fn resource(&self) -> Option<&str> {
+        self.resource.as_deref()
+    }
+
+    pub fn context(&self) -> Option<&serde_json::Value> {
+        self.context.as_ref()
+    }
+}
+
 fn string_to_euid(optional_str: Option<String>) -> Result<Option<EntityUid>, ParseErrors> {
     match optional_str {
         Some(p) => match EntityUid::from_str(&p) {
@@ -68,7 +86,7 @@ impl TryInto<Request> for AuthorizationCall {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum DecisionRef {
     Allow,
     /// The `Authorizer` determined that the query should be denied.
@@ -93,6 +111,20 @@ pub struct AuthorizationAnswer {
     diagnostics: DiagnosticsRef,
 }
 
+impl AuthorizationAnswer {
+    pub fn decision(&self) -> &DecisionRef {
+        &self.decision
+    }
+
+    pub fn reasons(&self) -> &HashSet<String> {
+        &self.diagnostics.reason
+    }
+
+    pub fn errors(&self) -> &HashSet<String> {
+        &self.diagnostics.errors
+    }
+}
+
 impl Into<Response> for AuthorizationAnswer {
     fn into(self) -> Response {
         Response::new(
@@ -15,7 +15,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::common::EmptyError;
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Entity(Value);
 
 impl From<ast::Entity> for Entity {
@@ -44,13 +44,29 @@ impl TryInto<ast::Entity> for Entity {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Entities(Vec<Entity>);
 
 impl Entities {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The type of every entity, as found in its `uid`
+    pub fn entity_types(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .filter_map(|entity| {
+                let uid = entity.0.get("uid")?;
+                let uid = uid.get("__entity").unwrap_or(uid);
+                uid.get("type")?.as_str().map(str::to_owned)
+            })
+            .collect()
+    }
 }
 
 impl From<entities::Entities> for Entities {
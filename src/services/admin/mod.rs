@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use cedar_policy::{
+    Authorizer, Context, Decision, Entities, Entity, EntityId, EntityTypeName, EntityUid,
+    PolicySet, Request, RestrictedExpression,
+};
+use log::{debug, info};
+
+use crate::config;
+use crate::schemas::data as schemas;
+use crate::schemas::policies::Policy;
+use crate::services::data::load_from_file::load_entities_from_file;
+use crate::services::policies::load_from_file::load_policies_from_file;
+
+pub const ANONYMOUS_CALLER: &str = "anonymous";
+
+/// Management operation of the agent, evaluated as `Action::"<operation>"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    ListPolicies,
+    GetPolicy,
+    CreatePolicy,
+    ReplacePolicies,
+    UpdatePolicy,
+    DeletePolicy,
+    GetEntities,
+    UpdateEntities,
+    DeleteEntities,
+    ReadAudit,
+    ReadChanges,
+    ExportBundle,
+    ActivateBundle,
+}
+
+impl Display for AdminAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// The target of a management operation
+pub enum AdminResource {
+    /// `Store::"policies"`, `Store::"data"`, `Store::"audit"`, `Store::"changes"`,
+    /// `Store::"bundle"` or `Store::"snapshot"`, for operations on a whole store
+    Store(&'static str),
+    /// `Policy::"<id>"`, with the annotations of the policy as attributes
+    Policy {
+        id: String,
+        annotations: HashMap<String, String>,
+    },
+    /// `EntityType::"<type>"`, for the entities of the given type
+    EntityType(String),
+}
+
+impl AdminResource {
+    pub fn policy(policy: &Policy) -> Self {
+        let annotations = match cedar_policy::Policy::parse(None, &policy.content) {
+            Ok(parsed) => parsed
+                .annotations()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect(),
+            Err(_) => HashMap::new(),
+        };
+        AdminResource::Policy {
+            id: policy.id.clone(),
+            annotations,
+        }
+    }
+
+    /// The policy of the given id when none is stored, without annotations
+    pub fn missing_policy(id: &str) -> Self {
+        AdminResource::Policy {
+            id: id.to_owned(),
+            annotations: HashMap::new(),
+        }
+    }
+
+    /// One resource per entity type found in the given entities
+    pub fn entity_types(entities: &schemas::Entities) -> Vec<Self> {
+        let types: HashSet<String> = entities.entity_types().into_iter().collect();
+        types.into_iter().map(AdminResource::EntityType).collect()
+    }
+
+    fn entity(&self) -> Result<Entity, Box<dyn Error>> {
+        Ok(match self {
+            AdminResource::Store(store) => Entity::with_uid(uid("Store", store)?),
+            AdminResource::Policy { id, annotations } => Entity::new(
+                uid("Policy", id)?,
+                annotations
+                    .iter()
+                    .map(|(key, value)| {
+                        (key.clone(), RestrictedExpression::new_string(value.clone()))
+                    })
+                    .collect(),
+                HashSet::new(),
+            ),
+            AdminResource::EntityType(entity_type) => {
+                Entity::with_uid(uid("EntityType", entity_type)?)
+            }
+        })
+    }
+}
+
+impl Display for AdminResource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminResource::Store(store) => write!(f, "Store::{:?}", store),
+            AdminResource::Policy { id, .. } => write!(f, "Policy::{:?}", id),
+            AdminResource::EntityType(entity_type) => write!(f, "EntityType::{:?}", entity_type),
+        }
+    }
+}
+
+fn uid(entity_type: &str, id: &str) -> Result<EntityUid, Box<dyn Error>> {
+    Ok(EntityUid::from_type_name_and_id(
+        EntityTypeName::from_str(entity_type)?,
+        EntityId::from_str(id)?,
+    ))
+}
+
+/// Authorize the management calls of the agent against a dedicated policy set
+pub struct AdminAuthorizer {
+    policies: Option<PolicySet>,
+    entities: Vec<Entity>,
+    authorizer: Authorizer,
+}
+
+impl AdminAuthorizer {
+    pub fn disabled() -> Self {
+        Self {
+            policies: None,
+            entities: Vec::new(),
+            authorizer: Authorizer::new(),
+        }
+    }
+
+    pub fn new(
+        policies: Vec<Policy>,
+        entities: Option<schemas::Entities>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut policy_set = PolicySet::new();
+        for policy in policies.iter() {
+            let policy: cedar_policy::Policy = policy.try_into()?;
+            policy_set.add(policy)?;
+        }
+        let entities = match entities {
+            Some(entities) => {
+                let entities: Entities = (&entities).try_into()?;
+                entities.iter().cloned().collect()
+            }
+            None => Vec::new(),
+        };
+        Ok(Self {
+            policies: Some(policy_set),
+            entities,
+            authorizer: Authorizer::new(),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.policies.is_some()
+    }
+
+    /// Evaluate `Caller::"<caller>"` performing the action on the resource,
+    /// always allowed when the admin policies are not configured
+    pub fn is_allowed(
+        &self,
+        caller: Option<&str>,
+        action: AdminAction,
+        resource: &AdminResource,
+    ) -> bool {
+        let Some(policies) = self.policies.as_ref() else {
+            return true;
+        };
+        let caller = caller.unwrap_or(ANONYMOUS_CALLER);
+        let evaluation = || -> Result<Decision, Box<dyn Error>> {
+            let resource_entity = resource.entity()?;
+            let request = Request::new(
+                Some(uid("Caller", caller)?),
+                Some(uid("Action", &action.to_string())?),
+                Some(resource_entity.uid()),
+                Context::empty(),
+            );
+            let entities = Entities::from_entities(
+                self.entities
+                    .iter()
+                    .cloned()
+                    .chain(std::iter::once(resource_entity)),
+            )?;
+            Ok(self
+                .authorizer
+                .is_authorized(&request, policies, &entities)
+                .decision())
+        };
+        match evaluation() {
+            Ok(decision) => {
+                debug!(caller = caller, action:% = action; "Admin decision {:?}", decision);
+                decision == Decision::Allow
+            }
+            Err(err) => {
+                info!(caller = caller, action:% = action; "Failed to evaluate admin request: {}", err);
+                false
+            }
+        }
+    }
+}
+
+pub(crate) async fn init(conf: &config::Config) -> Result<AdminAuthorizer, Box<dyn Error>> {
+    let Some(policies_path) = conf.admin_policies.clone() else {
+        return Ok(AdminAuthorizer::disabled());
+    };
+    let policies = load_policies_from_file(policies_path).await?;
+    let entities = match conf.admin_data.clone() {
+        Some(data_path) => Some(load_entities_from_file(data_path).await?),
+        None => None,
+    };
+    let admin_authorizer = AdminAuthorizer::new(policies.into_inner(), entities)?;
+    info!("Admin API calls are authorized using the admin policies");
+    Ok(admin_authorizer)
+}
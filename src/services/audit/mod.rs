@@ -0,0 +1,505 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_lock::Mutex;
+use async_trait::async_trait;
+use log::{error, info, warn};
+use rocket::route::{self, Handler};
+use rocket::serde::json::{serde_json, Value};
+use rocket::{Data, Request, Route};
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::authn;
+use crate::common::RequestId;
+use crate::config;
+use crate::services::admin::{AdminAction, AdminResource, ANONYMOUS_CALLER};
+use crate::services::storage::StorageError;
+
+pub mod stores;
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+/// The caller recorded for the changes the agent makes by itself, outside of a request:
+/// loading files, polling a bundle or a Git repository and following a leader
+pub const AGENT_CALLER: &str = "agent";
+
+tokio::task_local! {
+    /// The caller of the request handled by the current task, `None` when not authenticated
+    static CURRENT_CALLER: Option<String>;
+}
+
+/// The caller of the change made by the current task
+fn current_caller() -> String {
+    match CURRENT_CALLER.try_with(|caller| caller.clone()) {
+        Ok(caller) => caller.unwrap_or_else(|| ANONYMOUS_CALLER.to_owned()),
+        Err(_) => AGENT_CALLER.to_owned(),
+    }
+}
+
+/// A change made through the admin API, as written to the audit log
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct AuditRecord {
+    /// Position of the record in the log, starting at 1
+    pub sequence: u64,
+    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// The name of the key, token subject or certificate subject of the caller
+    pub caller: String,
+    pub action: String,
+    /// The changed object, e.g. `Policy::"admins-policy"` or `Store::"data"`
+    pub object: String,
+    /// The object before the change. When a whole store is replaced,
+    /// only the entries removed or changed by the replacement
+    pub before: Option<Value>,
+    /// The object after the change. When a whole store is replaced,
+    /// only the entries added or changed by the replacement
+    pub after: Option<Value>,
+    /// Hash of the previous record, when the hash chain is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_hash: Option<String>,
+    /// SHA-256 of the previous hash followed by this record without its hash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl AuditRecord {
+    fn compute_hash(&self) -> Result<String, serde_json::Error> {
+        let unhashed = AuditRecord {
+            hash: None,
+            ..self.clone()
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(self.previous_hash.as_deref().unwrap_or_default().as_bytes());
+        hasher.update(serde_json::to_string(&unhashed)?.as_bytes());
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+/// Filter of the audit records returned by a query
+#[derive(Default, Clone)]
+pub struct AuditQuery {
+    /// Only the records after this sequence number
+    pub since: Option<u64>,
+    pub limit: Option<usize>,
+    pub caller: Option<String>,
+    pub object: Option<String>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        !matches!(self.since, Some(since) if record.sequence <= since)
+            && !matches!(&self.caller, Some(caller) if caller != &record.caller)
+            && !matches!(&self.object, Some(object) if object != &record.object)
+    }
+}
+
+struct AuditFile {
+    path: PathBuf,
+    file: File,
+    /// Length of the file up to the end of the last record written
+    size: u64,
+    sequence: u64,
+    last_hash: Option<String>,
+    /// Lines of the records that failed to be written, written before any other change
+    pending: Vec<String>,
+}
+
+impl AuditFile {
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        // Drop what a failed write left of a record, so that the retried one follows the last
+        if self.file.metadata()?.len() > self.size {
+            self.file.set_len(self.size)?;
+        }
+        while let Some(line) = self.pending.first() {
+            let line = format!("{}\n", line);
+            let written = self
+                .file
+                .write_all(line.as_bytes())
+                .and_then(|_| self.file.sync_data());
+            if let Err(err) = written {
+                if let Err(truncate_err) = self.file.set_len(self.size) {
+                    warn!(path:% = self.path.display(), error:% = truncate_err; "Failed to truncate the audit log");
+                }
+                return Err(err);
+            }
+            self.size += line.len() as u64;
+            self.pending.remove(0);
+        }
+        Ok(())
+    }
+
+    /// Write the pending records on a blocking thread
+    async fn flush_blocking(mut file: async_lock::MutexGuardArc<AuditFile>) -> std::io::Result<()> {
+        rocket::tokio::task::spawn_blocking(move || file.flush())
+            .await
+            .map_err(std::io::Error::other)?
+    }
+}
+
+/// Append-only log of the changes of the policies and data.
+/// A record that fails to be written is kept and retried, and the stores refuse
+/// any other change until it is written, so that no change goes unrecorded
+pub struct AuditLog {
+    file: Option<Arc<Mutex<AuditFile>>>,
+    hash_chain: bool,
+}
+
+type RecordResult = Result<AuditRecord, Box<dyn Error + Send + Sync>>;
+
+/// Read the records of an audit log file one line at a time
+fn read_records(
+    path: &Path,
+) -> Result<Box<dyn Iterator<Item = RecordResult>>, Box<dyn Error + Send + Sync>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Box::new(std::iter::empty()))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let records = BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(index, line)| -> RecordResult {
+            serde_json::from_str(&line?)
+                .map_err(|err| format!("line {}: {}", index + 1, err).into())
+        });
+    Ok(Box::new(records))
+}
+
+/// Read the records matching the query, stopping once its limit is reached
+fn read_page(
+    path: &Path,
+    query: &AuditQuery,
+) -> Result<Vec<AuditRecord>, Box<dyn Error + Send + Sync>> {
+    let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+    let mut records = Vec::new();
+    for record in read_records(path)? {
+        if records.len() >= limit {
+            break;
+        }
+        let record = record?;
+        if query.matches(&record) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// A copy of the value with the keys of its objects and the items of its arrays sorted,
+/// as the sets of an entity, e.g. its parents, are serialized in any order
+fn canonical(value: &Value) -> Value {
+    match value {
+        Value::Array(items) => {
+            let mut items: Vec<Value> = items.iter().map(canonical).collect();
+            items.sort_by_cached_key(Value::to_string);
+            Value::Array(items)
+        }
+        Value::Object(fields) => {
+            let mut fields: Vec<(&String, &Value)> = fields.iter().collect();
+            fields.sort_by_key(|(name, _)| *name);
+            Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| (name.clone(), canonical(value)))
+                    .collect(),
+            )
+        }
+        value => value.clone(),
+    }
+}
+
+/// The entries of the arrays `before` and `after` that differ:
+/// those removed or changed, as they were, and those added or changed, as they are
+pub fn diff(before: Option<Value>, after: Option<Value>) -> (Option<Value>, Option<Value>) {
+    let (Some(Value::Array(before)), Some(Value::Array(after))) = (&before, &after) else {
+        return (before, after);
+    };
+    let changed = |entries: &[Value], others: &[Value]| -> Vec<Value> {
+        let others: HashSet<String> = others
+            .iter()
+            .map(|other| canonical(other).to_string())
+            .collect();
+        entries
+            .iter()
+            .filter(|entry| !others.contains(&canonical(entry).to_string()))
+            .cloned()
+            .collect()
+    };
+    (
+        Some(Value::Array(changed(before, after))),
+        Some(Value::Array(changed(after, before))),
+    )
+}
+
+/// Check the sequence numbers and the hash chain of an audit log file,
+/// returning the number of records
+pub fn verify(path: &Path) -> Result<u64, Box<dyn Error>> {
+    let mut previous: Option<AuditRecord> = None;
+    let mut count = 0;
+    let records = read_records(path).map_err(|err| err as Box<dyn Error>)?;
+    for record in records {
+        let record = record.map_err(|err| err as Box<dyn Error>)?;
+        let expected_sequence = previous.as_ref().map_or(1, |p| p.sequence + 1);
+        if record.sequence != expected_sequence {
+            return Err(format!(
+                "record {} follows record {}",
+                record.sequence,
+                expected_sequence - 1
+            )
+            .into());
+        }
+        if let Some(hash) = record.hash.as_ref() {
+            let previous_hash = previous.as_ref().and_then(|p| p.hash.clone());
+            if record.previous_hash != previous_hash {
+                return Err(format!("record {} breaks the hash chain", record.sequence).into());
+            }
+            if &record.compute_hash()? != hash {
+                return Err(format!("record {} was modified", record.sequence).into());
+            }
+        }
+        count += 1;
+        previous = Some(record);
+    }
+    Ok(count)
+}
+
+impl AuditLog {
+    pub fn disabled() -> Self {
+        Self {
+            file: None,
+            hash_chain: false,
+        }
+    }
+
+    /// Open the audit log, resuming the sequence and the hash chain of the existing records
+    pub fn new(path: PathBuf, hash_chain: bool) -> Result<Self, Box<dyn Error>> {
+        let mut last = None;
+        for record in read_records(&path).map_err(|err| err as Box<dyn Error>)? {
+            last = Some(record.map_err(|err| err as Box<dyn Error>)?);
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(AuditFile {
+                sequence: last.as_ref().map_or(0, |record| record.sequence),
+                last_hash: last.and_then(|record| record.hash),
+                size: file.metadata()?.len(),
+                path,
+                file,
+                pending: Vec::new(),
+            }))),
+            hash_chain,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Append a record of a successful change made by the caller
+    pub async fn record(
+        &self,
+        caller: Option<&str>,
+        action: AdminAction,
+        resource: &AdminResource,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) {
+        let Some(file) = self.file.as_ref() else {
+            return;
+        };
+        let mut file = file.lock_arc().await;
+        let mut record = AuditRecord {
+            sequence: file.sequence + 1,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            request_id: RequestId::current().map(|id| id.to_string()),
+            caller: caller.unwrap_or(ANONYMOUS_CALLER).to_owned(),
+            action: action.to_string(),
+            object: resource.to_string(),
+            before,
+            after,
+            previous_hash: None,
+            hash: None,
+        };
+        let line = (|| -> Result<String, serde_json::Error> {
+            if self.hash_chain {
+                record.previous_hash = file.last_hash.clone();
+                record.hash = Some(record.compute_hash()?);
+            }
+            serde_json::to_string(&record)
+        })();
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                error!(
+                    path:% = file.path.display(), sequence = record.sequence;
+                    "Failed to serialize the audit record of {} {}: {}", record.action, record.object, err
+                );
+                return;
+            }
+        };
+        file.sequence = record.sequence;
+        file.last_hash = record.hash.clone();
+        file.pending.push(line);
+        let path = file.path.clone();
+        if let Err(err) = AuditFile::flush_blocking(file).await {
+            error!(
+                path:% = path.display(), sequence = record.sequence;
+                "Failed to write the audit record of {} {}, refusing changes until it is written: {}",
+                record.action, record.object, err
+            );
+        }
+    }
+
+    /// Append a record of a change made by the caller of the current task
+    pub async fn record_current(
+        &self,
+        action: AdminAction,
+        resource: &AdminResource,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) {
+        self.record(Some(&current_caller()), action, resource, before, after)
+            .await
+    }
+
+    /// Write the records left by a failed write, failing while they cannot be written
+    /// so that the change about to be made is refused
+    pub async fn ready(&self) -> Result<(), StorageError> {
+        let Some(file) = self.file.as_ref() else {
+            return Ok(());
+        };
+        let file = file.lock_arc().await;
+        if file.pending.is_empty() {
+            return Ok(());
+        }
+        let pending = file.pending.len();
+        let path = file.path.clone();
+        match AuditFile::flush_blocking(file).await {
+            Ok(()) => {
+                info!(path:% = path.display(), records = pending; "Wrote the pending audit records");
+                Ok(())
+            }
+            Err(err) => Err(StorageError::new(
+                path.display(),
+                format!("{} audit records could not be written: {}", pending, err),
+            )),
+        }
+    }
+
+    /// Read the records matching the query from the file, on a blocking thread
+    pub async fn query(
+        &self,
+        query: &AuditQuery,
+    ) -> Result<Vec<AuditRecord>, Box<dyn Error + Send + Sync>> {
+        let Some(file) = self.file.as_ref() else {
+            return Ok(Vec::new());
+        };
+        let path = file.lock().await.path.clone();
+        let query = query.clone();
+        rocket::tokio::task::spawn_blocking(move || read_page(&path, &query)).await?
+    }
+}
+
+/// Route handler running the wrapped handler with the caller of the request set,
+/// so the stores record the changes made by the request under its caller
+#[derive(Clone)]
+struct CallerHandler(Box<dyn Handler>);
+
+#[async_trait]
+impl Handler for CallerHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        let caller = authn::caller(req).await.map(str::to_owned);
+        CURRENT_CALLER.scope(caller, self.0.handle(req, data)).await
+    }
+}
+
+pub(crate) fn with_caller(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(CallerHandler(route.handler));
+            route
+        })
+        .collect()
+}
+
+pub(crate) fn init(conf: &config::Config) -> Result<AuditLog, Box<dyn Error>> {
+    let Some(path) = conf.audit_log.clone() else {
+        return Ok(AuditLog::disabled());
+    };
+    let hash_chain = conf.audit_log_hash_chain.unwrap_or(false);
+    // Refuse to extend a log whose records were modified or removed
+    if hash_chain {
+        verify(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+    let audit_log = AuditLog::new(path.clone(), hash_chain)?;
+    info!(path:% = path.display(), hash_chain = hash_chain; "Recording the changes of the policies and data to the audit log");
+    Ok(audit_log)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::stores::AuditedPolicyStore;
+    use super::*;
+    use crate::schemas::policies::Policy;
+    use crate::services::policies::memory::MemoryPolicyStore;
+    use crate::services::PolicyStore;
+
+    #[tokio::test]
+    async fn pending_records_tests() {
+        let path = std::env::temp_dir().join(format!("audit-{}.log", uuid::Uuid::new_v4()));
+        let audit_log = Arc::new(AuditLog::new(path.clone(), true).unwrap());
+        let policy_store =
+            AuditedPolicyStore::new(Box::new(MemoryPolicyStore::new()), audit_log.clone());
+        let policy = Policy {
+            id: "all".to_owned(),
+            content: "permit(principal, action, resource);".to_owned(),
+        };
+        let record = || {
+            audit_log.record(
+                Some("admin"),
+                AdminAction::DeleteEntities,
+                &AdminResource::Store("data"),
+                None,
+                None,
+            )
+        };
+        record().await;
+
+        // A file opened for reading only fails every write
+        let writable = {
+            let mut file = audit_log.file.as_ref().unwrap().lock().await;
+            std::mem::replace(&mut file.file, File::open(&path).unwrap())
+        };
+        record().await;
+        assert!(audit_log.ready().await.is_err());
+        // No other change is made until the record is written
+        assert!(policy_store.create_policy(&policy).await.is_err());
+        assert!(policy_store.get_policies().await.is_empty());
+        assert_eq!(verify(&path).unwrap(), 1);
+
+        // What a failed write left of a record is dropped before it is retried
+        let mut partial = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(partial, "{{\"sequence\":2,").unwrap();
+        assert!(verify(&path).is_err());
+        audit_log.file.as_ref().unwrap().lock().await.file = writable;
+        assert!(audit_log.ready().await.is_ok());
+        policy_store.create_policy(&policy).await.unwrap();
+        assert_eq!(verify(&path).unwrap(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use cedar_policy::PolicySet;
+use rocket::serde::json::serde_json;
+
+use crate::schemas::data as schemas;
+use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::services::admin::{AdminAction, AdminResource};
+use crate::services::audit::{diff, AuditLog};
+use crate::services::{DataStore, PolicyStore};
+
+/// Policy store recording its successful changes to the audit log, whatever made them,
+/// and refusing any change while a previous record could not be written
+pub struct AuditedPolicyStore {
+    store: Box<dyn PolicyStore>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl AuditedPolicyStore {
+    pub fn new(store: Box<dyn PolicyStore>, audit_log: Arc<AuditLog>) -> Self {
+        Self { store, audit_log }
+    }
+}
+
+#[async_trait]
+impl PolicyStore for AuditedPolicyStore {
+    async fn policy_set(&self) -> PolicySet {
+        self.store.policy_set().await
+    }
+
+    async fn revised_policy_set(&self) -> (PolicySet, u64) {
+        self.store.revised_policy_set().await
+    }
+
+    async fn get_policies(&self) -> Vec<Policy> {
+        self.store.get_policies().await
+    }
+
+    async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        self.store.get_policy(id).await
+    }
+
+    async fn create_policy(&self, policy: &Policy) -> Result<Policy, Box<dyn Error>> {
+        self.audit_log.ready().await?;
+        let created = self.store.create_policy(policy).await?;
+        self.audit_log
+            .record_current(
+                AdminAction::CreatePolicy,
+                &AdminResource::policy(&created),
+                None,
+                serde_json::to_value(&created).ok(),
+            )
+            .await;
+        Ok(created)
+    }
+
+    async fn update_policies(&self, policies: Vec<Policy>) -> Result<Vec<Policy>, Box<dyn Error>> {
+        self.audit_log.ready().await?;
+        let stored = self.store.get_policies().await;
+        let updated = self.store.update_policies(policies).await?;
+        let (before, after) = diff(
+            serde_json::to_value(stored).ok(),
+            serde_json::to_value(&updated).ok(),
+        );
+        self.audit_log
+            .record_current(
+                AdminAction::ReplacePolicies,
+                &AdminResource::Store("policies"),
+                before,
+                after,
+            )
+            .await;
+        Ok(updated)
+    }
+
+    async fn update_policy(
+        &self,
+        id: String,
+        policy: PolicyUpdate,
+    ) -> Result<Policy, Box<dyn Error>> {
+        self.audit_log.ready().await?;
+        let stored = self.store.get_policy(&id).await.ok();
+        let updated = self.store.update_policy(id, policy).await?;
+        self.audit_log
+            .record_current(
+                AdminAction::UpdatePolicy,
+                &AdminResource::policy(&updated),
+                stored.and_then(|stored| serde_json::to_value(stored).ok()),
+                serde_json::to_value(&updated).ok(),
+            )
+            .await;
+        Ok(updated)
+    }
+
+    async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        self.audit_log.ready().await?;
+        let deleted = self.store.delete_policy(id).await?;
+        self.audit_log
+            .record_current(
+                AdminAction::DeletePolicy,
+                &AdminResource::policy(&deleted),
+                serde_json::to_value(&deleted).ok(),
+                None,
+            )
+            .await;
+        Ok(deleted)
+    }
+}
+
+/// Data store recording its successful changes to the audit log, whatever made them,
+/// and refusing any change while a previous record could not be written
+pub struct AuditedDataStore {
+    store: Box<dyn DataStore>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl AuditedDataStore {
+    pub fn new(store: Box<dyn DataStore>, audit_log: Arc<AuditLog>) -> Self {
+        Self { store, audit_log }
+    }
+}
+
+#[async_trait]
+impl DataStore for AuditedDataStore {
+    async fn entities(&self) -> cedar_policy::Entities {
+        self.store.entities().await
+    }
+
+    async fn get_entities(&self) -> schemas::Entities {
+        self.store.get_entities().await
+    }
+
+    async fn delete_entities(&self) -> Result<(), Box<dyn Error>> {
+        self.audit_log.ready().await?;
+        let stored = self.store.get_entities().await;
+        self.store.delete_entities().await?;
+        self.audit_log
+            .record_current(
+                AdminAction::DeleteEntities,
+                &AdminResource::Store("data"),
+                serde_json::to_value(stored).ok(),
+                None,
+            )
+            .await;
+        Ok(())
+    }
+
+    async fn update_entities(
+        &self,
+        entities: schemas::Entities,
+    ) -> Result<schemas::Entities, Box<dyn Error>> {
+        self.audit_log.ready().await?;
+        let stored = self.store.get_entities().await;
+        let updated = self.store.update_entities(entities).await?;
+        let (before, after) = diff(
+            serde_json::to_value(stored).ok(),
+            serde_json::to_value(&updated).ok(),
+        );
+        self.audit_log
+            .record_current(
+                AdminAction::UpdateEntities,
+                &AdminResource::Store("data"),
+                before,
+                after,
+            )
+            .await;
+        Ok(updated)
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cedar_policy::{PolicySet, Schema, ValidationMode, Validator};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use jsonwebtoken::{Algorithm, DecodingKey};
+use log::{error, info};
+use rocket::serde::json::{serde_json, Value};
+use rocket::tokio::sync::{RwLock, RwLockReadGuard};
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::authn;
+use crate::config;
+use crate::schemas::data::Entities;
+use crate::schemas::policies::Policy;
+use crate::services::changes::{ChangeFeed, Position};
+use crate::services::storage::StorageError;
+use crate::services::{DataStore, PolicyStore};
+
+pub mod polling;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// The signature of the manifest file, base64url encoded as in a JSON Web Signature
+pub const SIGNATURE_FILE: &str = "manifest.sig";
+pub const POLICIES_FILE: &str = "policies.json";
+pub const DATA_FILE: &str = "data.json";
+pub const SCHEMA_FILE: &str = "schema.json";
+/// Maximum size of the unpacked files of a bundle, against decompression bombs
+const MAX_UNPACKED_SIZE: u64 = 256 * 1024 * 1024;
+const DEFAULT_POLL_INTERVAL: u64 = 60;
+
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error("Unable to read the bundle archive: {0}")]
+    Archive(String),
+    #[error("The bundle has no {0} file")]
+    MissingFile(&'static str),
+    #[error("The file {0} of the bundle is not listed in its manifest")]
+    UnexpectedFile(String),
+    #[error("The checksum of the file {0} does not match the manifest")]
+    Checksum(String),
+    #[error("The bundle signature is invalid: {0}")]
+    Signature(String),
+    #[error("The file {file} of the bundle is invalid: {reason}")]
+    Invalid { file: &'static str, reason: String },
+}
+
+/// The revision of a bundle and the SHA-256 digest of each of its files
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    pub revision: String,
+    /// The hex SHA-256 digest of every file of the bundle, by file name
+    pub files: BTreeMap<String, String>,
+}
+
+fn checksum(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Checks the signature of the bundle manifests against a public key
+#[derive(Clone)]
+pub struct Verifier {
+    key: Option<(DecodingKey, Algorithm)>,
+}
+
+impl Verifier {
+    /// Accept bundles whatever their signature, still checking their checksums
+    pub fn unverified() -> Self {
+        Self { key: None }
+    }
+
+    pub fn new(key: DecodingKey, algorithm: Algorithm) -> Self {
+        Self {
+            key: Some((key, algorithm)),
+        }
+    }
+
+    /// Load an RSA, EC or Ed25519 public key in PEM format
+    pub fn from_pem(pem: &[u8], algorithm: Algorithm) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(authn::jwt::load_pem(pem)?, algorithm))
+    }
+
+    fn verify(&self, manifest: &[u8], signature: Option<&[u8]>) -> Result<(), BundleError> {
+        let (key, algorithm) = match self.key.as_ref() {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        let signature = signature.ok_or(BundleError::MissingFile(SIGNATURE_FILE))?;
+        let signature = String::from_utf8_lossy(signature);
+        match jsonwebtoken::crypto::verify(signature.trim(), manifest, key, *algorithm) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BundleError::Signature(
+                "the manifest was not signed by the configured key".to_owned(),
+            )),
+            Err(err) => Err(BundleError::Signature(err.to_string())),
+        }
+    }
+}
+
+/// The policies, entities and optional schema distributed together under a revision
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    pub manifest: Manifest,
+    pub policies: Vec<Policy>,
+    pub entities: Entities,
+    pub schema: Option<Value>,
+}
+
+impl Bundle {
+    pub fn new(
+        revision: String,
+        policies: Vec<Policy>,
+        entities: Entities,
+        schema: Option<Value>,
+    ) -> Self {
+        let mut bundle = Self {
+            manifest: Manifest {
+                revision,
+                files: BTreeMap::new(),
+            },
+            policies,
+            entities,
+            schema,
+        };
+        bundle.manifest.files = bundle
+            .files()
+            .into_iter()
+            .map(|(name, content)| (name.to_owned(), checksum(&content)))
+            .collect();
+        bundle
+    }
+
+    /// Read a bundle from a tar.gz archive, checking its signature, checksums and schema
+    pub fn unpack(archive: &[u8], verifier: &Verifier) -> Result<Self, BundleError> {
+        let archive_error = |err: std::io::Error| BundleError::Archive(err.to_string());
+        let decoder = GzDecoder::new(archive).take(MAX_UNPACKED_SIZE);
+        let mut archive = tar::Archive::new(decoder);
+        let mut files = BTreeMap::new();
+        for entry in archive.entries().map_err(archive_error)? {
+            let mut entry = entry.map_err(archive_error)?;
+            if entry.header().entry_type().is_dir() {
+                continue;
+            }
+            let path = entry.path().map_err(archive_error)?;
+            let name = path.to_string_lossy();
+            let name = name.strip_prefix("./").unwrap_or(&name).to_owned();
+            if !entry.header().entry_type().is_file() {
+                return Err(BundleError::Archive(format!(
+                    "{} is not a regular file",
+                    name
+                )));
+            }
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).map_err(archive_error)?;
+            if files.insert(name.clone(), content).is_some() {
+                return Err(BundleError::Archive(format!(
+                    "{} appears more than once",
+                    name
+                )));
+            }
+        }
+
+        let manifest_content = files
+            .remove(MANIFEST_FILE)
+            .ok_or(BundleError::MissingFile(MANIFEST_FILE))?;
+        verifier.verify(&manifest_content, files.remove(SIGNATURE_FILE).as_deref())?;
+        let manifest: Manifest =
+            serde_json::from_slice(&manifest_content).map_err(|err| BundleError::Invalid {
+                file: MANIFEST_FILE,
+                reason: err.to_string(),
+            })?;
+        for (name, content) in files.iter() {
+            match manifest.files.get(name) {
+                Some(expected) if *expected == checksum(content) => {}
+                Some(_) => return Err(BundleError::Checksum(name.clone())),
+                None => return Err(BundleError::UnexpectedFile(name.clone())),
+            }
+        }
+        if let Some(name) = manifest
+            .files
+            .keys()
+            .find(|name| !files.contains_key(*name))
+        {
+            return Err(BundleError::Archive(format!(
+                "the file {} of the manifest is missing",
+                name
+            )));
+        }
+
+        let policies =
+            parse(&files, POLICIES_FILE)?.ok_or(BundleError::MissingFile(POLICIES_FILE))?;
+        let entities = parse(&files, DATA_FILE)?.ok_or(BundleError::MissingFile(DATA_FILE))?;
+        let schema = parse(&files, SCHEMA_FILE)?;
+        let bundle = Self {
+            manifest,
+            policies,
+            entities,
+            schema,
+        };
+        bundle.validate()?;
+        Ok(bundle)
+    }
+
+    /// Write the bundle as a tar.gz archive, without a signature
+    pub fn pack(&self) -> Result<Vec<u8>, BundleError> {
+        self.archive(None)
+    }
+
+    fn archive(&self, signature: Option<String>) -> Result<Vec<u8>, BundleError> {
+        let archive_error = |err: std::io::Error| BundleError::Archive(err.to_string());
+        let mut files = vec![(MANIFEST_FILE, self.manifest_content()?)];
+        if let Some(signature) = signature {
+            files.push((SIGNATURE_FILE, signature.into_bytes()));
+        }
+        files.extend(self.files());
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_slice())
+                .map_err(archive_error)?;
+        }
+        builder
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .map_err(archive_error)
+    }
+
+    fn manifest_content(&self) -> Result<Vec<u8>, BundleError> {
+        serde_json::to_vec_pretty(&self.manifest).map_err(|err| BundleError::Invalid {
+            file: MANIFEST_FILE,
+            reason: err.to_string(),
+        })
+    }
+
+    /// The content of the policy, data and schema files
+    fn files(&self) -> Vec<(&'static str, Vec<u8>)> {
+        let mut files = vec![
+            (POLICIES_FILE, serde_json::to_vec_pretty(&self.policies)),
+            (DATA_FILE, serde_json::to_vec_pretty(&self.entities)),
+        ];
+        if let Some(schema) = self.schema.as_ref() {
+            files.push((SCHEMA_FILE, serde_json::to_vec_pretty(schema)));
+        }
+        files
+            .into_iter()
+            .map(|(name, content)| (name, content.unwrap_or_default()))
+            .collect()
+    }
+
+    /// Check the policies and entities against the schema, when the bundle has one
+    pub fn validate(&self) -> Result<(), BundleError> {
+        let schema = self
+            .schema
+            .as_ref()
+            .map(|schema| Schema::from_json_value(schema.clone()))
+            .transpose()
+            .map_err(|err| BundleError::Invalid {
+                file: SCHEMA_FILE,
+                reason: err.to_string(),
+            })?;
+        let policy_set = self.parse_contents(schema.as_ref())?;
+        let schema = match schema {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+        let validator = Validator::new(schema);
+        let result = validator.validate(&policy_set, ValidationMode::default());
+        if !result.validation_passed() {
+            let errors: Vec<String> = result
+                .validation_errors()
+                .map(|err| err.to_string())
+                .collect();
+            return Err(BundleError::Invalid {
+                file: POLICIES_FILE,
+                reason: errors.join("; "),
+            });
+        }
+        Ok(())
+    }
+
+    /// Parse the policies and entities as the stores do, the entities against the schema
+    /// when given, so that a bundle the stores would reject is rejected before either changes
+    fn parse_contents(&self, schema: Option<&Schema>) -> Result<PolicySet, BundleError> {
+        let invalid =
+            |file: &'static str| move |reason: String| BundleError::Invalid { file, reason };
+        let mut policy_set = PolicySet::new();
+        for policy in self.policies.iter() {
+            let policy: cedar_policy::Policy =
+                policy
+                    .try_into()
+                    .map_err(|err: cedar_policy::ParseErrors| {
+                        invalid(POLICIES_FILE)(err.to_string())
+                    })?;
+            policy_set
+                .add(policy)
+                .map_err(|err| invalid(POLICIES_FILE)(err.to_string()))?;
+        }
+        let entities = serde_json::to_value(&self.entities)
+            .map_err(|err| invalid(DATA_FILE)(err.to_string()))?;
+        cedar_policy::Entities::from_json_value(entities, schema)
+            .map_err(|err| invalid(DATA_FILE)(err.to_string()))?;
+        Ok(policy_set)
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(
+    files: &BTreeMap<String, Vec<u8>>,
+    name: &'static str,
+) -> Result<Option<T>, BundleError> {
+    files
+        .get(name)
+        .map(|content| serde_json::from_slice(content))
+        .transpose()
+        .map_err(|err| BundleError::Invalid {
+            file: name,
+            reason: err.to_string(),
+        })
+}
+
+/// Report the entities the data store failed to write as an error
+/// that can be held while the policies are restored
+fn rejected_entities(err: Box<dyn Error>) -> Box<dyn Error + Send + Sync> {
+    match err.downcast::<StorageError>() {
+        Ok(err) => err,
+        Err(err) => Box::new(BundleError::Invalid {
+            file: DATA_FILE,
+            reason: err.to_string(),
+        }),
+    }
+}
+
+/// The bundle in the stores
+#[derive(Debug, Clone)]
+pub struct ActiveBundle {
+    pub manifest: Manifest,
+    pub schema: Option<Value>,
+    /// The revision of the change feed once the bundle was activated
+    change_revision: u64,
+}
+
+/// Swaps bundles into the policy and data stores
+pub struct Bundles {
+    verifier: Verifier,
+    policy_store: Arc<dyn PolicyStore>,
+    data_store: Arc<dyn DataStore>,
+    change_feed: ChangeFeed,
+    active: RwLock<Option<ActiveBundle>>,
+}
+
+impl Bundles {
+    pub fn new(
+        verifier: Verifier,
+        policy_store: Arc<dyn PolicyStore>,
+        data_store: Arc<dyn DataStore>,
+        change_feed: ChangeFeed,
+    ) -> Self {
+        Self {
+            verifier,
+            policy_store,
+            data_store,
+            change_feed,
+            active: RwLock::new(None),
+        }
+    }
+
+    /// The active bundle. A bundle cannot be swapped in while the guard is held,
+    /// so the policies and entities read meanwhile are from the same bundle
+    pub async fn active(&self) -> RwLockReadGuard<'_, Option<ActiveBundle>> {
+        self.active.read().await
+    }
+
+    /// Unpack an archive with the verifier of the bundles, out of the async runtime
+    pub async fn unpack(&self, archive: Vec<u8>) -> Result<Bundle, BundleError> {
+        let verifier = self.verifier.clone();
+        rocket::tokio::task::spawn_blocking(move || Bundle::unpack(&archive, &verifier))
+            .await
+            .map_err(|err| BundleError::Archive(err.to_string()))?
+    }
+
+    /// Replace the policies and entities by those of the bundle. Both are parsed before
+    /// either store changes, the previous policies are only restored when the data store
+    /// fails to write the entities
+    pub async fn activate(&self, bundle: Bundle) -> Result<Manifest, Box<dyn Error>> {
+        let bundle = rocket::tokio::task::spawn_blocking(move || {
+            bundle.parse_contents(None).map(|_| bundle)
+        })
+        .await??;
+        let mut active = self.active.write().await;
+        let previous_policies = self.policy_store.get_policies().await;
+        self.policy_store.update_policies(bundle.policies).await?;
+        let updated = if bundle.entities.is_empty() {
+            self.data_store.delete_entities().await
+        } else {
+            self.data_store
+                .update_entities(bundle.entities)
+                .await
+                .map(|_| ())
+        };
+        if let Err(err) = updated.map_err(rejected_entities) {
+            if let Err(rollback_err) = self.policy_store.update_policies(previous_policies).await {
+                error!(error:% = rollback_err; "Failed to restore the policies after a rejected bundle");
+            }
+            return Err(err);
+        }
+        info!(revision = bundle.manifest.revision; "Activated bundle {}", bundle.manifest.revision);
+        *active = Some(ActiveBundle {
+            manifest: bundle.manifest.clone(),
+            schema: bundle.schema,
+            change_revision: self.change_feed.revision(),
+        });
+        Ok(bundle.manifest)
+    }
+
+    /// The current policies and entities as a bundle, with the schema of the active bundle.
+    /// The revision is that of the active bundle, or `local.<change revision>`
+    /// once the stores were changed by other means
+    pub async fn export(&self) -> Bundle {
+        self.export_at().await.0
+    }
+
+    /// The current policies and entities as a bundle, with the position of the change feed
+    /// they are at. No change is written while they are read, so both are of that position
+    pub async fn export_at(&self) -> (Bundle, Position) {
+        let active = self.active.read().await;
+        let _writes = self.change_feed.lock_writes().await;
+        let policies = self.policy_store.get_policies().await;
+        let entities = self.data_store.get_entities().await;
+        let position = self.change_feed.position();
+        let revision = match active.as_ref() {
+            Some(active) if active.change_revision == position.revision => {
+                active.manifest.revision.clone()
+            }
+            _ => format!("local.{}", position.revision),
+        };
+        let schema = active.as_ref().and_then(|active| active.schema.clone());
+        (Bundle::new(revision, policies, entities, schema), position)
+    }
+}
+
+/// Load the bundle file when one is configured, failing on an invalid bundle,
+/// then start polling the bundle URL when one is configured
+pub(crate) async fn init(
+    conf: &config::Config,
+    policy_store: Arc<dyn PolicyStore>,
+    data_store: Arc<dyn DataStore>,
+    change_feed: ChangeFeed,
+) -> Result<Arc<Bundles>, Box<dyn Error>> {
+    let verifier = match conf.bundle_public_key.as_ref() {
+        Some(path) => Verifier::from_pem(
+            &fs::read(path)?,
+            conf.bundle_signing_algorithm.unwrap_or(Algorithm::RS256),
+        )
+        .map_err(|err| format!("{}: {}", path.display(), err))?,
+        None => Verifier::unverified(),
+    };
+    let bundles = Arc::new(Bundles::new(
+        verifier,
+        policy_store,
+        data_store,
+        change_feed,
+    ));
+    if let Some(path) = conf.bundle.as_ref() {
+        let archive = fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let bundle = bundles
+            .unpack(archive)
+            .await
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        bundles.activate(bundle).await?;
+    }
+    if let Some(url) = conf.bundle_url.as_ref() {
+        let interval = conf.bundle_poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+        polling::BundlePoller::new(
+            url,
+            conf.bundle_url_authentication
+                .as_ref()
+                .map(|key| key.expose()),
+            Duration::from_secs(interval),
+            conf.bundle_max_size(),
+            bundles.clone(),
+        )?
+        .spawn();
+        info!(url = url, interval = interval; "Polling the bundle");
+    }
+    Ok(bundles)
+}
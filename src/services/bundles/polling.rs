@@ -0,0 +1,140 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use reqwest::header::{AUTHORIZATION, ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+
+use crate::services::bundles::{Bundles, Manifest};
+use crate::services::retry::RetryPolicy;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The result of a poll of the bundle URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollOutcome {
+    /// A new bundle was activated
+    Activated(Manifest),
+    /// The server answered `304 Not Modified`, or the bundle has the revision of the active one
+    Unchanged,
+}
+
+/// Fetches the bundle from a URL on an interval and activates it when it changes
+pub struct BundlePoller {
+    url: String,
+    authentication: Option<String>,
+    interval: Duration,
+    max_size: u64,
+    retry: RetryPolicy,
+    client: reqwest::Client,
+    bundles: Arc<Bundles>,
+    etag: Option<String>,
+}
+
+impl BundlePoller {
+    /// Poll the URL every interval, rejecting the bundles larger than `max_size` bytes
+    pub fn new(
+        url: &str,
+        authentication: Option<&str>,
+        interval: Duration,
+        max_size: u64,
+        bundles: Arc<Bundles>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            url: url.to_owned(),
+            authentication: authentication.map(str::to_owned),
+            interval,
+            max_size,
+            retry: RetryPolicy::within(interval),
+            client,
+            bundles,
+            etag: None,
+        })
+    }
+
+    pub fn spawn(mut self) {
+        rocket::tokio::spawn(async move {
+            let mut failures = 0;
+            loop {
+                let delay = match self.poll().await {
+                    Ok(_) => {
+                        failures = 0;
+                        self.interval
+                    }
+                    Err(err) => {
+                        failures += 1;
+                        let delay = self.retry.backoff(failures);
+                        warn!(
+                            url = self.url, error:% = err, failures = failures, retry_in:? = delay;
+                            "Failed to poll the bundle"
+                        );
+                        delay
+                    }
+                };
+                rocket::tokio::time::sleep(delay).await;
+            }
+        });
+    }
+
+    /// Fetch the bundle unless it is unchanged since the last poll, then verify and activate it
+    pub async fn poll(&mut self) -> Result<PollOutcome, Box<dyn Error + Send + Sync>> {
+        let mut request = self.client.get(&self.url);
+        if let Some(etag) = self.etag.as_ref() {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(authentication) = self.authentication.as_ref() {
+            request = request.header(AUTHORIZATION, authentication);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            debug!(url = self.url; "The bundle is not modified");
+            return Ok(PollOutcome::Unchanged);
+        }
+        let mut response = response.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_owned);
+        // Read by chunks, as the length of a chunked response is only known once read
+        if response.content_length().unwrap_or_default() > self.max_size {
+            return Err(self.too_large());
+        }
+        let mut archive = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (archive.len() + chunk.len()) as u64 > self.max_size {
+                return Err(self.too_large());
+            }
+            archive.extend_from_slice(&chunk);
+        }
+        let bundle = self.bundles.unpack(archive).await?;
+        let active_revision = self
+            .bundles
+            .active()
+            .await
+            .as_ref()
+            .map(|active| active.manifest.revision.clone());
+        let outcome = if active_revision.as_ref() == Some(&bundle.manifest.revision) {
+            PollOutcome::Unchanged
+        } else {
+            let manifest = self
+                .bundles
+                .activate(bundle)
+                .await
+                .map_err(|err| err.to_string())?;
+            info!(url = self.url, revision = manifest.revision; "Activated the polled bundle");
+            PollOutcome::Activated(manifest)
+        };
+        // Only remembered once activated, so that a rejected bundle is fetched again
+        self.etag = etag;
+        Ok(outcome)
+    }
+
+    fn too_large(&self) -> Box<dyn Error + Send + Sync> {
+        format!("The bundle is larger than {} bytes", self.max_size).into()
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use async_lock::{Mutex as AsyncMutex, MutexGuard as AsyncMutexGuard};
+use chrono::Utc;
+use rocket::request::{FromRequest, Outcome};
+use rocket::tokio::sync::broadcast;
+use rocket::Request;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::{Object, Parameter, ParameterValue};
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub mod stores;
+
+/// Number of past changes kept to resume a feed
+const HISTORY_SIZE: usize = 1000;
+/// Number of changes buffered for a slow subscriber before it is disconnected
+const CHANNEL_CAPACITY: usize = 256;
+
+pub const POLICIES_STORE: &str = "policies";
+pub const DATA_STORE: &str = "data";
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOperation {
+    Create,
+    Update,
+    Delete,
+    /// The whole store was replaced
+    Replace,
+}
+
+/// A change of the policies or data
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct Change {
+    /// Position of the change in the feed, increasing by 1 with every change
+    pub revision: u64,
+    /// Identifier of the running agent, the revisions start over under a new epoch after a restart
+    pub epoch: String,
+    pub timestamp: String,
+    /// `policies` or `data`
+    pub store: String,
+    pub operation: ChangeOperation,
+    /// The id of the changed policy, for the changes of a single policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy_id: Option<String>,
+}
+
+impl Change {
+    /// The id of the event sending the change
+    pub fn event_id(&self) -> String {
+        event_id(&self.epoch, self.revision)
+    }
+}
+
+/// A position in the feed: the revision reached under the epoch of the running agent
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub revision: u64,
+    pub epoch: String,
+}
+
+/// The id of an event of the feed, `<epoch>:<revision>`, for a client to resume from
+pub fn event_id(epoch: &str, revision: u64) -> String {
+    format!("{}:{}", epoch, revision)
+}
+
+/// The `Last-Event-ID` header sent by a client reconnecting to the feed
+pub struct LastEventId(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(LastEventId(
+            request
+                .headers()
+                .get_one("Last-Event-ID")
+                .map(str::to_owned),
+        ))
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for LastEventId {
+    fn from_request_input(
+        gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::Parameter(Parameter {
+            name: "Last-Event-ID".to_owned(),
+            location: "header".to_owned(),
+            description: Some(
+                "The id of the last event received, `<epoch>:<revision>`, to resume from"
+                    .to_owned(),
+            ),
+            required: false,
+            deprecated: false,
+            allow_empty_value: false,
+            value: ParameterValue::Schema {
+                style: None,
+                explode: None,
+                allow_reserved: false,
+                schema: gen.json_schema::<String>(),
+                example: None,
+                examples: None,
+            },
+            extensions: Object::default(),
+        }))
+    }
+}
+
+/// The changes a subscriber missed and the receiver of the next ones
+pub struct Subscription {
+    /// The revision of the feed when subscribing
+    pub revision: u64,
+    /// The current revision, when the changes after the requested one are no longer known
+    /// and the subscriber must reload the whole state
+    pub reset: Option<u64>,
+    pub missed: Vec<Change>,
+    pub receiver: broadcast::Receiver<Change>,
+}
+
+struct History {
+    revision: u64,
+    changes: VecDeque<Change>,
+}
+
+/// The feed of the changes made through the policy and data stores since the agent started
+#[derive(Clone)]
+pub struct ChangeFeed {
+    epoch: Arc<str>,
+    history: Arc<Mutex<History>>,
+    sender: broadcast::Sender<Change>,
+    writes: Arc<AsyncMutex<()>>,
+}
+
+impl ChangeFeed {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            epoch: Uuid::new_v4().to_string().into(),
+            history: Arc::new(Mutex::new(History {
+                revision: 0,
+                changes: VecDeque::with_capacity(HISTORY_SIZE),
+            })),
+            sender,
+            writes: Arc::new(AsyncMutex::new(())),
+        }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.history.lock().unwrap().revision
+    }
+
+    pub fn epoch(&self) -> &str {
+        &self.epoch
+    }
+
+    pub fn position(&self) -> Position {
+        Position {
+            revision: self.revision(),
+            epoch: self.epoch.to_string(),
+        }
+    }
+
+    /// Held by the stores from a write to its publication,
+    /// so the revisions follow the order in which the writes were applied
+    pub async fn lock_writes(&self) -> AsyncMutexGuard<'_, ()> {
+        self.writes.lock().await
+    }
+
+    /// Record a change under the next revision and send it to the subscribers
+    pub fn publish(
+        &self,
+        store: &str,
+        operation: ChangeOperation,
+        policy_id: Option<String>,
+    ) -> Change {
+        let mut history = self.history.lock().unwrap();
+        history.revision += 1;
+        let change = Change {
+            revision: history.revision,
+            epoch: self.epoch.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            store: store.to_owned(),
+            operation,
+            policy_id,
+        };
+        if history.changes.len() == HISTORY_SIZE {
+            history.changes.pop_front();
+        }
+        history.changes.push_back(change.clone());
+        // Sent while holding the lock, so subscribers see the changes in revision order
+        let _ = self.sender.send(change.clone());
+        change
+    }
+
+    /// Subscribe to the changes after the given revision, or to the next ones only
+    pub fn subscribe(&self, since: Option<u64>) -> Subscription {
+        let history = self.history.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let since = match since {
+            Some(since) => since,
+            None => {
+                return Subscription {
+                    revision: history.revision,
+                    reset: None,
+                    missed: Vec::new(),
+                    receiver,
+                }
+            }
+        };
+        let oldest = history
+            .changes
+            .front()
+            .map(|change| change.revision)
+            .unwrap_or(history.revision + 1);
+        if since > history.revision || since + 1 < oldest {
+            return Subscription {
+                revision: history.revision,
+                reset: Some(history.revision),
+                missed: Vec::new(),
+                receiver,
+            };
+        }
+        Subscription {
+            revision: history.revision,
+            reset: None,
+            missed: history
+                .changes
+                .iter()
+                .filter(|change| change.revision > since)
+                .cloned()
+                .collect(),
+            receiver,
+        }
+    }
+
+    /// Subscribe to the changes after a revision of the given epoch,
+    /// which are unknown when the epoch is not the current one
+    pub fn resume(&self, since: u64, epoch: &str) -> Subscription {
+        if epoch == self.epoch() {
+            return self.subscribe(Some(since));
+        }
+        self.reset()
+    }
+
+    /// Subscribe to the changes after the event of the given id,
+    /// which are unknown when the id is not one of the current epoch
+    pub fn resume_after(&self, last_event_id: &str) -> Subscription {
+        let position = last_event_id
+            .rsplit_once(':')
+            .and_then(|(epoch, revision)| Some((epoch, revision.parse().ok()?)));
+        match position {
+            Some((epoch, since)) => self.resume(since, epoch),
+            None => self.reset(),
+        }
+    }
+
+    /// Subscribe to the next changes, the subscriber reloading the whole state first
+    pub fn reset(&self) -> Subscription {
+        let history = self.history.lock().unwrap();
+        Subscription {
+            revision: history.revision,
+            reset: Some(history.revision),
+            missed: Vec::new(),
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,145 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use cedar_policy::PolicySet;
+
+use crate::schemas::data as schemas;
+use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::services::changes::{ChangeFeed, ChangeOperation, DATA_STORE, POLICIES_STORE};
+use crate::services::policies::PolicyCheck;
+use crate::services::{DataStore, PolicyStore};
+
+/// Policy store publishing its successful changes to the change feed,
+/// one write at a time so that the revisions follow the order of the writes
+pub struct ObservedPolicyStore {
+    store: Box<dyn PolicyStore>,
+    feed: ChangeFeed,
+}
+
+impl ObservedPolicyStore {
+    pub fn new(store: Box<dyn PolicyStore>, feed: ChangeFeed) -> Self {
+        Self { store, feed }
+    }
+
+    fn publish(&self, operation: ChangeOperation, policy_id: Option<&str>) {
+        self.feed
+            .publish(POLICIES_STORE, operation, policy_id.map(str::to_owned));
+    }
+}
+
+#[async_trait]
+impl PolicyStore for ObservedPolicyStore {
+    async fn policy_set(&self) -> PolicySet {
+        self.store.policy_set().await
+    }
+
+    async fn revised_policy_set(&self) -> (PolicySet, u64) {
+        self.store.revised_policy_set().await
+    }
+
+    async fn get_policies(&self) -> Vec<Policy> {
+        self.store.get_policies().await
+    }
+
+    async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        self.store.get_policy(id).await
+    }
+
+    async fn create_policy(&self, policy: &Policy) -> Result<Policy, Box<dyn Error>> {
+        let _write = self.feed.lock_writes().await;
+        let created = self.store.create_policy(policy).await?;
+        self.publish(ChangeOperation::Create, Some(&created.id));
+        Ok(created)
+    }
+
+    async fn update_policies(&self, policies: Vec<Policy>) -> Result<Vec<Policy>, Box<dyn Error>> {
+        let _write = self.feed.lock_writes().await;
+        let updated = self.store.update_policies(policies).await?;
+        self.publish(ChangeOperation::Replace, None);
+        Ok(updated)
+    }
+
+    async fn update_policy(
+        &self,
+        id: String,
+        policy: PolicyUpdate,
+    ) -> Result<Policy, Box<dyn Error>> {
+        let _write = self.feed.lock_writes().await;
+        let updated = self.store.update_policy(id, policy).await?;
+        self.publish(ChangeOperation::Update, Some(&updated.id));
+        Ok(updated)
+    }
+
+    async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        let _write = self.feed.lock_writes().await;
+        let deleted = self.store.delete_policy(id).await?;
+        self.publish(ChangeOperation::Delete, Some(&deleted.id));
+        Ok(deleted)
+    }
+
+    async fn update_policy_checked(
+        &self,
+        id: String,
+        policy: PolicyUpdate,
+        check: PolicyCheck<'_>,
+    ) -> Result<Policy, Box<dyn Error>> {
+        let _write = self.feed.lock_writes().await;
+        check(self.store.get_policy(&id).await.ok().as_ref())?;
+        let updated = self.store.update_policy(id, policy).await?;
+        self.publish(ChangeOperation::Update, Some(&updated.id));
+        Ok(updated)
+    }
+
+    async fn delete_policy_checked(
+        &self,
+        id: &str,
+        check: PolicyCheck<'_>,
+    ) -> Result<Policy, Box<dyn Error>> {
+        let _write = self.feed.lock_writes().await;
+        check(self.store.get_policy(id).await.ok().as_ref())?;
+        let deleted = self.store.delete_policy(id).await?;
+        self.publish(ChangeOperation::Delete, Some(&deleted.id));
+        Ok(deleted)
+    }
+}
+
+/// Data store publishing its successful changes to the change feed
+pub struct ObservedDataStore {
+    store: Box<dyn DataStore>,
+    feed: ChangeFeed,
+}
+
+impl ObservedDataStore {
+    pub fn new(store: Box<dyn DataStore>, feed: ChangeFeed) -> Self {
+        Self { store, feed }
+    }
+}
+
+#[async_trait]
+impl DataStore for ObservedDataStore {
+    async fn entities(&self) -> cedar_policy::Entities {
+        self.store.entities().await
+    }
+
+    async fn get_entities(&self) -> schemas::Entities {
+        self.store.get_entities().await
+    }
+
+    async fn delete_entities(&self) -> Result<(), Box<dyn Error>> {
+        let _write = self.feed.lock_writes().await;
+        self.store.delete_entities().await?;
+        self.feed.publish(DATA_STORE, ChangeOperation::Delete, None);
+        Ok(())
+    }
+
+    async fn update_entities(
+        &self,
+        entities: schemas::Entities,
+    ) -> Result<schemas::Entities, Box<dyn Error>> {
+        let _write = self.feed.lock_writes().await;
+        let updated = self.store.update_entities(entities).await?;
+        self.feed
+            .publish(DATA_STORE, ChangeOperation::Replace, None);
+        Ok(updated)
+    }
+}
@@ -0,0 +1,34 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::data as schemas;
+use crate::services::data::memory::{Entities, MemoryDataStore};
+use crate::services::data::DataStore;
+
+/// A change of the entities, as written to the write-ahead log of the file store
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum DataChange {
+    Replace { entities: schemas::Entities },
+    Delete,
+}
+
+impl DataChange {
+    /// Apply a change read back from storage
+    #[cfg_attr(not(feature = "file"), allow(dead_code))]
+    pub async fn apply(self, store: &MemoryDataStore) -> Result<(), Box<dyn Error>> {
+        match self {
+            DataChange::Replace { entities } => store.update_entities(entities).await.map(|_| ()),
+            DataChange::Delete => store.delete_entities().await,
+        }
+    }
+
+    /// Check that the change applies, before it is persisted
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        match self {
+            DataChange::Replace { entities } => Entities::parse(entities.clone()).map(|_| ()),
+            DataChange::Delete => Ok(()),
+        }
+    }
+}
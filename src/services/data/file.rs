@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_lock::{Mutex, MutexGuardArc};
+use async_trait::async_trait;
+use log::error;
+
+use crate::schemas::data as schemas;
+use crate::services::data::change::DataChange;
+use crate::services::data::memory::MemoryDataStore;
+use crate::services::data::DataStore;
+use crate::services::journal::Journal;
+
+const JOURNAL_NAME: &str = "data";
+
+/// Data store kept in memory and persisted to a directory,
+/// as a write-ahead log of the changes compacted into snapshots
+pub struct FileDataStore {
+    entities: MemoryDataStore,
+    journal: Arc<Mutex<Journal>>,
+}
+
+impl FileDataStore {
+    /// Open the store, recovering the entities persisted in the directory
+    pub async fn open(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let (journal, recovered) =
+            Journal::open::<schemas::Entities, DataChange>(dir, JOURNAL_NAME).await?;
+        let entities = MemoryDataStore::new();
+        if let Some(snapshot) = recovered.snapshot {
+            entities.update_entities(snapshot).await?;
+        }
+        for change in recovered.changes {
+            change.apply(&entities).await?;
+        }
+        Ok(Self {
+            entities,
+            journal: Arc::new(Mutex::new(journal)),
+        })
+    }
+
+    /// Check a change and write it to the log, before it is applied in memory
+    /// while still holding the returned journal
+    async fn log(
+        &self,
+        journal: MutexGuardArc<Journal>,
+        change: &DataChange,
+    ) -> Result<MutexGuardArc<Journal>, Box<dyn Error>> {
+        change.check()?;
+        match Journal::append(journal, change).await {
+            Ok(journal) => Ok(journal),
+            Err(err) => {
+                error!(store = "data", error:% = err; "Failed to persist the entities change");
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Compact the log into a snapshot of the entities once it grew too large
+    async fn compact(&self, journal: MutexGuardArc<Journal>) {
+        if journal.needs_compaction() {
+            let state = self.entities.get_entities().await;
+            if let Err(err) = Journal::compact(journal, state).await {
+                error!(store = "data", error:% = err; "Failed to compact the entities");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl DataStore for FileDataStore {
+    async fn entities(&self) -> cedar_policy::Entities {
+        self.entities.entities().await
+    }
+
+    async fn get_entities(&self) -> schemas::Entities {
+        self.entities.get_entities().await
+    }
+
+    async fn delete_entities(&self) -> Result<(), Box<dyn Error>> {
+        let journal = self.journal.lock_arc().await;
+        let journal = self.log(journal, &DataChange::Delete).await?;
+        self.entities.delete_entities().await?;
+        self.compact(journal).await;
+        Ok(())
+    }
+
+    async fn update_entities(
+        &self,
+        entities: schemas::Entities,
+    ) -> Result<schemas::Entities, Box<dyn Error>> {
+        let journal = self.journal.lock_arc().await;
+        let change = DataChange::Replace {
+            entities: entities.clone(),
+        };
+        let journal = self.log(journal, &change).await?;
+        let updated = self.entities.update_entities(entities).await?;
+        self.compact(journal).await;
+        Ok(updated)
+    }
+}
@@ -2,6 +2,7 @@ use std::path::PathBuf;
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
+use std::sync::Arc;
 use log::{error, info};
 
 use rocket::fairing::{Fairing, Info, Kind};
@@ -14,7 +15,7 @@ use crate::schemas::data::Entities;
 
 pub struct InitDataFairing;
 
-pub(crate) async fn init(conf: &config::Config, data_store: &Box<dyn DataStore>) {
+pub(crate) async fn init(conf: &config::Config, data_store: &Arc<dyn DataStore>) {
 
     if conf.data.is_none() {
         return;
@@ -32,7 +33,7 @@ pub(crate) async fn init(conf: &config::Config, data_store: &Box<dyn DataStore>)
 
     match data_store.update_entities(entities).await {
         Ok(entities) => {
-            info!("Successfully updated entities from file {}: {} entities", &file_path.display(), entities.len());
+            info!(path:% = file_path.display(), entity_count = entities.len(); "Successfully updated entities from file {}: {} entities", &file_path.display(), entities.len());
         }
         Err(err) => {
             error!("Failed to update entities: {}", err);
@@ -47,7 +48,7 @@ pub async fn load_entities_from_file(path: PathBuf) -> Result<Entities, Box<dyn
         return Err("File does not exist".into());
     }
     
-    if path.extension().unwrap() != "json" {
+    if path.extension().unwrap_or_default() != "json" {
         return Err("File is not a json file".into());
     }
 
@@ -78,7 +79,7 @@ impl Fairing for InitDataFairing {
             return Ok(rocket);
         }
 
-        init(config.unwrap(), rocket.state::<Box<dyn DataStore>>().unwrap()).await;
+        init(config.unwrap(), rocket.state::<Arc<dyn DataStore>>().unwrap()).await;
 
         Ok(rocket)
     }
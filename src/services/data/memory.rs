@@ -34,6 +34,22 @@ impl Entities {
             1: core_entities,
         }
     }
+
+    /// Parse the entities as they are stored
+    pub fn parse(entities: schemas::Entities) -> Result<Self, Box<dyn Error>> {
+        let core_entities: entities::Entities = match entities.try_into() {
+            Ok(entities) => entities,
+            Err(err) => {
+                return {
+                    error!(error:% = err; "Failed to parse entities");
+                    Err(err.into())
+                }
+            }
+        };
+        let schema_entities: schemas::Entities = core_entities.clone().into();
+        let cedar_entities: cedar_policy::Entities = schema_entities.borrow().try_into()?;
+        Ok(Self::new(cedar_entities, core_entities))
+    }
 }
 
 pub struct MemoryDataStore {
@@ -48,12 +64,12 @@ impl MemoryDataStore {
     }
 
     async fn read(&self) -> RwLockReadGuard<Entities> {
-        debug!("Trying to acquire read lock on entities");
+        debug!(store = "data"; "Trying to acquire read lock on entities");
         self.entities.read().await
     }
 
     async fn write(&self) -> RwLockWriteGuard<Entities> {
-        debug!("Trying to acquire write lock on entities");
+        debug!(store = "data"; "Trying to acquire write lock on entities");
         self.entities.write().await
     }
 }
@@ -66,38 +82,27 @@ impl DataStore for MemoryDataStore {
     }
 
     async fn get_entities(&self) -> schemas::Entities {
-        info!("Getting stored entities");
         let lock = self.read().await;
+        info!(entity_count = lock.1.iter().count(); "Getting stored entities");
         schemas::Entities::from(lock.1.clone())
     }
 
-    async fn delete_entities(&self) {
+    async fn delete_entities(&self) -> Result<(), Box<dyn Error>> {
         info!("Deleting stored entities");
         let mut lock = self.write().await;
         *lock = Entities::empty();
+        Ok(())
     }
 
     async fn update_entities(
         &self,
         entities: schemas::Entities,
     ) -> Result<schemas::Entities, Box<dyn Error>> {
-        info!("Updating stored entities");
+        info!(entity_count = entities.len(); "Updating stored entities");
         let mut lock = self.write().await;
-        let core_entities: entities::Entities = match entities.try_into() {
-            Ok(entities) => entities,
-            Err(err) => {
-                return {
-                    error!("Failed to parse entities");
-                    Err(err.into())
-                }
-            }
-        };
-        let schema_entities: schemas::Entities = core_entities.clone().into();
-        let cedar_entities: cedar_policy::Entities = match schema_entities.borrow().try_into() {
-            Ok(entities) => entities,
-            Err(err) => return Err(err.into()),
-        };
-        *lock = Entities::new(cedar_entities, core_entities);
+        let entities = Entities::parse(entities)?;
+        let schema_entities = schemas::Entities::from(entities.1.clone());
+        *lock = entities;
         Ok(schema_entities)
     }
 }
@@ -4,14 +4,22 @@ use async_trait::async_trait;
 
 use crate::schemas::data as schemas;
 
+#[cfg(any(feature = "file", feature = "redis", feature = "sqlite"))]
+pub(crate) mod change;
+#[cfg(feature = "file")]
+pub mod file;
 pub mod memory;
+#[cfg(feature = "redis")]
+pub mod redis;
 pub mod load_from_file;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 
 #[async_trait]
 pub trait DataStore: Send + Sync {
     async fn entities(&self) -> cedar_policy::Entities;
     async fn get_entities(&self) -> schemas::Entities;
-    async fn delete_entities(&self);
+    async fn delete_entities(&self) -> Result<(), Box<dyn Error>>;
     async fn update_entities(
         &self,
         entities: schemas::Entities,
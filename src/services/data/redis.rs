@@ -0,0 +1,184 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::{debug, error};
+use redis::aio::ConnectionManager;
+use rocket::serde::json::serde_json;
+use rocket::serde::json::Value;
+use thiserror::Error;
+
+use crate::schemas::data as schemas;
+use crate::services::data::change::DataChange;
+use crate::services::data::memory::MemoryDataStore;
+use crate::services::data::DataStore;
+use crate::services::redis::{key, read, HashWrite, Replica, WRITE_ATTEMPTS};
+use crate::services::storage::StorageError;
+
+const ENTITIES_KEY: &str = "entities";
+const CHANGES_CHANNEL: &str = "entities:changes";
+
+/// The uid of an entity as `Type::"id"`, in its explicit `__entity` or implicit form
+fn uid(entity: &Value) -> Option<String> {
+    let uid = entity.get("uid")?;
+    let uid = uid.get("__entity").unwrap_or(uid);
+    Some(format!(
+        "{}::{}",
+        uid.get("type")?.as_str()?,
+        uid.get("id")?
+    ))
+}
+
+/// An entity without a type and id to key it by in the shared hash
+#[derive(Debug, Error)]
+#[error("the entity {0} has no valid type and id")]
+pub struct InvalidUid(String);
+
+/// The fields of the entities in the shared hash, keyed by uid. An entity whose uid cannot
+/// be read is refused rather than left out of the hash read by the other replicas
+pub fn entity_fields(
+    entities: &schemas::Entities,
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let rows = serde_json::to_value(entities)?;
+    rows.as_array()
+        .into_iter()
+        .flatten()
+        .map(|entity| match uid(entity) {
+            Some(uid) => Ok((uid, entity.to_string())),
+            None => Err(InvalidUid(entity.to_string()).into()),
+        })
+        .collect()
+}
+
+/// Reload the entities shared by the replicas and their version into the cache
+async fn reload(
+    connection: &mut ConnectionManager,
+    cache: &MemoryDataStore,
+    version: &AtomicU64,
+) -> Result<(), Box<dyn Error>> {
+    let (stored_version, stored) = read(connection, ENTITIES_KEY).await?;
+    let entities = stored
+        .values()
+        .map(|entity| serde_json::from_str(entity))
+        .collect::<Result<Vec<Value>, _>>()?;
+    cache
+        .update_entities(serde_json::from_value(Value::Array(entities))?)
+        .await?;
+    version.store(stored_version, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Data store shared by the agent replicas through a Redis server, evaluating requests
+/// against a parsed copy of the entities refreshed whenever another replica writes them
+pub struct RedisDataStore {
+    replica: Replica,
+    entities: Arc<MemoryDataStore>,
+    /// The version of the shared entities held by the cache, guarded by the connection lock
+    version: Arc<AtomicU64>,
+}
+
+impl RedisDataStore {
+    /// Connect to the server, load the shared entities and subscribe to their changes
+    pub async fn open(url: &str) -> Result<Self, Box<dyn Error>> {
+        let replica = Replica::connect(url).await?;
+        let entities = Arc::new(MemoryDataStore::new());
+        let version = Arc::new(AtomicU64::new(0));
+        reload(&mut *replica.connection.lock().await, &entities, &version).await?;
+        let connection = replica.connection.clone();
+        let cache = entities.clone();
+        let cached_version = version.clone();
+        replica.subscribe(key(CHANGES_CHANNEL), move || {
+            let connection = connection.clone();
+            let cache = cache.clone();
+            let version = cached_version.clone();
+            async move {
+                let mut connection = connection.lock().await;
+                if let Err(err) = reload(&mut connection, &cache, &version).await {
+                    error!(store = "data", error:% = err.to_string(); "Failed to refresh the entities");
+                }
+            }
+        });
+        Ok(Self {
+            replica,
+            entities,
+            version,
+        })
+    }
+
+    /// Persist a checked change and notify the other replicas, reloading the entities first
+    /// whenever another replica wrote them since they were cached.
+    /// The caller applies the change to the cache once persisted, still holding the connection
+    async fn persist(
+        &self,
+        connection: &mut ConnectionManager,
+        change: &DataChange,
+        write: &HashWrite,
+    ) -> Result<(), Box<dyn Error>> {
+        change.check()?;
+        for _ in 0..WRITE_ATTEMPTS {
+            let expected = self.version.load(Ordering::SeqCst);
+            match self
+                .replica
+                .write(connection, ENTITIES_KEY, CHANGES_CHANNEL, expected, write)
+                .await
+            {
+                Ok(Some(version)) => {
+                    self.version.store(version, Ordering::SeqCst);
+                    return Ok(());
+                }
+                Ok(None) => {
+                    debug!(store = "data"; "The entities were written by another replica, retrying");
+                    reload(connection, &self.entities, &self.version).await?;
+                }
+                Err(err) => {
+                    let err = StorageError::new(&self.replica.url, err);
+                    error!(store = "data", error:% = err; "Failed to persist the entities change");
+                    return Err(err.into());
+                }
+            }
+        }
+        let err = StorageError::new(&self.replica.url, "too many concurrent writes");
+        error!(store = "data", error:% = err; "Failed to persist the entities change");
+        Err(err.into())
+    }
+}
+
+#[async_trait]
+impl DataStore for RedisDataStore {
+    async fn entities(&self) -> cedar_policy::Entities {
+        self.entities.entities().await
+    }
+
+    async fn get_entities(&self) -> schemas::Entities {
+        self.entities.get_entities().await
+    }
+
+    async fn delete_entities(&self) -> Result<(), Box<dyn Error>> {
+        let mut connection = self.replica.connection.lock().await;
+        let write = HashWrite {
+            replace: true,
+            ..Default::default()
+        };
+        self.persist(&mut connection, &DataChange::Delete, &write)
+            .await?;
+        self.entities.delete_entities().await
+    }
+
+    async fn update_entities(
+        &self,
+        entities: schemas::Entities,
+    ) -> Result<schemas::Entities, Box<dyn Error>> {
+        let write = HashWrite {
+            replace: true,
+            set: entity_fields(&entities)?,
+            ..Default::default()
+        };
+        let mut connection = self.replica.connection.lock().await;
+        let change = DataChange::Replace {
+            entities: entities.clone(),
+        };
+        self.persist(&mut connection, &change, &write).await?;
+        self.entities.update_entities(entities).await
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_lock::{Mutex, MutexGuardArc};
+use async_trait::async_trait;
+use log::error;
+use rocket::serde::json::serde_json::{self, json};
+use rocket::serde::json::Value;
+use rusqlite::{params, Connection, Transaction};
+use thiserror::Error;
+
+use crate::schemas::data as schemas;
+use crate::services::data::change::DataChange;
+use crate::services::data::memory::MemoryDataStore;
+use crate::services::data::DataStore;
+use crate::services::sqlite;
+
+type Uid = (String, String);
+
+/// The type and id of an entity reference, in its explicit `__entity` or implicit form
+fn uid(value: &Value) -> Option<Uid> {
+    let uid = value.get("__entity").unwrap_or(value);
+    Some((
+        uid.get("type")?.as_str()?.to_owned(),
+        uid.get("id")?.as_str()?.to_owned(),
+    ))
+}
+
+fn uid_json((entity_type, entity_id): &Uid) -> Value {
+    json!({ "__entity": { "type": entity_type, "id": entity_id } })
+}
+
+/// An entity that could not be written to its rows
+#[derive(Debug, Error)]
+enum RowError {
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("the entity {0} has no valid type and id")]
+    InvalidUid(String),
+}
+
+fn insert(transaction: &Transaction, entity: &Value) -> Result<(), RowError> {
+    let invalid = || RowError::InvalidUid(entity.to_string());
+    let (entity_type, entity_id) = entity.get("uid").and_then(uid).ok_or_else(invalid)?;
+    let attrs = entity.get("attrs").cloned().unwrap_or_else(|| json!({}));
+    transaction.execute(
+        "INSERT INTO entities (entity_type, entity_id, attrs) VALUES (?1, ?2, ?3)",
+        params![entity_type, entity_id, attrs.to_string()],
+    )?;
+    let parents = entity.get("parents").and_then(Value::as_array);
+    for parent in parents.into_iter().flatten() {
+        let (parent_type, parent_id) = uid(parent).ok_or_else(invalid)?;
+        transaction.execute(
+            "INSERT OR IGNORE INTO entity_parents (entity_type, entity_id, parent_type, parent_id)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![entity_type, entity_id, parent_type, parent_id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Read the stored entities back into their JSON format
+fn load(connection: &Connection) -> Result<schemas::Entities, Box<dyn Error + Send + Sync>> {
+    let mut parents: HashMap<Uid, Vec<Value>> = HashMap::new();
+    let mut statement = connection.prepare(
+        "SELECT entity_type, entity_id, parent_type, parent_id FROM entity_parents
+         ORDER BY entity_type, entity_id, parent_type, parent_id",
+    )?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        parents
+            .entry((row.get(0)?, row.get(1)?))
+            .or_default()
+            .push(uid_json(&(row.get(2)?, row.get(3)?)));
+    }
+    let mut entities = Vec::new();
+    let mut statement = connection.prepare(
+        "SELECT entity_type, entity_id, attrs FROM entities ORDER BY entity_type, entity_id",
+    )?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let uid: Uid = (row.get(0)?, row.get(1)?);
+        let attrs: Value = serde_json::from_str(&row.get::<_, String>(2)?)?;
+        entities.push(json!({
+            "uid": uid_json(&uid),
+            "attrs": attrs,
+            "parents": parents.remove(&uid).unwrap_or_default(),
+        }));
+    }
+    Ok(serde_json::from_value(Value::Array(entities))?)
+}
+
+/// Data store persisted to a SQLite database,
+/// evaluating requests against a parsed copy of the entities kept in memory
+pub struct SqliteDataStore {
+    path: PathBuf,
+    connection: Arc<Mutex<Connection>>,
+    entities: MemoryDataStore,
+}
+
+impl SqliteDataStore {
+    /// Open the database, migrating it if needed, and load the stored entities
+    pub async fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let (connection, stored_entities) = sqlite::open(path, load).await?;
+        let entities = MemoryDataStore::new();
+        if !stored_entities.is_empty() {
+            entities.update_entities(stored_entities).await?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            connection: Arc::new(Mutex::new(connection)),
+            entities,
+        })
+    }
+
+    /// Persist a change once checked. The caller applies it to the cache
+    /// once persisted, still holding the returned connection
+    async fn persist(
+        &self,
+        connection: MutexGuardArc<Connection>,
+        change: &DataChange,
+        statements: impl FnOnce(&Transaction) -> Result<(), RowError> + Send + 'static,
+    ) -> Result<MutexGuardArc<Connection>, Box<dyn Error>> {
+        change.check()?;
+        match sqlite::write(connection, &self.path, statements).await {
+            Ok(connection) => Ok(connection),
+            Err(err) => {
+                error!(store = "data", error:% = err; "Failed to persist the entities change");
+                Err(err.into())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl DataStore for SqliteDataStore {
+    async fn entities(&self) -> cedar_policy::Entities {
+        self.entities.entities().await
+    }
+
+    async fn get_entities(&self) -> schemas::Entities {
+        self.entities.get_entities().await
+    }
+
+    async fn delete_entities(&self) -> Result<(), Box<dyn Error>> {
+        let connection = self.connection.lock_arc().await;
+        let _connection = self
+            .persist(connection, &DataChange::Delete, |transaction| {
+                transaction.execute("DELETE FROM entities", [])?;
+                Ok(())
+            })
+            .await?;
+        self.entities.delete_entities().await
+    }
+
+    async fn update_entities(
+        &self,
+        entities: schemas::Entities,
+    ) -> Result<schemas::Entities, Box<dyn Error>> {
+        let connection = self.connection.lock_arc().await;
+        let rows = serde_json::to_value(&entities)?;
+        let change = DataChange::Replace {
+            entities: entities.clone(),
+        };
+        let _connection = self
+            .persist(connection, &change, move |transaction| {
+                transaction.execute("DELETE FROM entities", [])?;
+                rows.as_array()
+                    .into_iter()
+                    .flatten()
+                    .try_for_each(|entity| insert(transaction, entity))
+            })
+            .await?;
+        self.entities.update_entities(entities).await
+    }
+}
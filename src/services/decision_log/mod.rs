@@ -0,0 +1,229 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{error, info, Level, Record};
+use log4rs::append::console::{ConsoleAppender, Target};
+use log4rs::append::Append;
+use log4rs::encode::pattern::PatternEncoder;
+use rocket::serde::json::{serde_json, Value};
+use serde::Serialize;
+
+use crate::common::{rolling_file_appender, DEFAULT_ROLLING_MAX_FILES, DEFAULT_ROLLING_MAX_SIZE};
+use crate::config;
+use crate::schemas::authorization::{AuthorizationAnswer, AuthorizationCall, DecisionRef};
+
+const STDOUT_SINK: &str = "stdout";
+const MASKED_VALUE: &str = "****";
+
+/// Destination of the decision log records
+pub enum DecisionLogSink {
+    Stdout,
+    /// A file rotated once it grows over `max_size` bytes,
+    /// keeping at most `max_files` rotated files next to it
+    File {
+        path: PathBuf,
+        max_size: u64,
+        max_files: u32,
+    },
+}
+
+/// A single authorization decision as written to the decision log
+#[derive(Debug, Serialize)]
+pub struct DecisionRecord {
+    pub request_id: String,
+    pub timestamp: String,
+    pub principal: Option<String>,
+    pub action: Option<String>,
+    pub resource: Option<String>,
+    pub context: Option<Value>,
+    pub decision: Option<DecisionRef>,
+    pub reasons: Vec<String>,
+    pub errors: Vec<String>,
+    /// The revision of the policy set, counted from the start of the agent
+    pub policy_set_revision: u64,
+    /// The epoch of the running agent, the policy set revisions start over under a new one
+    /// after a restart
+    pub epoch: String,
+    /// The revision of the active bundle, the commit SHA when tracking a Git repository
+    pub bundle_revision: Option<String>,
+    pub latency_us: u128,
+}
+
+impl DecisionRecord {
+    pub fn new(request_id: String, epoch: &str, call: &AuthorizationCall) -> Self {
+        Self {
+            request_id,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            principal: call.principal().map(str::to_owned),
+            action: call.action().map(str::to_owned),
+            resource: call.resource().map(str::to_owned),
+            context: call.context().cloned(),
+            decision: None,
+            reasons: Vec::new(),
+            errors: Vec::new(),
+            policy_set_revision: 0,
+            epoch: epoch.to_owned(),
+            bundle_revision: None,
+            latency_us: 0,
+        }
+    }
+
+    pub fn with_answer(
+        mut self,
+        answer: &AuthorizationAnswer,
+        policy_set_revision: u64,
+        latency: Duration,
+    ) -> Self {
+        let mut reasons = Vec::from_iter(answer.reasons().iter().cloned());
+        reasons.sort();
+        let mut errors = Vec::from_iter(answer.errors().iter().cloned());
+        errors.sort();
+        self.decision = Some(*answer.decision());
+        self.reasons = reasons;
+        self.errors = errors;
+        self.policy_set_revision = policy_set_revision;
+        self.latency_us = latency.as_micros();
+        self
+    }
+
+    /// A request rejected as invalid, without a decision and with the reason as its error
+    pub fn with_rejection(
+        mut self,
+        reason: &str,
+        policy_set_revision: u64,
+        latency: Duration,
+    ) -> Self {
+        self.errors = vec![reason.to_owned()];
+        self.policy_set_revision = policy_set_revision;
+        self.latency_us = latency.as_micros();
+        self
+    }
+
+    pub fn with_bundle_revision(mut self, bundle_revision: Option<String>) -> Self {
+        self.bundle_revision = bundle_revision;
+        self
+    }
+
+    /// Replace the values of the given context attributes,
+    /// nested attributes are addressed using a dot separated path
+    pub fn mask(&mut self, paths: &[String]) {
+        if let Some(context) = self.context.as_mut() {
+            for path in paths {
+                mask_path(context, path.split('.').collect::<Vec<&str>>().as_slice());
+            }
+        }
+    }
+}
+
+fn mask_path(value: &mut Value, path: &[&str]) {
+    let Some((key, rest)) = path.split_first() else {
+        return;
+    };
+    if let Some(attribute) = value.as_object_mut().and_then(|o| o.get_mut(*key)) {
+        if rest.is_empty() {
+            *attribute = Value::String(MASKED_VALUE.to_owned());
+        } else {
+            mask_path(attribute, rest);
+        }
+    }
+}
+
+pub struct DecisionLogger {
+    appender: Option<Box<dyn Append>>,
+    sample_rate: f64,
+    mask: Vec<String>,
+}
+
+impl DecisionLogger {
+    pub fn disabled() -> Self {
+        Self {
+            appender: None,
+            sample_rate: 0.0,
+            mask: Vec::new(),
+        }
+    }
+
+    pub fn new(
+        sink: DecisionLogSink,
+        sample_rate: f64,
+        mask: Vec<String>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !(0.0..=1.0).contains(&sample_rate) {
+            return Err(format!("Invalid sample rate {}, expected 0.0-1.0", sample_rate).into());
+        }
+        let encoder = Box::new(PatternEncoder::new("{m}{n}"));
+        let appender: Box<dyn Append> = match sink {
+            DecisionLogSink::Stdout => Box::new(
+                ConsoleAppender::builder()
+                    .target(Target::Stdout)
+                    .encoder(encoder)
+                    .build(),
+            ),
+            DecisionLogSink::File {
+                path,
+                max_size,
+                max_files,
+            } => Box::new(rolling_file_appender(&path, max_size, max_files, encoder)?),
+        };
+        Ok(Self {
+            appender: Some(appender),
+            sample_rate,
+            mask,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.appender.is_some()
+    }
+
+    /// Decide whether the current decision should be recorded
+    pub fn sample(&self) -> bool {
+        self.is_enabled() && (self.sample_rate >= 1.0 || rand::random::<f64>() < self.sample_rate)
+    }
+
+    pub fn log(&self, mut record: DecisionRecord) {
+        let Some(appender) = self.appender.as_ref() else {
+            return;
+        };
+        record.mask(&self.mask);
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(err) => {
+                error!("Failed to serialize decision record: {}", err);
+                return;
+            }
+        };
+        if let Err(err) = appender.append(
+            &Record::builder()
+                .args(format_args!("{}", line))
+                .level(Level::Info)
+                .target("decision_log")
+                .build(),
+        ) {
+            error!("Failed to write decision record: {}", err);
+        }
+    }
+}
+
+/// Open the decision log when configured, failing when it cannot be written
+pub(crate) fn init(conf: &config::Config) -> Result<DecisionLogger, Box<dyn Error>> {
+    let sink = match conf.decision_log.as_deref() {
+        None => return Ok(DecisionLogger::disabled()),
+        Some(STDOUT_SINK) => DecisionLogSink::Stdout,
+        Some(path) => DecisionLogSink::File {
+            path: PathBuf::from(path),
+            max_size: conf
+                .decision_log_max_size
+                .unwrap_or(DEFAULT_ROLLING_MAX_SIZE),
+            max_files: conf
+                .decision_log_max_files
+                .unwrap_or(DEFAULT_ROLLING_MAX_FILES),
+        },
+    };
+    let sample_rate = conf.decision_log_sample_rate.unwrap_or(1.0);
+    let mask = conf.decision_log_mask.clone().unwrap_or_default();
+    let decision_logger = DecisionLogger::new(sink, sample_rate, mask)?;
+    info!("Decision logging enabled");
+    Ok(decision_logger)
+}
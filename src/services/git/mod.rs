@@ -0,0 +1,442 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{debug, info, warn};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::serde::json::{serde_json, Value};
+use rocket::tokio::task::JoinHandle;
+use rocket::{Orbit, Rocket};
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::config;
+use crate::schemas::data::Entities;
+use crate::schemas::policies::Policy;
+use crate::services::bundles::polling::PollOutcome;
+use crate::services::bundles::{Bundle, Bundles, SCHEMA_FILE};
+use crate::services::retry::RetryPolicy;
+
+/// The directory of the repository holding the `.cedar` and `.json` policy files
+pub const POLICIES_DIRECTORY: &str = "policies";
+/// The directory of the repository holding the `.json` entity files
+pub const DATA_DIRECTORY: &str = "data";
+/// The ref of the cache the tracked reference is fetched into, so only its history is kept
+const TRACKED_REF: &str = "refs/cedar-agent/tracked";
+const DEFAULT_REFERENCE: &str = "HEAD";
+const DEFAULT_POLL_INTERVAL: u64 = 60;
+
+/// The synchronization of the policies and data with a Git repository
+#[derive(Serialize, JsonSchema, Clone, Debug, Default)]
+pub struct GitStatus {
+    pub repository: String,
+    pub reference: String,
+    /// The SHA of the commit whose policies and data are active
+    pub commit: Option<String>,
+    /// When the repository was last fetched successfully
+    pub synced_at: Option<String>,
+    /// The error of the last fetch, when it failed
+    pub error: Option<String>,
+}
+
+/// The Git repository the agent tracks, if any
+#[derive(Clone)]
+pub struct GitSource {
+    status: Option<Arc<Mutex<GitStatus>>>,
+    task: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl GitSource {
+    pub fn disabled() -> Self {
+        Self {
+            status: None,
+            task: Arc::default(),
+        }
+    }
+
+    pub fn new(repository: &str, reference: &str) -> Self {
+        Self {
+            status: Some(Arc::new(Mutex::new(GitStatus {
+                repository: repository.to_owned(),
+                reference: reference.to_owned(),
+                ..GitStatus::default()
+            }))),
+            task: Arc::default(),
+        }
+    }
+
+    pub fn status(&self) -> Option<GitStatus> {
+        self.status
+            .as_ref()
+            .map(|status| status.lock().unwrap().clone())
+    }
+
+    fn update(&self, update: impl FnOnce(&mut GitStatus)) {
+        if let Some(status) = self.status.as_ref() {
+            update(&mut status.lock().unwrap());
+        }
+    }
+}
+
+/// Stops the synchronization once the server shut down, removing its cache
+#[rocket::async_trait]
+impl Fairing for GitSource {
+    fn info(&self) -> Info {
+        Info {
+            name: "Git synchronization",
+            kind: Kind::Shutdown,
+        }
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            task.abort();
+            // Dropping the aborted task drops the synchronization and its cache
+            let _ = task.await;
+        }
+    }
+}
+
+/// A reference of a Git repository, fetched into a private bare repository
+#[derive(Clone)]
+struct Checkout {
+    repository: String,
+    reference: String,
+    path: Option<String>,
+    cache: PathBuf,
+}
+
+impl Checkout {
+    /// Fetch the reference into the cache, returning the SHA of its commit
+    fn fetch(&self) -> Result<String, String> {
+        let refspec = format!("{}:{}", self.reference, TRACKED_REF);
+        git(
+            &self.cache,
+            &[
+                "fetch",
+                "--quiet",
+                "--no-tags",
+                "--force",
+                "--",
+                &self.repository,
+                &refspec,
+            ],
+        )?;
+        let commit = git(
+            &self.cache,
+            &[
+                "rev-parse",
+                "--verify",
+                &format!("{}^{{commit}}", TRACKED_REF),
+            ],
+        )?;
+        // The commits no longer reachable from the tracked reference are pruned
+        git(&self.cache, &["gc", "--auto", "--quiet"])?;
+        Ok(String::from_utf8_lossy(&commit).trim().to_owned())
+    }
+
+    /// Read the policies, entities and schema of the commit as a bundle revised by its SHA
+    fn read(&self, commit: &str) -> Result<Bundle, String> {
+        let tree = match self.path.as_ref() {
+            Some(path) => format!("{}:{}", commit, path),
+            None => commit.to_owned(),
+        };
+        let archive = git(&self.cache, &["archive", "--format=tar", &tree])?;
+        let mut files = BTreeMap::new();
+        let mut archive = tar::Archive::new(archive.as_slice());
+        for entry in archive.entries().map_err(|err| err.to_string())? {
+            let mut entry = entry.map_err(|err| err.to_string())?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry
+                .path()
+                .map_err(|err| err.to_string())?
+                .to_string_lossy()
+                .into_owned();
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .map_err(|err| format!("{}: {}", name, err))?;
+            files.insert(name, content);
+        }
+        let bundle = read_files(commit, &files)?;
+        bundle.validate().map_err(|err| err.to_string())?;
+        Ok(bundle)
+    }
+}
+
+/// Activates the policies and data of every new commit of a Git reference as a bundle
+pub struct GitSync {
+    source: GitSource,
+    checkout: Checkout,
+    interval: Duration,
+    retry: RetryPolicy,
+    bundles: Arc<Bundles>,
+}
+
+impl GitSync {
+    /// Track the reference of the repository, a URL or a local path, reading the files
+    /// below `path` when given, or at the root of the repository
+    pub fn new(
+        source: GitSource,
+        repository: &str,
+        reference: &str,
+        path: Option<&str>,
+        interval: Duration,
+        bundles: Arc<Bundles>,
+    ) -> Result<Self, Box<dyn Error>> {
+        check_argument("repository", repository)?;
+        check_argument("reference", reference)?;
+        if reference.contains(':') {
+            return Err(format!("The Git reference {} must not contain ':'", reference).into());
+        }
+        // Git runs in the cache, so a relative local path would be resolved from there
+        let repository = match Path::new(repository).canonicalize() {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => repository.to_owned(),
+        };
+        let cache = std::env::temp_dir().join(format!("cedar-agent-git-{}", uuid::Uuid::new_v4()));
+        git(
+            Path::new("."),
+            &["init", "--quiet", "--bare", "--", &cache.to_string_lossy()],
+        )?;
+        for (key, value) in [("gc.pruneExpire", "now"), ("gc.autoDetach", "false")] {
+            git(&cache, &["config", key, value])?;
+        }
+        Ok(Self {
+            source,
+            checkout: Checkout {
+                repository,
+                reference: reference.to_owned(),
+                path: path
+                    .map(|path| path.trim_matches('/').to_owned())
+                    .filter(|path| !path.is_empty()),
+                cache,
+            },
+            interval,
+            retry: RetryPolicy::within(interval),
+            bundles,
+        })
+    }
+
+    /// Sync the reference every interval, until the source is shut down
+    pub fn spawn(mut self) {
+        let task_slot = self.source.task.clone();
+        let task = rocket::tokio::spawn(async move {
+            let mut failures = 0;
+            loop {
+                let delay = match self.sync().await {
+                    Ok(_) => {
+                        failures = 0;
+                        self.interval
+                    }
+                    Err(err) => {
+                        failures += 1;
+                        let delay = self.retry.backoff(failures);
+                        warn!(
+                            repository = self.checkout.repository, error:% = err,
+                            failures = failures, retry_in:? = delay;
+                            "Failed to sync the Git repository"
+                        );
+                        delay
+                    }
+                };
+                rocket::tokio::time::sleep(delay).await;
+            }
+        });
+        *task_slot.lock().unwrap() = Some(task);
+    }
+
+    /// Fetch the reference, then activate the files of its commit unless it is already active
+    pub async fn sync(&mut self) -> Result<PollOutcome, Box<dyn Error + Send + Sync>> {
+        let result = self.activate_reference().await;
+        if let Err(err) = result.as_ref() {
+            self.source
+                .update(|status| status.error = Some(err.to_string()));
+        }
+        result
+    }
+
+    async fn activate_reference(&self) -> Result<PollOutcome, Box<dyn Error + Send + Sync>> {
+        // Git runs out of the async runtime
+        let checkout = self.checkout.clone();
+        let commit = rocket::tokio::task::spawn_blocking(move || checkout.fetch()).await??;
+        let active_revision = self
+            .bundles
+            .active()
+            .await
+            .as_ref()
+            .map(|active| active.manifest.revision.clone());
+        let outcome = if active_revision.as_ref() == Some(&commit) {
+            debug!(repository = self.checkout.repository, commit = commit; "The Git reference is unchanged");
+            PollOutcome::Unchanged
+        } else {
+            let checkout = self.checkout.clone();
+            let revision = commit.clone();
+            let bundle =
+                rocket::tokio::task::spawn_blocking(move || checkout.read(&revision)).await??;
+            let manifest = self
+                .bundles
+                .activate(bundle)
+                .await
+                .map_err(|err| err.to_string())?;
+            info!(repository = self.checkout.repository, commit = manifest.revision; "Activated the Git commit");
+            PollOutcome::Activated(manifest)
+        };
+        self.source.update(|status| {
+            status.commit = Some(commit);
+            status.synced_at = Some(Utc::now().to_rfc3339());
+            status.error = None;
+        });
+        Ok(outcome)
+    }
+}
+
+impl Drop for GitSync {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.checkout.cache);
+    }
+}
+
+/// A repository or reference starting with `-` would be read by Git as an option
+fn check_argument(name: &str, value: &str) -> Result<(), String> {
+    if value.starts_with('-') {
+        return Err(format!(
+            "The Git {} {} must not start with '-'",
+            name, value
+        ));
+    }
+    Ok(())
+}
+
+/// Run a Git command in the directory, returning its output
+fn git(directory: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .output()
+        .map_err(|err| format!("Failed to run git: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// Build the bundle of the files of a commit, by path: the `.cedar` and `.json` policy files
+/// of the policies directory, the `.json` entity files of the data directory and the schema
+fn read_files(commit: &str, files: &BTreeMap<String, String>) -> Result<Bundle, String> {
+    let mut policies: BTreeMap<String, Policy> = BTreeMap::new();
+    let mut entities = Vec::new();
+    let mut schema = None;
+    for (name, content) in files {
+        let file_error = |err: &dyn std::fmt::Display| format!("{}: {}", name, err);
+        let mut file_policies = Vec::new();
+        if let Some(path) = name.strip_prefix(&format!("{}/", POLICIES_DIRECTORY)) {
+            if let Some(stem) = path.strip_suffix(".cedar") {
+                // The ids are path segments of the policy routes
+                let stem = stem.replace('/', ".");
+                file_policies = parse_cedar(&stem, content).map_err(|err| file_error(&err))?;
+            } else if path.ends_with(".json") {
+                file_policies = serde_json::from_str(content).map_err(|err| file_error(&err))?;
+            }
+        } else if name.starts_with(&format!("{}/", DATA_DIRECTORY)) && name.ends_with(".json") {
+            let file_entities: Vec<Value> =
+                serde_json::from_str(content).map_err(|err| file_error(&err))?;
+            entities.extend(file_entities);
+        } else if name == SCHEMA_FILE {
+            schema = Some(serde_json::from_str(content).map_err(|err| file_error(&err))?);
+        }
+        for policy in file_policies {
+            if policies.contains_key(&policy.id) {
+                return Err(file_error(&format!(
+                    "the policy id {} is not unique",
+                    policy.id
+                )));
+            }
+            policies.insert(policy.id.clone(), policy);
+        }
+    }
+    let entities: Entities =
+        serde_json::from_value(Value::Array(entities)).map_err(|err| err.to_string())?;
+    Ok(Bundle::new(
+        commit.to_owned(),
+        policies.into_values().collect(),
+        entities,
+        schema,
+    ))
+}
+
+/// Parse the policies of a `.cedar` file. A policy is identified by its `@id` annotation,
+/// otherwise by the dotted path of the file without its extension, followed by the position
+/// of the policy in the file when it holds several
+fn parse_cedar(stem: &str, content: &str) -> Result<Vec<Policy>, cedar_policy::ParseErrors> {
+    let policy_set = cedar_policy::PolicySet::from_str(content)?;
+    let mut parsed: Vec<(usize, cedar_policy::Policy)> = policy_set
+        .policies()
+        .map(|policy| {
+            // The parser names the policies of a file policy0, policy1 and so on
+            let position = policy
+                .id()
+                .to_string()
+                .trim_start_matches("policy")
+                .parse()
+                .unwrap_or_default();
+            (position, policy.clone())
+        })
+        .collect();
+    parsed.sort_by_key(|(position, _)| *position);
+    let several = parsed.len() > 1;
+    Ok(parsed
+        .into_iter()
+        .map(|(position, policy)| {
+            let id = match policy.annotation("id") {
+                Some(id) => id.to_owned(),
+                None if several => format!("{}.{}", stem, position),
+                None => stem.to_owned(),
+            };
+            Policy {
+                id,
+                content: policy.to_string(),
+            }
+        })
+        .collect())
+}
+
+/// Start tracking the Git repository when one is configured
+pub(crate) fn init(
+    conf: &config::Config,
+    bundles: Arc<Bundles>,
+) -> Result<GitSource, Box<dyn Error>> {
+    let repository = match conf.git_repository.as_ref() {
+        Some(repository) => repository,
+        None => return Ok(GitSource::disabled()),
+    };
+    let reference = conf.git_ref.as_deref().unwrap_or(DEFAULT_REFERENCE);
+    let interval = conf.git_poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+    let source = GitSource::new(repository, reference);
+    GitSync::new(
+        source.clone(),
+        repository,
+        reference,
+        conf.git_path.as_deref(),
+        Duration::from_secs(interval),
+        bundles,
+    )?
+    .spawn();
+    info!(repository = repository, reference = reference, interval = interval; "Tracking the Git repository");
+    Ok(source)
+}
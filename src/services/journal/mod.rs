@@ -0,0 +1,221 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use async_lock::MutexGuardArc;
+use log::{info, warn};
+use rocket::serde::json::serde_json;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::services::storage::StorageError;
+
+/// Number of entries of the write-ahead log above which it is compacted into a snapshot
+const COMPACTION_ENTRIES: u64 = 100;
+/// Size in bytes of the write-ahead log above which it is compacted into a snapshot
+const COMPACTION_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<S> {
+    sequence: u64,
+    state: S,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<E> {
+    sequence: u64,
+    change: E,
+}
+
+/// The state found on disk when opening a journal
+pub struct Recovered<S, E> {
+    pub snapshot: Option<S>,
+    /// The changes made after the snapshot, to replay in order
+    pub changes: Vec<E>,
+}
+
+/// Write-ahead log of the changes of a store, compacted into a snapshot of the whole state.
+///
+/// `<name>.wal` holds one JSON entry per line, `<name>.snapshot.json` the state as of a
+/// sequence number. Entries up to the sequence of the snapshot are skipped on recovery,
+/// so a crash between writing the snapshot and truncating the log loses nothing.
+/// The files are read and written on blocking threads, out of the async runtime.
+pub struct Journal {
+    wal_path: PathBuf,
+    snapshot_path: PathBuf,
+    wal: File,
+    sequence: u64,
+    entries: u64,
+    size: u64,
+}
+
+impl Journal {
+    /// Open the journal of a store, recovering the state it holds
+    pub async fn open<S, E>(
+        dir: &Path,
+        name: &str,
+    ) -> Result<(Self, Recovered<S, E>), Box<dyn Error>>
+    where
+        S: DeserializeOwned + Send + 'static,
+        E: DeserializeOwned + Send + 'static,
+    {
+        let dir = dir.to_path_buf();
+        let name = name.to_owned();
+        rocket::tokio::task::spawn_blocking(move || Self::recover(&dir, &name))
+            .await?
+            .map_err(|err: Box<dyn Error + Send + Sync>| err as Box<dyn Error>)
+    }
+
+    fn recover<S: DeserializeOwned, E: DeserializeOwned>(
+        dir: &Path,
+        name: &str,
+    ) -> Result<(Self, Recovered<S, E>), Box<dyn Error + Send + Sync>> {
+        fs::create_dir_all(dir)?;
+        let wal_path = dir.join(format!("{}.wal", name));
+        let snapshot_path = dir.join(format!("{}.snapshot.json", name));
+
+        let (mut sequence, snapshot) = match fs::read_to_string(&snapshot_path) {
+            Ok(contents) => {
+                let snapshot: Snapshot<S> = serde_json::from_str(&contents)
+                    .map_err(|err| format!("{}: {}", snapshot_path.display(), err))?;
+                (snapshot.sequence, Some(snapshot.state))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (0, None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut changes = Vec::new();
+        let mut entries = 0;
+        let contents = match fs::read(&wal_path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        // Length of the complete entries, a crash while appending leaves a partial last line
+        let mut size = 0;
+        for (index, line) in contents.split_inclusive(|byte| *byte == b'\n').enumerate() {
+            // Only the last line can be unterminated, its change was never applied
+            if !line.ends_with(b"\n") {
+                warn!(path:% = wal_path.display(); "Discarding the incomplete last entry of the write-ahead log");
+                break;
+            }
+            // A complete entry that cannot be read is a corruption, not an interrupted append
+            let entry: Entry<E> = serde_json::from_slice(line)
+                .map_err(|err| format!("{} line {}: {}", wal_path.display(), index + 1, err))?;
+            size += line.len() as u64;
+            entries += 1;
+            if entry.sequence > sequence {
+                sequence = entry.sequence;
+                changes.push(entry.change);
+            }
+        }
+
+        let wal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&wal_path)?;
+        // Drop the partial last line so that the next entry starts on a line of its own
+        if size < contents.len() as u64 {
+            wal.set_len(size)?;
+            wal.sync_all()?;
+        }
+        info!(path:% = dir.display(), sequence = sequence, change_count = changes.len(); "Recovered the {} store", name);
+        Ok((
+            Self {
+                wal_path,
+                snapshot_path,
+                wal,
+                sequence,
+                entries,
+                size,
+            },
+            Recovered { snapshot, changes },
+        ))
+    }
+
+    fn error(path: &Path, err: impl ToString) -> StorageError {
+        StorageError::new(path.display(), err)
+    }
+
+    /// Append a change and flush it to disk, before it is applied. The journal is handed back
+    /// once written, for the caller to apply the change before the next one is appended
+    pub async fn append<E: Serialize + Sync>(
+        mut journal: MutexGuardArc<Journal>,
+        change: &E,
+    ) -> Result<MutexGuardArc<Journal>, StorageError> {
+        let entry = Entry {
+            sequence: journal.sequence + 1,
+            change,
+        };
+        let mut line =
+            serde_json::to_string(&entry).map_err(|err| Self::error(&journal.wal_path, err))?;
+        line.push('\n');
+        let wal_path = journal.wal_path.clone();
+        rocket::tokio::task::spawn_blocking(move || {
+            journal.write_line(&line)?;
+            Ok(journal)
+        })
+        .await
+        .map_err(|err| Self::error(&wal_path, err))?
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), StorageError> {
+        let written = self
+            .wal
+            .write_all(line.as_bytes())
+            .and_then(|_| self.wal.sync_data());
+        if let Err(err) = written {
+            // Drop what was written of the line, so that the next entry follows the last one
+            if let Err(truncate_err) = self.wal.set_len(self.size) {
+                warn!(path:% = self.wal_path.display(), error:% = truncate_err; "Failed to truncate the write-ahead log");
+            }
+            return Err(Self::error(&self.wal_path, err));
+        }
+        self.sequence += 1;
+        self.entries += 1;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    pub fn needs_compaction(&self) -> bool {
+        self.entries >= COMPACTION_ENTRIES || self.size >= COMPACTION_SIZE
+    }
+
+    /// Atomically replace the snapshot with the current state and truncate the log
+    pub async fn compact<S: Serialize + Send + 'static>(
+        mut journal: MutexGuardArc<Journal>,
+        state: S,
+    ) -> Result<(), StorageError> {
+        let snapshot_path = journal.snapshot_path.clone();
+        rocket::tokio::task::spawn_blocking(move || journal.write_snapshot(&state))
+            .await
+            .map_err(|err| Self::error(&snapshot_path, err))?
+    }
+
+    fn write_snapshot<S: Serialize>(&mut self, state: &S) -> Result<(), StorageError> {
+        let snapshot = Snapshot {
+            sequence: self.sequence,
+            state,
+        };
+        let temporary_path = self.snapshot_path.with_extension("json.tmp");
+        let write_snapshot = || -> Result<(), Box<dyn Error>> {
+            let mut file = File::create(&temporary_path)?;
+            serde_json::to_writer(&mut file, &snapshot)?;
+            file.sync_all()?;
+            fs::rename(&temporary_path, &self.snapshot_path)?;
+            if let Some(dir) = self.snapshot_path.parent() {
+                File::open(dir)?.sync_all()?;
+            }
+            Ok(())
+        };
+        write_snapshot().map_err(|err| Self::error(&self.snapshot_path, err))?;
+        self.wal
+            .set_len(0)
+            .and_then(|_| self.wal.sync_all())
+            .map_err(|err| Self::error(&self.wal_path, err))?;
+        self.entries = 0;
+        self.size = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use rocket::data::{self, FromData, Limits};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::route::{self, Handler};
+use rocket::serde::json::{serde_json, Json};
+use rocket::{Data, Request, Route};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::RequestBody;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use rocket_okapi::request::OpenApiFromData;
+use serde::de::DeserializeOwned;
+
+use crate::authn;
+use crate::common::RequestId;
+use crate::config;
+use crate::errors::response::AgentError;
+
+const LIMITED_PATH_PREFIX: &str = "/v1/";
+/// Number of tracked clients above which the idle buckets are dropped
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+/// The bucket shared by the new clients while the tracked ones are all active
+const OVERFLOW_CLIENT: &str = "overflow";
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Buckets {
+    clients: HashMap<String, Bucket>,
+    swept: Option<Instant>,
+}
+
+/// Token bucket rate limiter, keeping one bucket per client
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Allow `rate` requests per second per client, with bursts of up to `burst` requests
+    pub fn new(rate: u32, burst: u32) -> Self {
+        Self {
+            rate: f64::from(rate),
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(Buckets {
+                clients: HashMap::new(),
+                swept: None,
+            }),
+        }
+    }
+
+    /// Take a token from the bucket of the client,
+    /// or return how long the client has to wait for the next one
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    pub fn check_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut client = client;
+        if buckets.clients.len() >= MAX_TRACKED_CLIENTS && !buckets.clients.contains_key(client) {
+            // Sweeping scans every bucket, so it runs at most once per time to refill a bucket
+            let refill_time = Duration::from_secs_f64(self.burst / self.rate);
+            if buckets
+                .swept
+                .is_none_or(|swept| now.saturating_duration_since(swept) >= refill_time)
+            {
+                buckets
+                    .clients
+                    .retain(|_, bucket| self.refill(bucket, now) < self.burst);
+                buckets.swept = Some(now);
+            }
+            if buckets.clients.len() >= MAX_TRACKED_CLIENTS {
+                client = OVERFLOW_CLIENT;
+            }
+        }
+        let bucket = buckets.clients.entry(client.to_owned()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refill(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.burst)
+    }
+}
+
+/// Maximum body size of the requests of each route family, in bytes
+#[derive(Default)]
+pub struct BodyLimits {
+    pub data: Option<u64>,
+    pub policies: Option<u64>,
+    pub authorization: Option<u64>,
+    pub bundle: Option<u64>,
+}
+
+/// The family of the routes of a path, naming its limit: `data`, `policies`,
+/// `authorization` or `bundle`
+pub fn route_family(path: &str) -> Option<&'static str> {
+    let path = path.strip_prefix(LIMITED_PATH_PREFIX)?;
+    if path.starts_with("data") {
+        Some("data")
+    } else if path.starts_with("policies") {
+        Some("policies")
+    } else if path.starts_with("is_authorized") {
+        Some("authorization")
+    } else if path.starts_with("bundle") || path.starts_with("snapshot") {
+        Some("bundle")
+    } else {
+        None
+    }
+}
+
+impl BodyLimits {
+    pub fn limit_for(&self, path: &str) -> Option<u64> {
+        match route_family(path)? {
+            "data" => self.data,
+            "policies" => self.policies,
+            "authorization" => self.authorization,
+            _ => self.bundle,
+        }
+    }
+}
+
+/// JSON request body, limited while it is read by the `json/<family>` limit of its route
+/// or else by the `json` limit of Rocket
+pub struct LimitedJson<T>(pub T);
+
+impl<T> LimitedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for LimitedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for LimitedJson<T> {
+    type Error = String;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limit = route_family(req.uri().path().as_str())
+            .and_then(|family| req.limits().find(["json", family]))
+            .unwrap_or(Limits::JSON);
+        let body = match data.open(limit).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => {
+                let reason = format!("the body is larger than {} bytes", limit.as_u64());
+                return data::Outcome::Failure((Status::PayloadTooLarge, reason));
+            }
+            Err(err) => return data::Outcome::Failure((Status::BadRequest, err.to_string())),
+        };
+        match serde_json::from_str(&body) {
+            Ok(value) => data::Outcome::Success(LimitedJson(value)),
+            Err(err) if err.classify() == serde_json::error::Category::Data => {
+                data::Outcome::Failure((Status::UnprocessableEntity, err.to_string()))
+            }
+            Err(err) => data::Outcome::Failure((Status::BadRequest, err.to_string())),
+        }
+    }
+}
+
+impl<'r, T: JsonSchema + DeserializeOwned> OpenApiFromData<'r> for LimitedJson<T> {
+    fn request_body(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<RequestBody> {
+        Json::<T>::request_body(gen)
+    }
+}
+
+/// Outcome of the limits checks, cached on the request by the fairing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestLimit {
+    Allowed,
+    TooManyRequests { retry_after: u64 },
+    PayloadTooLarge { size: u64, limit: u64 },
+}
+
+/// Check the rate limit of the client and the declared body size of every API request,
+/// the bodies without a declared size being limited by `LimitedJson` as they are read
+pub struct LimitsFairing {
+    rate_limiter: Option<RateLimiter>,
+    body_limits: BodyLimits,
+}
+
+impl LimitsFairing {
+    pub fn new(rate_limiter: Option<RateLimiter>, body_limits: BodyLimits) -> Self {
+        Self {
+            rate_limiter,
+            body_limits,
+        }
+    }
+
+    /// Requests are limited per caller when it is known without verifying a slow hash or
+    /// signature, per client IP otherwise, so that made-up keys neither get a bucket of their
+    /// own nor are verified once the IP is limited
+    async fn client(req: &Request<'_>) -> String {
+        match authn::limited_caller(req).await {
+            Some(caller) => format!("caller:{}", caller),
+            None => match req.client_ip() {
+                Some(ip) => format!("ip:{}", ip),
+                None => "ip:unknown".to_owned(),
+            },
+        }
+    }
+
+    async fn check(&self, req: &Request<'_>) -> RequestLimit {
+        let path = req.uri().path();
+        if !path.starts_with(LIMITED_PATH_PREFIX) {
+            return RequestLimit::Allowed;
+        }
+        if let Some(limit) = self.body_limits.limit_for(path.as_str()) {
+            let size = req
+                .headers()
+                .get_one("Content-Length")
+                .and_then(|size| size.parse::<u64>().ok());
+            if let Some(size) = size.filter(|size| *size > limit) {
+                return RequestLimit::PayloadTooLarge { size, limit };
+            }
+        }
+        if let Some(rate_limiter) = self.rate_limiter.as_ref() {
+            if let Err(wait) = rate_limiter.check(&Self::client(req).await) {
+                return RequestLimit::TooManyRequests {
+                    retry_after: wait.as_secs_f64().ceil() as u64,
+                };
+            }
+        }
+        RequestLimit::Allowed
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for LimitsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Limits",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let limit = self.check(req).await;
+        if limit != RequestLimit::Allowed {
+            // Fairings run outside the handler setting the request id of the log lines
+            debug!(request_id = RequestId::of(req).as_str(), path:% = req.uri().path(); "Rejected request: {:?}", limit);
+        }
+        req.local_cache(|| limit);
+    }
+}
+
+/// Route handler rejecting the requests over the limits before running the wrapped handler,
+/// as fairings cannot respond to requests themselves
+#[derive(Clone)]
+struct LimitsHandler(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for LimitsHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        match *req.local_cache(|| RequestLimit::Allowed) {
+            RequestLimit::Allowed => self.0.handle(req, data).await,
+            RequestLimit::TooManyRequests { retry_after } => {
+                route::Outcome::from(req, AgentError::TooManyRequests { retry_after })
+            }
+            RequestLimit::PayloadTooLarge { size, limit } => {
+                route::Outcome::from(req, AgentError::PayloadTooLarge { size, limit })
+            }
+        }
+    }
+}
+
+pub(crate) fn with_limits(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(LimitsHandler(route.handler));
+            route
+        })
+        .collect()
+}
+
+pub(crate) fn init(conf: &config::Config) -> LimitsFairing {
+    let rate_limiter = conf
+        .rate_limit
+        .map(|rate| RateLimiter::new(rate, conf.rate_limit_burst.unwrap_or(rate)));
+    LimitsFairing::new(
+        rate_limiter,
+        BodyLimits {
+            data: conf.data_body_limit,
+            policies: conf.policies_body_limit,
+            authorization: conf.authorization_body_limit,
+            bundle: conf.bundle_body_limit,
+        },
+    )
+}
@@ -1,4 +1,24 @@
+pub mod admin;
+pub mod audit;
+pub mod bundles;
+pub mod changes;
 pub mod data;
+pub mod decision_log;
+pub mod git;
+#[cfg(feature = "file")]
+pub mod journal;
+pub mod limits;
 pub mod policies;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod registry;
+pub mod replication;
+pub mod retry;
+pub mod snapshots;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod storage;
+pub mod telemetry;
+pub mod webhooks;
 pub use data::DataStore;
 pub use policies::PolicyStore;
@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use cedar_policy::PolicySetError;
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::services::policies::errors::PolicyStoreError;
+use crate::services::policies::memory::MemoryPolicyStore;
+use crate::services::policies::PolicyStore;
+
+/// A change of the policies, as written to the write-ahead log of the file store
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum PolicyChange {
+    Create { policy: Policy },
+    Replace { policies: Vec<Policy> },
+    Update { id: String, content: String },
+    Delete { id: String },
+}
+
+impl PolicyChange {
+    /// Apply a change read back from storage
+    #[cfg_attr(not(feature = "file"), allow(dead_code))]
+    pub async fn apply(self, store: &MemoryPolicyStore) -> Result<(), Box<dyn Error>> {
+        match self {
+            PolicyChange::Create { policy } => store.create_policy(&policy).await.map(|_| ()),
+            PolicyChange::Replace { policies } => store.update_policies(policies).await.map(|_| ()),
+            PolicyChange::Update { id, content } => store
+                .update_policy(id, PolicyUpdate { content })
+                .await
+                .map(|_| ()),
+            PolicyChange::Delete { id } => store.delete_policy(&id).await.map(|_| ()),
+        }
+    }
+
+    /// Check that the change applies to the stored policies, before it is persisted
+    pub async fn check(&self, store: &MemoryPolicyStore) -> Result<(), Box<dyn Error>> {
+        let parse = |policy: &Policy| -> Result<(), Box<dyn Error>> {
+            TryInto::<cedar_policy::Policy>::try_into(policy)?;
+            Ok(())
+        };
+        match self {
+            PolicyChange::Create { policy } => match store.get_policy(&policy.id).await {
+                Ok(_) => {
+                    Err(PolicyStoreError::PolicySetError(PolicySetError::AlreadyDefined).into())
+                }
+                Err(_) => parse(policy),
+            },
+            PolicyChange::Replace { policies } => {
+                let mut ids = HashSet::new();
+                for policy in policies {
+                    if !ids.insert(policy.id.as_str()) {
+                        return Err(PolicySetError::AlreadyDefined.into());
+                    }
+                    parse(policy)?;
+                }
+                Ok(())
+            }
+            PolicyChange::Update { id, content } => parse(&Policy {
+                id: id.clone(),
+                content: content.clone(),
+            }),
+            PolicyChange::Delete { id } => store.get_policy(id).await.map(|_| ()),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_lock::{Mutex, MutexGuardArc};
+use async_trait::async_trait;
+use cedar_policy::PolicySet;
+use log::error;
+
+use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::services::journal::Journal;
+use crate::services::policies::change::PolicyChange;
+use crate::services::policies::memory::MemoryPolicyStore;
+use crate::services::policies::PolicyStore;
+
+const JOURNAL_NAME: &str = "policies";
+
+/// Policy store kept in memory and persisted to a directory,
+/// as a write-ahead log of the changes compacted into snapshots
+pub struct FilePolicyStore {
+    policies: MemoryPolicyStore,
+    journal: Arc<Mutex<Journal>>,
+}
+
+impl FilePolicyStore {
+    /// Open the store, recovering the policies persisted in the directory
+    pub async fn open(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let (journal, recovered) =
+            Journal::open::<Vec<Policy>, PolicyChange>(dir, JOURNAL_NAME).await?;
+        let policies = MemoryPolicyStore::new();
+        if let Some(snapshot) = recovered.snapshot {
+            policies.update_policies(snapshot).await?;
+        }
+        for change in recovered.changes {
+            change.apply(&policies).await?;
+        }
+        Ok(Self {
+            policies,
+            journal: Arc::new(Mutex::new(journal)),
+        })
+    }
+
+    /// Check a change and write it to the log, before it is applied in memory
+    /// while still holding the returned journal
+    async fn log(
+        &self,
+        journal: MutexGuardArc<Journal>,
+        change: &PolicyChange,
+    ) -> Result<MutexGuardArc<Journal>, Box<dyn Error>> {
+        change.check(&self.policies).await?;
+        match Journal::append(journal, change).await {
+            Ok(journal) => Ok(journal),
+            Err(err) => {
+                error!(store = "policies", error:% = err; "Failed to persist the policies change");
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Compact the log into a snapshot of the policies once it grew too large
+    async fn compact(&self, journal: MutexGuardArc<Journal>) {
+        if journal.needs_compaction() {
+            let state = self.policies.get_policies().await;
+            if let Err(err) = Journal::compact(journal, state).await {
+                error!(store = "policies", error:% = err; "Failed to compact the policies");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PolicyStore for FilePolicyStore {
+    async fn policy_set(&self) -> PolicySet {
+        self.policies.policy_set().await
+    }
+
+    async fn revised_policy_set(&self) -> (PolicySet, u64) {
+        self.policies.revised_policy_set().await
+    }
+
+    async fn get_policies(&self) -> Vec<Policy> {
+        self.policies.get_policies().await
+    }
+
+    async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        self.policies.get_policy(id).await
+    }
+
+    async fn create_policy(&self, policy: &Policy) -> Result<Policy, Box<dyn Error>> {
+        let journal = self.journal.lock_arc().await;
+        let change = PolicyChange::Create {
+            policy: policy.clone(),
+        };
+        let journal = self.log(journal, &change).await?;
+        let created = self.policies.create_policy(policy).await?;
+        self.compact(journal).await;
+        Ok(created)
+    }
+
+    async fn update_policies(&self, policies: Vec<Policy>) -> Result<Vec<Policy>, Box<dyn Error>> {
+        let journal = self.journal.lock_arc().await;
+        let change = PolicyChange::Replace {
+            policies: policies.clone(),
+        };
+        let journal = self.log(journal, &change).await?;
+        let updated = self.policies.update_policies(policies).await?;
+        self.compact(journal).await;
+        Ok(updated)
+    }
+
+    async fn update_policy(
+        &self,
+        id: String,
+        policy: PolicyUpdate,
+    ) -> Result<Policy, Box<dyn Error>> {
+        let journal = self.journal.lock_arc().await;
+        let change = PolicyChange::Update {
+            id: id.clone(),
+            content: policy.content.clone(),
+        };
+        let journal = self.log(journal, &change).await?;
+        let updated = self.policies.update_policy(id, policy).await?;
+        self.compact(journal).await;
+        Ok(updated)
+    }
+
+    async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        let journal = self.journal.lock_arc().await;
+        let change = PolicyChange::Delete { id: id.to_owned() };
+        let journal = self.log(journal, &change).await?;
+        let deleted = self.policies.delete_policy(id).await?;
+        self.compact(journal).await;
+        Ok(deleted)
+    }
+}
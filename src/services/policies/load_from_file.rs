@@ -2,6 +2,7 @@ use std::path::PathBuf;
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
+use std::sync::Arc;
 use log::{error, info};
 
 use rocket::fairing::{Fairing, Info, Kind};
@@ -15,7 +16,7 @@ use crate::config;
 
 pub struct InitPoliciesFairing;
 
-pub(crate) async fn init(conf: &config::Config, policy_store: &Box<dyn PolicyStore>) {
+pub(crate) async fn init(conf: &config::Config, policy_store: &Arc<dyn PolicyStore>) {
 
     if conf.policies.is_none() {
         return;
@@ -33,7 +34,7 @@ pub(crate) async fn init(conf: &config::Config, policy_store: &Box<dyn PolicySto
 
     match policy_store.update_policies(policies.into_inner()).await {
         Ok(policies) => {
-            info!("Successfully updated policies from file {}: {} policies", &file_path.display(), policies.len());
+            info!(path:% = file_path.display(), policy_count = policies.len(); "Successfully updated policies from file {}: {} policies", &file_path.display(), policies.len());
         }
         Err(err) => {
             error!("Failed to update policies: {}", err);
@@ -48,7 +49,7 @@ pub async fn load_policies_from_file(path: PathBuf) -> Result<Json<Vec<Policy>>,
         return Err("File does not exist".into());
     }
 
-    if path.extension().unwrap() != "json" {
+    if path.extension().unwrap_or_default() != "json" {
         return Err("File is not a json file".into());
     }
 
@@ -79,7 +80,7 @@ impl Fairing for InitPoliciesFairing {
             return Ok(rocket);
         }
 
-        init(config.unwrap(), rocket.state::<Box<dyn PolicyStore>>().unwrap()).await;
+        init(config.unwrap(), rocket.state::<Arc<dyn PolicyStore>>().unwrap()).await;
 
         Ok(rocket)
     }
@@ -12,13 +12,14 @@ use crate::schemas::policies::{Policy, PolicyUpdate};
 use crate::services::policies::errors::PolicyStoreError;
 use crate::services::policies::PolicyStore;
 
-pub struct Policies(HashMap<String, cedar_policy::Policy>, PolicySet);
+pub struct Policies(HashMap<String, cedar_policy::Policy>, PolicySet, u64);
 
 impl Policies {
     fn new() -> Self {
         Self {
             0: HashMap::new(),
             1: PolicySet::new(),
+            2: 0,
         }
     }
 
@@ -37,6 +38,7 @@ impl Policies {
             policy_set.add(policy.clone()).unwrap();
         }
         self.1 = policy_set;
+        self.2 += 1;
     }
 }
 
@@ -52,12 +54,12 @@ impl MemoryPolicyStore {
     }
 
     async fn read(&self) -> RwLockReadGuard<Policies> {
-        debug!("Trying to acquire read lock on policies");
+        debug!(store = "policies"; "Trying to acquire read lock on policies");
         self.policies.read().await
     }
 
     async fn write(&self) -> RwLockWriteGuard<Policies> {
-        debug!("Trying to acquire write lock on policies");
+        debug!(store = "policies"; "Trying to acquire write lock on policies");
         self.policies.write().await
     }
 }
@@ -69,14 +71,19 @@ impl PolicyStore for MemoryPolicyStore {
         lock.policy_set()
     }
 
+    async fn revised_policy_set(&self) -> (PolicySet, u64) {
+        let lock = self.read().await;
+        (lock.policy_set(), lock.2)
+    }
+
     async fn get_policies(&self) -> Vec<Policy> {
-        info!("Getting policies");
         let lock = self.read().await;
+        info!(policy_count = lock.0.len(); "Getting policies");
         Vec::from_iter(lock.0.values().cloned().map(|p| Policy::from(p)))
     }
 
     async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
-        info!("Getting policy {}", id);
+        info!(policy_id = id; "Getting policy {}", id);
         let lock = self.read().await;
         let policy = lock.0.get(id);
         match policy {
@@ -86,7 +93,7 @@ impl PolicyStore for MemoryPolicyStore {
     }
 
     async fn create_policy(&self, policy: &Policy) -> Result<Policy, Box<dyn Error>> {
-        info!("Creating policy {}", policy.id);
+        info!(policy_id = policy.id.as_str(); "Creating policy {}", policy.id);
         let mut lock = self.write().await;
         let stored_policy = lock.0.get(&policy.id);
         match stored_policy {
@@ -107,7 +114,7 @@ impl PolicyStore for MemoryPolicyStore {
     }
 
     async fn update_policies(&self, policies: Vec<Policy>) -> Result<Vec<Policy>, Box<dyn Error>> {
-        info!("Updating policies");
+        info!(policy_count = policies.len(); "Updating policies");
         let mut lock = self.write().await;
         let mut new_policies: HashMap<String, cedar_policy::Policy> = HashMap::new();
         for policy in policies {
@@ -134,7 +141,7 @@ impl PolicyStore for MemoryPolicyStore {
         id: String,
         policy_update: PolicyUpdate,
     ) -> Result<Policy, Box<dyn Error>> {
-        info!("Updating policy {}", id);
+        info!(policy_id = id.as_str(); "Updating policy {}", id);
         let mut lock = self.write().await;
         let policy = Policy::from_policy_update(id.clone(), policy_update);
         let policy: cedar_policy::Policy = match policy.borrow().try_into() {
@@ -150,7 +157,7 @@ impl PolicyStore for MemoryPolicyStore {
     }
 
     async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
-        info!("Deleting policy {}", id);
+        info!(policy_id = id; "Deleting policy {}", id);
         let mut lock = self.write().await;
         match lock.0.remove(id) {
             Some(policy) => {
@@ -5,13 +5,27 @@ use cedar_policy::PolicySet;
 
 use crate::schemas::policies::{Policy, PolicyUpdate};
 
+#[cfg(any(feature = "file", feature = "redis", feature = "sqlite"))]
+pub(crate) mod change;
 pub(crate) mod errors;
+#[cfg(feature = "file")]
+pub mod file;
 pub mod memory;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 pub mod load_from_file;
 
+/// Check of the stored version of a policy, `None` when missing, failing to refuse its change
+pub type PolicyCheck<'a> =
+    &'a (dyn Fn(Option<&Policy>) -> Result<(), Box<dyn Error>> + Send + Sync);
+
 #[async_trait]
 pub trait PolicyStore: Send + Sync {
     async fn policy_set(&self) -> PolicySet;
+    /// The policy set with its revision, incremented on every change, read at once
+    async fn revised_policy_set(&self) -> (PolicySet, u64);
     async fn get_policies(&self) -> Vec<Policy>;
     async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>>;
     async fn create_policy(&self, policy: &Policy) -> Result<Policy, Box<dyn Error>>;
@@ -22,4 +36,27 @@ pub trait PolicyStore: Send + Sync {
         policy: PolicyUpdate,
     ) -> Result<Policy, Box<dyn Error>>;
     async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>>;
+
+    /// Update the policy once the check accepts its stored version.
+    /// The stores serializing their writes run both under their write lock
+    async fn update_policy_checked(
+        &self,
+        id: String,
+        policy: PolicyUpdate,
+        check: PolicyCheck<'_>,
+    ) -> Result<Policy, Box<dyn Error>> {
+        check(self.get_policy(&id).await.ok().as_ref())?;
+        self.update_policy(id, policy).await
+    }
+
+    /// Delete the policy once the check accepts its stored version.
+    /// The stores serializing their writes run both under their write lock
+    async fn delete_policy_checked(
+        &self,
+        id: &str,
+        check: PolicyCheck<'_>,
+    ) -> Result<Policy, Box<dyn Error>> {
+        check(self.get_policy(id).await.ok().as_ref())?;
+        self.delete_policy(id).await
+    }
 }
@@ -0,0 +1,195 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use cedar_policy::PolicySet;
+use log::{debug, error};
+use redis::aio::ConnectionManager;
+
+use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::services::policies::change::PolicyChange;
+use crate::services::policies::memory::MemoryPolicyStore;
+use crate::services::policies::PolicyStore;
+use crate::services::redis::{key, read, version, HashWrite, Replica, WRITE_ATTEMPTS};
+use crate::services::storage::StorageError;
+
+const POLICIES_KEY: &str = "policies";
+const CHANGES_CHANNEL: &str = "policies:changes";
+
+/// Reload the policies shared by the replicas, ordered by id, and their version into the cache
+async fn reload(
+    connection: &mut ConnectionManager,
+    cache: &MemoryPolicyStore,
+    version: &AtomicU64,
+) -> Result<(), Box<dyn Error>> {
+    let (stored_version, stored) = read(connection, POLICIES_KEY).await?;
+    let mut policies: Vec<Policy> = stored
+        .into_iter()
+        .map(|(id, content)| Policy { id, content })
+        .collect();
+    policies.sort_by(|first, second| first.id.cmp(&second.id));
+    cache.update_policies(policies).await?;
+    version.store(stored_version, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Policy store shared by the agent replicas through a Redis server, evaluating requests
+/// against a compiled copy of the policies refreshed whenever another replica writes them
+pub struct RedisPolicyStore {
+    replica: Replica,
+    policies: Arc<MemoryPolicyStore>,
+    /// The version of the shared policies held by the cache, guarded by the connection lock
+    version: Arc<AtomicU64>,
+}
+
+impl RedisPolicyStore {
+    /// Connect to the server, load the shared policies and subscribe to their changes
+    pub async fn open(url: &str) -> Result<Self, Box<dyn Error>> {
+        let replica = Replica::connect(url).await?;
+        let policies = Arc::new(MemoryPolicyStore::new());
+        let version = Arc::new(AtomicU64::new(0));
+        reload(&mut *replica.connection.lock().await, &policies, &version).await?;
+        let connection = replica.connection.clone();
+        let cache = policies.clone();
+        let cached_version = version.clone();
+        replica.subscribe(key(CHANGES_CHANNEL), move || {
+            let connection = connection.clone();
+            let cache = cache.clone();
+            let version = cached_version.clone();
+            async move {
+                let mut connection = connection.lock().await;
+                if let Err(err) = reload(&mut connection, &cache, &version).await {
+                    error!(store = "policies", error:% = err.to_string(); "Failed to refresh the policies");
+                }
+            }
+        });
+        Ok(Self {
+            replica,
+            policies,
+            version,
+        })
+    }
+
+    /// Persist a change checked against the cached policies and notify the other replicas,
+    /// reloading the policies and checking it again whenever another replica wrote them since.
+    /// The caller applies the change to the cache once persisted, still holding the connection
+    async fn persist(
+        &self,
+        connection: &mut ConnectionManager,
+        change: &PolicyChange,
+        write: &HashWrite,
+    ) -> Result<(), Box<dyn Error>> {
+        for _ in 0..WRITE_ATTEMPTS {
+            let expected = self.version.load(Ordering::SeqCst);
+            if change.check(&self.policies).await.is_err() {
+                // The change may only be refused by a stale cache
+                if version(connection, POLICIES_KEY).await? != expected {
+                    reload(connection, &self.policies, &self.version).await?;
+                    continue;
+                }
+                change.check(&self.policies).await?;
+            }
+            match self
+                .replica
+                .write(connection, POLICIES_KEY, CHANGES_CHANNEL, expected, write)
+                .await
+            {
+                Ok(Some(version)) => {
+                    self.version.store(version, Ordering::SeqCst);
+                    return Ok(());
+                }
+                Ok(None) => {
+                    debug!(store = "policies"; "The policies were written by another replica, retrying");
+                    reload(connection, &self.policies, &self.version).await?;
+                }
+                Err(err) => {
+                    let err = StorageError::new(&self.replica.url, err);
+                    error!(store = "policies", error:% = err; "Failed to persist the policies change");
+                    return Err(err.into());
+                }
+            }
+        }
+        let err = StorageError::new(&self.replica.url, "too many concurrent writes");
+        error!(store = "policies", error:% = err; "Failed to persist the policies change");
+        Err(err.into())
+    }
+}
+
+#[async_trait]
+impl PolicyStore for RedisPolicyStore {
+    async fn policy_set(&self) -> PolicySet {
+        self.policies.policy_set().await
+    }
+
+    async fn revised_policy_set(&self) -> (PolicySet, u64) {
+        self.policies.revised_policy_set().await
+    }
+
+    async fn get_policies(&self) -> Vec<Policy> {
+        self.policies.get_policies().await
+    }
+
+    async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        self.policies.get_policy(id).await
+    }
+
+    async fn create_policy(&self, policy: &Policy) -> Result<Policy, Box<dyn Error>> {
+        let mut connection = self.replica.connection.lock().await;
+        let change = PolicyChange::Create {
+            policy: policy.clone(),
+        };
+        let write = HashWrite {
+            set: vec![(policy.id.clone(), policy.content.clone())],
+            ..Default::default()
+        };
+        self.persist(&mut connection, &change, &write).await?;
+        self.policies.create_policy(policy).await
+    }
+
+    async fn update_policies(&self, policies: Vec<Policy>) -> Result<Vec<Policy>, Box<dyn Error>> {
+        let mut connection = self.replica.connection.lock().await;
+        let write = HashWrite {
+            replace: true,
+            set: policies
+                .iter()
+                .map(|policy| (policy.id.clone(), policy.content.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        let change = PolicyChange::Replace {
+            policies: policies.clone(),
+        };
+        self.persist(&mut connection, &change, &write).await?;
+        self.policies.update_policies(policies).await
+    }
+
+    async fn update_policy(
+        &self,
+        id: String,
+        policy: PolicyUpdate,
+    ) -> Result<Policy, Box<dyn Error>> {
+        let mut connection = self.replica.connection.lock().await;
+        let change = PolicyChange::Update {
+            id: id.clone(),
+            content: policy.content.clone(),
+        };
+        let write = HashWrite {
+            set: vec![(id.clone(), policy.content.clone())],
+            ..Default::default()
+        };
+        self.persist(&mut connection, &change, &write).await?;
+        self.policies.update_policy(id, policy).await
+    }
+
+    async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        let mut connection = self.replica.connection.lock().await;
+        let change = PolicyChange::Delete { id: id.to_string() };
+        let write = HashWrite {
+            deleted: vec![id.to_string()],
+            ..Default::default()
+        };
+        self.persist(&mut connection, &change, &write).await?;
+        self.policies.delete_policy(id).await
+    }
+}
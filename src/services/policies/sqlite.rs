@@ -0,0 +1,161 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_lock::{Mutex, MutexGuardArc};
+use async_trait::async_trait;
+use cedar_policy::PolicySet;
+use log::error;
+use rusqlite::{params, Connection, Transaction};
+
+use crate::schemas::policies::{Policy, PolicyUpdate};
+use crate::services::policies::change::PolicyChange;
+use crate::services::policies::memory::MemoryPolicyStore;
+use crate::services::policies::PolicyStore;
+use crate::services::sqlite;
+
+fn insert(transaction: &Transaction, policy: &Policy) -> rusqlite::Result<()> {
+    transaction.execute(
+        "INSERT INTO policies (id, content) VALUES (?1, ?2)
+         ON CONFLICT (id) DO UPDATE SET content = excluded.content",
+        params![policy.id, policy.content],
+    )?;
+    Ok(())
+}
+
+/// Policy store persisted to a SQLite database,
+/// evaluating requests against a compiled copy of the policies kept in memory
+pub struct SqlitePolicyStore {
+    path: PathBuf,
+    connection: Arc<Mutex<Connection>>,
+    policies: MemoryPolicyStore,
+}
+
+impl SqlitePolicyStore {
+    /// Open the database, migrating it if needed, and load the stored policies
+    pub async fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let (connection, stored_policies) = sqlite::open(path, |connection| {
+            let mut statement =
+                connection.prepare("SELECT id, content FROM policies ORDER BY id")?;
+            let rows = statement.query_map([], |row| {
+                Ok(Policy {
+                    id: row.get(0)?,
+                    content: row.get(1)?,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<Policy>>>()?)
+        })
+        .await?;
+        let policies = MemoryPolicyStore::new();
+        if !stored_policies.is_empty() {
+            policies.update_policies(stored_policies).await?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            connection: Arc::new(Mutex::new(connection)),
+            policies,
+        })
+    }
+
+    /// Persist a change once checked against the cached policies. The caller applies it
+    /// to the cache once persisted, still holding the returned connection
+    async fn persist(
+        &self,
+        connection: MutexGuardArc<Connection>,
+        change: &PolicyChange,
+        statements: impl FnOnce(&Transaction) -> rusqlite::Result<()> + Send + 'static,
+    ) -> Result<MutexGuardArc<Connection>, Box<dyn Error>> {
+        change.check(&self.policies).await?;
+        match sqlite::write(connection, &self.path, statements).await {
+            Ok(connection) => Ok(connection),
+            Err(err) => {
+                error!(store = "policies", error:% = err; "Failed to persist the policies change");
+                Err(err.into())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PolicyStore for SqlitePolicyStore {
+    async fn policy_set(&self) -> PolicySet {
+        self.policies.policy_set().await
+    }
+
+    async fn revised_policy_set(&self) -> (PolicySet, u64) {
+        self.policies.revised_policy_set().await
+    }
+
+    async fn get_policies(&self) -> Vec<Policy> {
+        self.policies.get_policies().await
+    }
+
+    async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        self.policies.get_policy(id).await
+    }
+
+    async fn create_policy(&self, policy: &Policy) -> Result<Policy, Box<dyn Error>> {
+        let connection = self.connection.lock_arc().await;
+        let change = PolicyChange::Create {
+            policy: policy.clone(),
+        };
+        let created = policy.clone();
+        let _connection = self
+            .persist(connection, &change, move |transaction| {
+                insert(transaction, &created)
+            })
+            .await?;
+        self.policies.create_policy(policy).await
+    }
+
+    async fn update_policies(&self, policies: Vec<Policy>) -> Result<Vec<Policy>, Box<dyn Error>> {
+        let connection = self.connection.lock_arc().await;
+        let change = PolicyChange::Replace {
+            policies: policies.clone(),
+        };
+        let rows = policies.clone();
+        let _connection = self
+            .persist(connection, &change, move |transaction| {
+                transaction.execute("DELETE FROM policies", [])?;
+                rows.iter()
+                    .try_for_each(|policy| insert(transaction, policy))
+            })
+            .await?;
+        self.policies.update_policies(policies).await
+    }
+
+    async fn update_policy(
+        &self,
+        id: String,
+        policy: PolicyUpdate,
+    ) -> Result<Policy, Box<dyn Error>> {
+        let connection = self.connection.lock_arc().await;
+        let change = PolicyChange::Update {
+            id: id.clone(),
+            content: policy.content.clone(),
+        };
+        let updated = Policy {
+            id: id.clone(),
+            content: policy.content.clone(),
+        };
+        let _connection = self
+            .persist(connection, &change, move |transaction| {
+                insert(transaction, &updated)
+            })
+            .await?;
+        self.policies.update_policy(id, policy).await
+    }
+
+    async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        let connection = self.connection.lock_arc().await;
+        let change = PolicyChange::Delete { id: id.to_string() };
+        let deleted = id.to_string();
+        let _connection = self
+            .persist(connection, &change, move |transaction| {
+                transaction.execute("DELETE FROM policies WHERE id = ?1", params![deleted])?;
+                Ok(())
+            })
+            .await?;
+        self.policies.delete_policy(id).await
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_lock::Mutex;
+use log::{debug, error, info};
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client, RedisError, Script};
+use rocket::futures::StreamExt;
+
+const KEY_PREFIX: &str = "cedar-agent";
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+/// How many times a store checks and writes a change again after losing it to another replica
+pub const WRITE_ATTEMPTS: usize = 5;
+
+/// Apply a change to a hash only if its version is still the one the change was checked against,
+/// then bump the version and notify the other replicas; returns the new version, or nil on a conflict.
+/// KEYS: the version and the hash. ARGV: the expected version, the channel, the replica id,
+/// whether to replace the hash, the number of deleted fields, the deleted fields and the set field pairs
+const WRITE_SCRIPT: &str = r#"
+local version = tonumber(redis.call('GET', KEYS[1]) or '0')
+if version ~= tonumber(ARGV[1]) then
+    return false
+end
+if ARGV[4] == '1' then
+    redis.call('DEL', KEYS[2])
+end
+local deleted = tonumber(ARGV[5])
+for index = 6, 5 + deleted do
+    redis.call('HDEL', KEYS[2], ARGV[index])
+end
+for index = 6 + deleted, #ARGV, 2 do
+    redis.call('HSET', KEYS[2], ARGV[index], ARGV[index + 1])
+end
+version = redis.call('INCR', KEYS[1])
+redis.call('PUBLISH', ARGV[2], ARGV[3])
+return version
+"#;
+
+/// The name of a key or channel shared by the agent replicas
+pub fn key(name: &str) -> String {
+    format!("{}:{}", KEY_PREFIX, name)
+}
+
+/// The key holding the version of a hash, bumped by every write
+fn version_key(name: &str) -> String {
+    key(&format!("{}:version", name))
+}
+
+/// A change of a hash shared by the replicas
+#[derive(Default)]
+pub struct HashWrite {
+    /// Remove all the fields before setting the new ones
+    pub replace: bool,
+    pub deleted: Vec<String>,
+    pub set: Vec<(String, String)>,
+}
+
+/// Read a hash shared by the replicas along with its version
+pub async fn read(
+    connection: &mut ConnectionManager,
+    name: &str,
+) -> Result<(u64, HashMap<String, String>), RedisError> {
+    let (version, fields): (Option<u64>, HashMap<String, String>) = redis::pipe()
+        .atomic()
+        .get(version_key(name))
+        .hgetall(key(name))
+        .query_async(connection)
+        .await?;
+    Ok((version.unwrap_or(0), fields))
+}
+
+/// Read the version of a hash shared by the replicas
+pub async fn version(connection: &mut ConnectionManager, name: &str) -> Result<u64, RedisError> {
+    let version: Option<u64> = connection.get(version_key(name)).await?;
+    Ok(version.unwrap_or(0))
+}
+
+/// A connection to the Redis server shared by the replicas, identified by a random replica id
+/// so that each replica can ignore the notifications of its own writes
+pub struct Replica {
+    pub id: String,
+    pub url: String,
+    pub client: Client,
+    pub connection: Arc<Mutex<ConnectionManager>>,
+}
+
+impl Replica {
+    pub async fn connect(url: &str) -> Result<Self, Box<dyn Error>> {
+        let client = Client::open(url)?;
+        let connection = ConnectionManager::new(client.clone()).await?;
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            url: url.to_owned(),
+            client,
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Write a hash if its version is still `expected` and announce the change on the channel,
+    /// returning the new version or `None` when another replica wrote it first
+    pub async fn write(
+        &self,
+        connection: &mut ConnectionManager,
+        name: &str,
+        channel: &str,
+        expected: u64,
+        write: &HashWrite,
+    ) -> Result<Option<u64>, RedisError> {
+        let script = Script::new(WRITE_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(name))
+            .key(key(name))
+            .arg(expected)
+            .arg(key(channel))
+            .arg(&self.id)
+            .arg(if write.replace { "1" } else { "0" })
+            .arg(write.deleted.len())
+            .arg(&write.deleted);
+        for (field, value) in &write.set {
+            invocation.arg(field).arg(value);
+        }
+        invocation.invoke_async(connection).await
+    }
+
+    /// Call `refresh` whenever another replica publishes to the channel,
+    /// and after every reconnection as notifications may have been missed meanwhile
+    pub fn subscribe<F, R>(&self, channel: String, refresh: F)
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: Future<Output = ()> + Send,
+    {
+        let client = self.client.clone();
+        let id = self.id.clone();
+        tokio::spawn(async move {
+            let mut reconnecting = false;
+            loop {
+                if let Err(err) = listen(&client, &channel, &id, reconnecting, &refresh).await {
+                    error!(channel = channel, error:% = err; "Lost the subscription to the store changes");
+                }
+                tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+                reconnecting = true;
+            }
+        });
+    }
+}
+
+async fn listen<F, R>(
+    client: &Client,
+    channel: &str,
+    id: &str,
+    reconnecting: bool,
+    refresh: &F,
+) -> Result<(), RedisError>
+where
+    F: Fn() -> R + Sync,
+    R: Future<Output = ()> + Send,
+{
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(channel).await?;
+    if reconnecting {
+        info!(channel = channel; "Resubscribed to the store changes");
+        refresh().await;
+    }
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let writer: String = message.get_payload()?;
+        if writer != id {
+            debug!(channel = channel, writer = writer; "Refreshing the store written by another replica");
+            refresh().await;
+        }
+    }
+    Err(RedisError::from((
+        redis::ErrorKind::IoError,
+        "the subscription was closed",
+    )))
+}
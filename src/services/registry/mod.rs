@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use rocket::futures::future::BoxFuture;
+
+use crate::services::data::memory::MemoryDataStore;
+use crate::services::policies::memory::MemoryPolicyStore;
+use crate::services::{DataStore, PolicyStore};
+
+pub use crate::config::StoreUri;
+
+type Opening<S> = BoxFuture<'static, Result<Box<S>, Box<dyn Error>>>;
+
+/// Opens a policy store at the location given by its uri
+pub type PolicyStoreBackend = fn(StoreUri) -> Opening<dyn PolicyStore>;
+
+/// Opens a data store at the location given by its uri
+pub type DataStoreBackend = fn(StoreUri) -> Opening<dyn DataStore>;
+
+/// The store backends available to the agent, by uri scheme
+pub struct Registry {
+    policy_stores: BTreeMap<&'static str, PolicyStoreBackend>,
+    data_stores: BTreeMap<&'static str, DataStoreBackend>,
+}
+
+impl Registry {
+    pub fn empty() -> Self {
+        Self {
+            policy_stores: BTreeMap::new(),
+            data_stores: BTreeMap::new(),
+        }
+    }
+
+    /// The registry of the backends built into the agent, as enabled by its cargo features
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register_policy_store("memory", |_| {
+            Box::pin(async { Ok(Box::new(MemoryPolicyStore::new()) as Box<dyn PolicyStore>) })
+        });
+        registry.register_data_store("memory", |_| {
+            Box::pin(async { Ok(Box::new(MemoryDataStore::new()) as Box<dyn DataStore>) })
+        });
+        #[cfg(feature = "file")]
+        {
+            use crate::services::data::file::FileDataStore;
+            use crate::services::policies::file::FilePolicyStore;
+
+            registry.register_policy_store("file", |uri| {
+                Box::pin(async move {
+                    let store = FilePolicyStore::open(uri.location.as_ref()).await?;
+                    Ok(Box::new(store) as Box<dyn PolicyStore>)
+                })
+            });
+            registry.register_data_store("file", |uri| {
+                Box::pin(async move {
+                    let store = FileDataStore::open(uri.location.as_ref()).await?;
+                    Ok(Box::new(store) as Box<dyn DataStore>)
+                })
+            });
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            use crate::services::data::sqlite::SqliteDataStore;
+            use crate::services::policies::sqlite::SqlitePolicyStore;
+
+            registry.register_policy_store("sqlite", |uri| {
+                Box::pin(async move {
+                    let store = SqlitePolicyStore::open(uri.location.as_ref()).await?;
+                    Ok(Box::new(store) as Box<dyn PolicyStore>)
+                })
+            });
+            registry.register_data_store("sqlite", |uri| {
+                Box::pin(async move {
+                    let store = SqliteDataStore::open(uri.location.as_ref()).await?;
+                    Ok(Box::new(store) as Box<dyn DataStore>)
+                })
+            });
+        }
+        #[cfg(feature = "redis")]
+        {
+            use crate::services::data::redis::RedisDataStore;
+            use crate::services::policies::redis::RedisPolicyStore;
+
+            registry.register_policy_store("redis", |uri| {
+                Box::pin(async move {
+                    let store = RedisPolicyStore::open(&uri.to_string()).await?;
+                    Ok(Box::new(store) as Box<dyn PolicyStore>)
+                })
+            });
+            registry.register_data_store("redis", |uri| {
+                Box::pin(async move {
+                    let store = RedisDataStore::open(&uri.to_string()).await?;
+                    Ok(Box::new(store) as Box<dyn DataStore>)
+                })
+            });
+        }
+        registry
+    }
+
+    pub fn register_policy_store(&mut self, scheme: &'static str, backend: PolicyStoreBackend) {
+        self.policy_stores.insert(scheme, backend);
+    }
+
+    pub fn register_data_store(&mut self, scheme: &'static str, backend: DataStoreBackend) {
+        self.data_stores.insert(scheme, backend);
+    }
+
+    pub async fn open_policy_store(
+        &self,
+        uri: &StoreUri,
+    ) -> Result<Box<dyn PolicyStore>, Box<dyn Error>> {
+        let backend = self
+            .policy_stores
+            .get(uri.scheme.as_str())
+            .ok_or_else(|| unknown_scheme(uri, self.policy_stores.keys()))?;
+        backend(uri.clone()).await
+    }
+
+    pub async fn open_data_store(
+        &self,
+        uri: &StoreUri,
+    ) -> Result<Box<dyn DataStore>, Box<dyn Error>> {
+        let backend = self
+            .data_stores
+            .get(uri.scheme.as_str())
+            .ok_or_else(|| unknown_scheme(uri, self.data_stores.keys()))?;
+        backend(uri.clone()).await
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unknown_scheme<'a>(uri: &StoreUri, schemes: impl Iterator<Item = &'a &'static str>) -> String {
+    let schemes: Vec<&str> = schemes.copied().collect();
+    format!(
+        "unknown store {}, this build supports {}",
+        uri,
+        schemes.join(", ")
+    )
+}
@@ -0,0 +1,449 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use rocket::http::Method;
+use rocket::route::{self, Handler};
+use rocket::serde::json::serde_json;
+use rocket::{Data, Request, Route};
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config;
+use crate::errors::response::AgentError;
+use crate::services::changes::{Change, Position};
+use crate::services::snapshots::Snapshot;
+use crate::services::{DataStore, PolicyStore};
+
+const AUTHENTICATION_HEADER: &str = "Authorization";
+/// Paths of the stores a follower only changes by replicating its leader
+const REPLICATED_PATH_PREFIXES: [&str; 4] =
+    ["/v1/policies", "/v1/data", "/v1/bundle", "/v1/snapshot"];
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(30);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplicationState {
+    /// Loading the policies and data of the leader
+    Bootstrapping,
+    /// Applying the changes of the leader as they happen
+    Following,
+    /// Waiting to reconnect to the leader
+    Disconnected,
+}
+
+/// The replication of the leader by a follower
+#[derive(Serialize, JsonSchema, Clone, Debug)]
+pub struct ReplicationStatus {
+    pub leader: String,
+    pub state: ReplicationState,
+    /// The last revision of the leader applied, unknown until the state of the leader is loaded
+    pub revision: Option<u64>,
+    /// How far behind the leader the follower may be: the delay of the last change applied
+    /// while following, the time since it was last in sync otherwise
+    pub lag_seconds: Option<f64>,
+}
+
+struct Progress {
+    state: ReplicationState,
+    /// The epoch of the leader the revision belongs to
+    epoch: Option<String>,
+    revision: Option<u64>,
+    synced_at: Option<Instant>,
+    lag: Duration,
+}
+
+/// The role of the agent, either standalone or following a leader
+#[derive(Clone)]
+pub struct Replication {
+    leader: Option<String>,
+    progress: Arc<Mutex<Progress>>,
+}
+
+impl Replication {
+    pub fn standalone() -> Self {
+        Self::new(None)
+    }
+
+    pub fn follower(leader: &str) -> Self {
+        Self::new(Some(leader.trim_end_matches('/').to_owned()))
+    }
+
+    fn new(leader: Option<String>) -> Self {
+        Self {
+            leader,
+            progress: Arc::new(Mutex::new(Progress {
+                state: ReplicationState::Bootstrapping,
+                epoch: None,
+                revision: None,
+                synced_at: None,
+                lag: Duration::ZERO,
+            })),
+        }
+    }
+
+    /// The base URL of the leader, when the agent is a follower
+    pub fn leader(&self) -> Option<&str> {
+        self.leader.as_deref()
+    }
+
+    pub fn status(&self) -> Option<ReplicationStatus> {
+        let leader = self.leader.as_ref()?;
+        let progress = self.progress.lock().unwrap();
+        let lag = match progress.state {
+            ReplicationState::Following => Some(progress.lag),
+            _ => progress.synced_at.map(|synced_at| synced_at.elapsed()),
+        };
+        Some(ReplicationStatus {
+            leader: leader.clone(),
+            state: progress.state,
+            revision: progress.revision,
+            lag_seconds: lag.map(|lag| lag.as_secs_f64()),
+        })
+    }
+
+    /// Change the state, returning the previous one
+    fn set_state(&self, state: ReplicationState) -> ReplicationState {
+        let mut progress = self.progress.lock().unwrap();
+        if progress.state == ReplicationState::Following {
+            progress.synced_at = Some(Instant::now());
+        }
+        std::mem::replace(&mut progress.state, state)
+    }
+
+    fn synced(&self, revision: Option<u64>, lag: Duration) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.state = ReplicationState::Following;
+        progress.revision = revision.or(progress.revision);
+        progress.synced_at = Some(Instant::now());
+        progress.lag = lag;
+    }
+
+    /// The last position of the leader applied
+    fn position(&self) -> Option<Position> {
+        let progress = self.progress.lock().unwrap();
+        Some(Position {
+            revision: progress.revision?,
+            epoch: progress.epoch.clone()?,
+        })
+    }
+
+    fn loaded(&self, position: Position, lag: Duration) {
+        self.synced(Some(position.revision), lag);
+        self.progress.lock().unwrap().epoch = Some(position.epoch);
+    }
+}
+
+/// An event of a Server-Sent Events stream
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerSentEvent {
+    pub id: Option<String>,
+    pub event: String,
+    pub data: String,
+}
+
+/// Incremental parser of a Server-Sent Events stream, fed with the chunks of the response
+#[derive(Default)]
+pub struct EventParser {
+    buffer: String,
+}
+
+impl EventParser {
+    /// The events completed by the chunk
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<ServerSentEvent> {
+        self.buffer
+            .push_str(&String::from_utf8_lossy(chunk).replace("\r\n", "\n"));
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.find("\n\n") {
+            let block: String = self.buffer.drain(..end + 2).collect();
+            if let Some(event) = Self::parse(&block) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn parse(block: &str) -> Option<ServerSentEvent> {
+        let mut event = ServerSentEvent::default();
+        let mut data = Vec::new();
+        for line in block.lines() {
+            // Lines starting with a colon are comments, such as the heartbeats
+            if line.is_empty() || line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "id" => event.id = Some(value.to_owned()),
+                "event" => event.event = value.to_owned(),
+                "data" => data.push(value),
+                _ => {}
+            }
+        }
+        if data.is_empty() {
+            return None;
+        }
+        if event.event.is_empty() {
+            event.event = "message".to_owned();
+        }
+        event.data = data.join("\n");
+        Some(event)
+    }
+}
+
+/// Replicates the policies and data of a leader into the local stores
+pub struct Follower {
+    replication: Replication,
+    leader: String,
+    authentication: Option<String>,
+    client: reqwest::Client,
+    policy_store: Arc<dyn PolicyStore>,
+    data_store: Arc<dyn DataStore>,
+}
+
+impl Follower {
+    pub fn new(
+        replication: Replication,
+        authentication: Option<&str>,
+        policy_store: Arc<dyn PolicyStore>,
+        data_store: Arc<dyn DataStore>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let leader = replication
+            .leader()
+            .ok_or("the agent does not follow a leader")?
+            .to_owned();
+        // No overall timeout, as the change stream stays open
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            replication,
+            leader,
+            authentication: authentication.map(str::to_owned),
+            client,
+            policy_store,
+            data_store,
+        })
+    }
+
+    pub fn spawn(self) {
+        rocket::tokio::spawn(async move { self.run().await });
+    }
+
+    /// Follow the leader, reconnecting with an exponential backoff
+    async fn run(&self) {
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let err = match self.follow().await {
+                Ok(()) => "the leader closed the change stream".into(),
+                Err(err) => err,
+            };
+            if self.replication.set_state(ReplicationState::Disconnected)
+                == ReplicationState::Following
+            {
+                backoff = INITIAL_BACKOFF;
+            }
+            warn!(
+                leader = self.leader, error:% = err, retry_in:? = backoff;
+                "Lost the connection to the leader"
+            );
+            rocket::tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Subscribe to the changes of the leader, after the last position applied when known,
+    /// then apply them until the stream ends
+    async fn follow(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let since = self.replication.position();
+        let url = match since.as_ref() {
+            Some(position) => format!(
+                "{}/v1/changes?since={}&epoch={}",
+                self.leader, position.revision, position.epoch
+            ),
+            None => format!("{}/v1/changes", self.leader),
+        };
+        let mut response = self.get(&url).send().await?.error_for_status()?;
+        let mut parser = EventParser::default();
+        while let Some(chunk) = response.chunk().await? {
+            let mut last_change = None;
+            for event in parser.feed(&chunk) {
+                match event.event.as_str() {
+                    "change" => {
+                        let change: Change = serde_json::from_str(&event.data)?;
+                        match self.replication.position() {
+                            // Already in the state loaded
+                            Some(position)
+                                if position.epoch == change.epoch
+                                    && change.revision <= position.revision => {}
+                            Some(position) if position.epoch == change.epoch => {
+                                last_change = Some(change)
+                            }
+                            // The leader restarted and numbers its changes anew
+                            _ => {
+                                info!(leader = self.leader, epoch = change.epoch; "The leader restarted");
+                                self.bootstrap().await?;
+                                last_change = None;
+                            }
+                        }
+                    }
+                    // Subscribed before loading the state, so that no change is missed in between
+                    "subscribed" => {
+                        let position: Position = serde_json::from_str(&event.data)?;
+                        match since.as_ref() {
+                            Some(since) if since.epoch == position.epoch => {
+                                self.replication.synced(None, Duration::ZERO)
+                            }
+                            _ => self.bootstrap().await?,
+                        }
+                    }
+                    "reset" => {
+                        info!(leader = self.leader; "The leader no longer has the missed changes");
+                        self.bootstrap().await?;
+                        last_change = None;
+                    }
+                    _ => {}
+                }
+            }
+            // The changes received together are applied by reloading the state of the leader once
+            if let Some(change) = last_change {
+                let position = self.load().await?;
+                self.replication.loaded(position, lag(&change));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the local policies and data by those of the leader
+    async fn bootstrap(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.replication.set_state(ReplicationState::Bootstrapping);
+        let position = self.load().await?;
+        info!(leader = self.leader, revision = position.revision, epoch = position.epoch; "Loaded the state of the leader");
+        self.replication.loaded(position, Duration::ZERO);
+        Ok(())
+    }
+
+    /// Load the snapshot of the leader into the local stores, returning its position.
+    /// The policies and data of a snapshot are of the same revision of the leader
+    async fn load(&self) -> Result<Position, Box<dyn Error + Send + Sync>> {
+        let snapshot: Snapshot = self.fetch("/v1/snapshot").await?;
+        let position = snapshot
+            .position
+            .ok_or("the snapshot of the leader has no position")?;
+        self.policy_store
+            .update_policies(snapshot.policies)
+            .await
+            .map_err(|err| err.to_string())?;
+        if snapshot.entities.is_empty() {
+            self.data_store.delete_entities().await
+        } else {
+            self.data_store
+                .update_entities(snapshot.entities)
+                .await
+                .map(|_| ())
+        }
+        .map_err(|err| err.to_string())?;
+        Ok(position)
+    }
+
+    async fn fetch<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let url = format!("{}{}", self.leader, path);
+        let response = self
+            .get(&url)
+            .timeout(SNAPSHOT_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(serde_json::from_slice(&response.bytes().await?)?)
+    }
+
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        let request = self.client.get(url);
+        match self.authentication.as_ref() {
+            Some(key) => request.header(AUTHENTICATION_HEADER, key),
+            None => request,
+        }
+    }
+}
+
+/// The delay between a change on the leader and its application
+fn lag(change: &Change) -> Duration {
+    DateTime::parse_from_rfc3339(&change.timestamp)
+        .ok()
+        .and_then(|timestamp| Utc::now().signed_duration_since(timestamp).to_std().ok())
+        .unwrap_or(Duration::ZERO)
+}
+
+/// Route handler rejecting the changes of the replicated stores while following a leader
+#[derive(Clone)]
+struct ReplicationHandler(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for ReplicationHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        let leader = req
+            .rocket()
+            .state::<Replication>()
+            .and_then(Replication::leader);
+        let path = req.uri().path();
+        match leader {
+            Some(leader)
+                if req.method() != Method::Get
+                    && REPLICATED_PATH_PREFIXES
+                        .iter()
+                        .any(|prefix| path.starts_with(prefix)) =>
+            {
+                route::Outcome::from(
+                    req,
+                    AgentError::ReadOnly {
+                        leader: leader.to_owned(),
+                    },
+                )
+            }
+            _ => self.0.handle(req, data).await,
+        }
+    }
+}
+
+pub(crate) fn with_replication(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(ReplicationHandler(route.handler));
+            route
+        })
+        .collect()
+}
+
+/// Start following the leader when one is configured
+pub(crate) fn init(
+    conf: &config::Config,
+    policy_store: Arc<dyn PolicyStore>,
+    data_store: Arc<dyn DataStore>,
+) -> Result<Replication, Box<dyn Error>> {
+    let leader = match conf.leader.as_ref() {
+        Some(leader) => leader,
+        None => return Ok(Replication::standalone()),
+    };
+    let replication = Replication::follower(leader);
+    let authentication = conf.leader_authentication.as_ref().map(|key| key.expose());
+    Follower::new(
+        replication.clone(),
+        authentication,
+        policy_store,
+        data_store,
+    )?
+    .spawn();
+    info!(leader = leader; "Following the leader");
+    Ok(replication)
+}
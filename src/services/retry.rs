@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Exponential backoff between the attempts of an operation
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            initial_backoff: INITIAL_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+        }
+    }
+
+    /// Retry without limit, for a task repeated every interval:
+    /// failures are retried sooner than the interval, but never later
+    pub fn within(interval: Duration) -> Self {
+        Self {
+            max_attempts: u32::MAX,
+            initial_backoff: INITIAL_BACKOFF.min(interval),
+            max_backoff: interval,
+        }
+    }
+
+    /// The delay after the given failed attempt, doubling with every attempt,
+    /// with a random jitter of up to half the delay
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
@@ -0,0 +1,161 @@
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{error, info};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::serde::json::{serde_json, Value};
+use rocket::{Orbit, Rocket};
+use rocket_okapi::okapi::schemars;
+use rocket_okapi::okapi::schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::schemas::data::Entities;
+use crate::schemas::policies::Policy;
+use crate::services::bundles::{Bundle, Bundles};
+use crate::services::changes::Position;
+
+/// The whole state of the agent in a single document
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct Snapshot {
+    /// The revision of the active bundle, or `local.<change revision>`
+    pub revision: String,
+    /// The hex SHA-256 digest of the compact JSON array `[policies, entities, schema]`
+    pub checksum: String,
+    pub policies: Vec<Policy>,
+    pub entities: Entities,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+    /// The position of the change feed of the agent the snapshot was exported from,
+    /// for a follower to apply the changes after it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+}
+
+impl Snapshot {
+    pub fn new(
+        revision: String,
+        policies: Vec<Policy>,
+        entities: Entities,
+        schema: Option<Value>,
+    ) -> Self {
+        let checksum = checksum(&policies, &entities, &schema);
+        Self {
+            revision,
+            checksum,
+            policies,
+            entities,
+            schema,
+            position: None,
+        }
+    }
+
+    /// The snapshot exported at the position of the change feed
+    pub fn at(mut self, position: Position) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    /// Check the checksum and the schema, returning the snapshot as a bundle to activate
+    pub fn into_bundle(self) -> Result<Bundle, Box<dyn Error + Send + Sync>> {
+        if checksum(&self.policies, &self.entities, &self.schema) != self.checksum {
+            return Err("The checksum does not match the content of the snapshot".into());
+        }
+        let bundle = Bundle::new(self.revision, self.policies, self.entities, self.schema);
+        bundle.validate()?;
+        Ok(bundle)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Write the snapshot to a synced temporary file renamed over the path,
+    /// so an interrupted write never leaves a truncated snapshot
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(&serde_json::to_vec(self)?)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temporary, path)?;
+        // The rename itself is only durable once the directory is synced
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(directory)?.sync_all()?;
+        Ok(())
+    }
+}
+
+impl From<Bundle> for Snapshot {
+    fn from(bundle: Bundle) -> Self {
+        Self::new(
+            bundle.manifest.revision,
+            bundle.policies,
+            bundle.entities,
+            bundle.schema,
+        )
+    }
+}
+
+fn checksum(policies: &[Policy], entities: &Entities, schema: &Option<Value>) -> String {
+    let content = serde_json::to_vec(&(policies, entities, schema)).unwrap_or_default();
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Writes a snapshot of the stores once the server shut down gracefully, when configured
+pub struct SnapshotOnShutdownFairing {
+    path: Option<PathBuf>,
+}
+
+#[rocket::async_trait]
+impl Fairing for SnapshotOnShutdownFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Snapshot on shutdown",
+            kind: Kind::Shutdown,
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        let (path, bundles) = match (self.path.as_ref(), rocket.state::<Arc<Bundles>>()) {
+            (Some(path), Some(bundles)) => (path, bundles),
+            _ => return,
+        };
+        let snapshot = Snapshot::from(bundles.export().await);
+        match snapshot.write(path) {
+            Ok(()) => {
+                info!(path:% = path.display(), revision = snapshot.revision; "Wrote the snapshot")
+            }
+            Err(err) => {
+                error!(path:% = path.display(), error:% = err; "Failed to write the snapshot")
+            }
+        }
+    }
+}
+
+/// Restore the snapshot file when one exists, failing on an invalid snapshot,
+/// and return the fairing writing one on shutdown
+pub(crate) async fn init(
+    conf: &config::Config,
+    bundles: &Bundles,
+) -> Result<SnapshotOnShutdownFairing, Box<dyn Error>> {
+    if let Some(path) = conf.snapshot.as_ref().filter(|path| path.exists()) {
+        let bundle = Snapshot::read(path)
+            .and_then(|snapshot| snapshot.into_bundle().map_err(|err| err as Box<dyn Error>))
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let manifest = bundles.activate(bundle).await?;
+        info!(path:% = path.display(), revision = manifest.revision; "Restored the snapshot");
+    }
+    Ok(SnapshotOnShutdownFairing {
+        path: conf.snapshot_on_shutdown.clone(),
+    })
+}
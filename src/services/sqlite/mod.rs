@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::path::Path;
+use std::time::Duration;
+
+use async_lock::MutexGuardArc;
+use log::info;
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+
+use crate::services::storage::StorageError;
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Schema migrations, applied in order. The schema version of a database is the number of
+/// migrations applied to it, stored as its `user_version`. Never edit a released migration,
+/// append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: policies by id, entities by uid with their parent edges
+    "CREATE TABLE policies (
+        id TEXT PRIMARY KEY NOT NULL,
+        content TEXT NOT NULL
+    );
+    CREATE TABLE entities (
+        entity_type TEXT NOT NULL,
+        entity_id TEXT NOT NULL,
+        attrs TEXT NOT NULL,
+        PRIMARY KEY (entity_type, entity_id)
+    );
+    CREATE TABLE entity_parents (
+        entity_type TEXT NOT NULL,
+        entity_id TEXT NOT NULL,
+        parent_type TEXT NOT NULL,
+        parent_id TEXT NOT NULL,
+        PRIMARY KEY (entity_type, entity_id, parent_type, parent_id),
+        FOREIGN KEY (entity_type, entity_id)
+            REFERENCES entities (entity_type, entity_id) ON DELETE CASCADE
+    );",
+];
+
+/// The schema version the agent expects
+pub fn schema_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+/// Open the database on a blocking thread, creating it if missing, migrating it to the
+/// current schema and reading its stored rows with `read`
+pub async fn open<T: Send + 'static>(
+    path: &Path,
+    read: impl FnOnce(&Connection) -> Result<T, Box<dyn Error + Send + Sync>> + Send + 'static,
+) -> Result<(Connection, T), Box<dyn Error>> {
+    let path = path.to_path_buf();
+    rocket::tokio::task::spawn_blocking(move || {
+        let connection = connect(&path)?;
+        let rows = read(&connection)?;
+        Ok((connection, rows))
+    })
+    .await?
+    .map_err(|err: Box<dyn Error + Send + Sync>| err as Box<dyn Error>)
+}
+
+fn connect(path: &Path) -> Result<Connection, Box<dyn Error + Send + Sync>> {
+    let mut connection = Connection::open(path)?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.pragma_update(None, "foreign_keys", true)?;
+    migrate(&mut connection)?;
+    Ok(connection)
+}
+
+/// Apply the migrations missing from the database,
+/// refusing a database written by a newer agent
+pub fn migrate(connection: &mut Connection) -> Result<u32, Box<dyn Error + Send + Sync>> {
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let version: u32 = transaction.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > schema_version() {
+        return Err(format!(
+            "the database schema version {} is newer than the supported version {}",
+            version,
+            schema_version()
+        )
+        .into());
+    }
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", schema_version())?;
+    transaction.commit()?;
+    if version < schema_version() {
+        info!(from = version, to = schema_version(); "Migrated the database schema");
+    }
+    Ok(schema_version())
+}
+
+/// Run the statements in a transaction on a blocking thread, committed only if all of them
+/// succeed. The connection is handed back once committed, for the caller to apply the change
+/// to its cache before the next change is written
+pub async fn write<E>(
+    mut connection: MutexGuardArc<Connection>,
+    path: &Path,
+    statements: impl FnOnce(&Transaction) -> Result<(), E> + Send + 'static,
+) -> Result<MutexGuardArc<Connection>, StorageError>
+where
+    E: From<rusqlite::Error> + Display + Send + 'static,
+{
+    let task = rocket::tokio::task::spawn_blocking(move || {
+        let run = || -> Result<(), E> {
+            let transaction = connection.transaction()?;
+            statements(&transaction)?;
+            transaction.commit()?;
+            Ok(())
+        };
+        run().map_err(|err| err.to_string())?;
+        Ok(connection)
+    });
+    task.await
+        .map_err(|err| err.to_string())
+        .and_then(|written| written)
+        .map_err(|err| StorageError::new(path.display(), err))
+}
@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+/// A store failed to persist a change to its backend, the stored state is unchanged
+#[derive(Debug, Error)]
+#[error("Unable to persist the change to {location}: {reason}")]
+pub struct StorageError {
+    pub location: String,
+    pub reason: String,
+}
+
+impl StorageError {
+    pub fn new(location: impl ToString, reason: impl ToString) -> Self {
+        Self {
+            location: location.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::error::Error;
+use std::future::Future;
+
+use log::{error, info};
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::{FutureExt, SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::HeaderMap;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+
+use crate::common::RequestId;
+use crate::config;
+
+const TRACER_NAME: &str = "cedar-agent";
+const DEFAULT_SERVICE_NAME: &str = "cedar-agent";
+
+/// Build a tracer provider exporting the spans in batches to an OTLP/HTTP endpoint,
+/// e.g. `http://localhost:4318/v1/traces`
+pub fn tracer_provider(
+    endpoint: &str,
+    service_name: &str,
+) -> Result<SdkTracerProvider, Box<dyn Error>> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(service_name.to_owned())
+                .build(),
+        )
+        .build())
+}
+
+pub(crate) fn init(conf: &config::Config) -> Option<SdkTracerProvider> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let endpoint = conf.otlp_endpoint.as_ref()?;
+    let service_name = conf
+        .otlp_service_name
+        .as_deref()
+        .unwrap_or(DEFAULT_SERVICE_NAME);
+    match tracer_provider(endpoint, service_name) {
+        Ok(provider) => {
+            info!(endpoint = endpoint.as_str(); "Exporting traces to {}", endpoint);
+            global::set_tracer_provider(provider.clone());
+            Some(provider)
+        }
+        Err(err) => {
+            error!("Failed to initialize the trace exporter: {}", err);
+            None
+        }
+    }
+}
+
+struct HeaderExtractor<'a> {
+    headers: &'a HeaderMap<'a>,
+    names: Vec<String>,
+}
+
+impl<'a> HeaderExtractor<'a> {
+    fn new(headers: &'a HeaderMap<'a>) -> Self {
+        Self {
+            headers,
+            names: headers.iter().map(|h| h.name().to_string()).collect(),
+        }
+    }
+}
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.headers.get_one(key)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.names.iter().map(String::as_str).collect()
+    }
+}
+
+/// Trace context of the handled request, used as the parent of the spans created by the route
+#[derive(Clone)]
+pub struct TraceContext(Context);
+
+impl TraceContext {
+    /// Extract the W3C `traceparent` of the incoming request, if any
+    pub fn extract(headers: &HeaderMap) -> Self {
+        Self(global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor::new(headers))
+        }))
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.0
+    }
+
+    /// Run the future within a child span of the request
+    pub async fn in_span<F: Future>(&self, name: &'static str, future: F) -> F::Output {
+        let span = global::tracer(TRACER_NAME).start_with_context(name, &self.0);
+        let cx = self.0.with_span(span);
+        let output = future.with_context(cx.clone()).await;
+        cx.span().end();
+        output
+    }
+
+    /// Run the function within a child span of the request,
+    /// the span can be annotated using the given context
+    pub fn in_span_sync<T>(&self, name: &'static str, f: impl FnOnce(&Context) -> T) -> T {
+        let span = global::tracer(TRACER_NAME).start_with_context(name, &self.0);
+        let cx = self.0.with_span(span);
+        let output = f(&cx);
+        cx.span().end();
+        output
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TraceContext {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(request.local_cache(|| TraceContext(Context::new())).clone())
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for TraceContext {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+/// Create a server span for every request, continuing the trace of the caller
+pub struct TracingFairing;
+
+#[rocket::async_trait]
+impl Fairing for TracingFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Tracing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let parent = TraceContext::extract(req.headers());
+        let tracer = global::tracer(TRACER_NAME);
+        let span = tracer
+            .span_builder(format!("{} {}", req.method(), req.uri().path()))
+            .with_kind(SpanKind::Server)
+            .with_attributes(vec![
+                KeyValue::new("http.request.method", req.method().as_str()),
+                KeyValue::new("url.path", req.uri().path().to_string()),
+            ])
+            .start_with_context(&tracer, parent.context());
+        let cx = parent.context().with_span(span);
+        req.local_cache(|| TraceContext(cx));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let cx = req.local_cache(|| TraceContext(Context::new()));
+        let span = cx.context().span();
+        if let Some(route) = req.route() {
+            span.update_name(format!("{} {}", req.method(), route.uri));
+            span.set_attribute(KeyValue::new("http.route", route.uri.to_string()));
+        }
+        let status = res.status().code;
+        span.set_attribute(KeyValue::new("http.response.status_code", status as i64));
+        if status >= 500 {
+            span.set_status(Status::error(res.status().reason_lossy()));
+        }
+        span.end();
+        // Logged after the handler, out of the task holding the request id, so both ids are given
+        let request_id = RequestId::of(req);
+        let message = format!("{} {} responded {}", req.method(), req.uri().path(), status);
+        let span_context = span.span_context();
+        if span_context.is_valid() {
+            info!(
+                request_id = request_id.as_str(), trace_id:% = span_context.trace_id();
+                "{}", message
+            );
+        } else {
+            // Neither traced by the caller nor exported, the span has no trace id
+            info!(request_id = request_id.as_str(); "{}", message);
+        }
+    }
+}
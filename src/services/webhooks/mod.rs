@@ -0,0 +1,284 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::{debug, error, warn};
+use rocket::serde::json::serde_json;
+use rocket::tokio::fs::OpenOptions;
+use rocket::tokio::io::AsyncWriteExt;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::tokio::sync::Mutex as AsyncMutex;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::config;
+use crate::services::changes::{Change, ChangeFeed, DATA_STORE, POLICIES_STORE};
+use crate::services::retry::RetryPolicy;
+
+/// Header holding `sha256=<hex HMAC-SHA256 of the body>`, when the webhook has a secret
+pub const SIGNATURE_HEADER: &str = "X-Cedar-Agent-Signature-256";
+/// Header holding an id of the delivery, identical across its attempts
+pub const DELIVERY_HEADER: &str = "X-Cedar-Agent-Delivery";
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The signature of a payload, as sent in the signature header
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", hex)
+}
+
+/// An endpoint notified of the changes of the policies and data
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    secret: Option<String>,
+    stores: Vec<String>,
+}
+
+impl Webhook {
+    pub fn new(url: &str, secret: Option<&str>, stores: Option<Vec<String>>) -> Self {
+        Self {
+            url: url.to_owned(),
+            secret: secret.map(str::to_owned),
+            stores: stores
+                .unwrap_or_else(|| vec![POLICIES_STORE.to_owned(), DATA_STORE.to_owned()]),
+        }
+    }
+
+    fn accepts(&self, change: &Change) -> bool {
+        self.stores.contains(&change.store)
+    }
+}
+
+/// The revisions of a sequence of changes, both included
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RevisionRange {
+    pub from: u64,
+    pub to: u64,
+}
+
+/// A change that could not be delivered, or the changes skipped by a dispatcher
+/// that fell behind the history of the feed, as written to the dead-letter log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    pub timestamp: String,
+    pub url: String,
+    pub attempts: u32,
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<Change>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<RevisionRange>,
+}
+
+/// Append-only log of the failed deliveries, as JSON lines
+pub struct DeadLetterLog {
+    path: Option<PathBuf>,
+    lock: AsyncMutex<()>,
+}
+
+impl DeadLetterLog {
+    pub fn disabled() -> Self {
+        Self {
+            path: None,
+            lock: AsyncMutex::new(()),
+        }
+    }
+
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            lock: AsyncMutex::new(()),
+        }
+    }
+
+    async fn record(&self, letter: &DeadLetter) {
+        error!(
+            url = letter.url,
+            revision:? = letter.change.as_ref().map(|change| change.revision),
+            skipped:? = letter.skipped,
+            attempts = letter.attempts,
+            error = letter.error;
+            "Failed to deliver the changes to the webhook"
+        );
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return,
+        };
+        let line = match serde_json::to_string(letter) {
+            Ok(line) => line + "\n",
+            Err(err) => {
+                error!(error:% = err; "Failed to serialize the dead letter");
+                return;
+            }
+        };
+        let _lock = self.lock.lock().await;
+        let written = match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+        {
+            Ok(mut file) => file.write_all(line.as_bytes()).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = written {
+            error!(path:? = path, error:% = err; "Failed to write to the webhook dead-letter log");
+        }
+    }
+}
+
+/// Delivers the changes of the feed to a webhook, one at a time and in revision order
+pub struct Dispatcher {
+    client: reqwest::Client,
+    webhook: Webhook,
+    retry: RetryPolicy,
+    dead_letters: Arc<DeadLetterLog>,
+}
+
+impl Dispatcher {
+    pub fn new(
+        webhook: Webhook,
+        retry: RetryPolicy,
+        dead_letters: Arc<DeadLetterLog>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            webhook,
+            retry,
+            dead_letters,
+        })
+    }
+
+    /// Deliver the changes published from now on, in the background
+    pub fn spawn(self, feed: ChangeFeed) {
+        let mut since = feed.revision();
+        tokio::spawn(async move {
+            loop {
+                // Resuming from the last revision handled when falling behind the feed
+                let mut subscription = feed.subscribe(Some(since));
+                if let Some(revision) = subscription.reset {
+                    self.dead_letters
+                        .record(&DeadLetter {
+                            timestamp: Utc::now().to_rfc3339(),
+                            url: self.webhook.url.clone(),
+                            attempts: 0,
+                            error: "the changes are no longer known to the feed".to_owned(),
+                            change: None,
+                            skipped: Some(RevisionRange {
+                                from: since + 1,
+                                to: revision,
+                            }),
+                        })
+                        .await;
+                    since = revision;
+                }
+                for change in subscription.missed.drain(..) {
+                    self.deliver(&change).await;
+                    since = change.revision;
+                }
+                loop {
+                    match subscription.receiver.recv().await {
+                        Ok(change) => {
+                            self.deliver(&change).await;
+                            since = change.revision;
+                        }
+                        Err(RecvError::Lagged(_)) => break,
+                        Err(RecvError::Closed) => return,
+                    }
+                }
+            }
+        });
+    }
+
+    /// Post the change until it is accepted, writing it to the dead-letter log
+    /// once all the attempts failed
+    async fn deliver(&self, change: &Change) {
+        if !self.webhook.accepts(change) {
+            return;
+        }
+        let body = match serde_json::to_vec(change) {
+            Ok(body) => body,
+            Err(err) => {
+                error!(error:% = err; "Failed to serialize the change");
+                return;
+            }
+        };
+        let delivery = uuid::Uuid::new_v4().to_string();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let err = match self.post(&delivery, &body).await {
+                Ok(()) => {
+                    debug!(url = self.webhook.url, revision = change.revision, attempt = attempt; "Delivered the change to the webhook");
+                    return;
+                }
+                Err(err) => err,
+            };
+            if attempt >= self.retry.max_attempts {
+                self.dead_letters
+                    .record(&DeadLetter {
+                        timestamp: Utc::now().to_rfc3339(),
+                        url: self.webhook.url.clone(),
+                        attempts: attempt,
+                        error: err,
+                        change: Some(change.clone()),
+                        skipped: None,
+                    })
+                    .await;
+                return;
+            }
+            let backoff = self.retry.backoff(attempt);
+            warn!(url = self.webhook.url, attempt = attempt, error = err, backoff:? = backoff; "Retrying the webhook delivery");
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    async fn post(&self, delivery: &str, body: &[u8]) -> Result<(), String> {
+        let mut request = self
+            .client
+            .post(&self.webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(DELIVERY_HEADER, delivery)
+            .body(body.to_vec());
+        if let Some(secret) = self.webhook.secret.as_deref() {
+            request = request.header(SIGNATURE_HEADER, sign(secret, body));
+        }
+        let response = request.send().await.map_err(|err| err.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("the webhook responded {}", response.status()))
+        }
+    }
+}
+
+/// Start delivering the changes of the feed to the configured webhooks
+pub fn init(conf: &config::Config, feed: &ChangeFeed) -> Result<(), Box<dyn Error>> {
+    let retry = RetryPolicy::new(conf.webhook_max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS));
+    let dead_letters = Arc::new(match conf.webhook_dead_letter_log.clone() {
+        Some(path) => DeadLetterLog::new(path),
+        None => DeadLetterLog::disabled(),
+    });
+    for webhook in conf.webhooks.iter().flatten() {
+        let webhook = Webhook::new(
+            &webhook.url,
+            webhook.secret.as_ref().map(config::Secret::expose),
+            webhook.stores.clone(),
+        );
+        Dispatcher::new(webhook, retry, dead_letters.clone())?.spawn(feed.clone());
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::PathBuf;
+
+use rocket::serde::json::serde_json::{self, json, Value};
+
+use cedar_agent::commands::authorize::OfflineAuthorizer;
+
+async fn authorizer() -> OfflineAuthorizer {
+    OfflineAuthorizer::load(
+        PathBuf::from("examples/policies.json"),
+        Some(PathBuf::from("examples/data.json")),
+    )
+    .await
+    .unwrap()
+}
+
+/// A request file of the examples, on a single line
+fn request_line(name: &str) -> String {
+    let request: Value =
+        serde_json::from_str(&fs::read_to_string(format!("examples/{}", name)).unwrap()).unwrap();
+    request.to_string()
+}
+
+#[tokio::test]
+async fn authorize_tests() {
+    let authorizer = authorizer().await;
+    let call = serde_json::from_str(&request_line("allowed_authorization_query.json")).unwrap();
+    let answer = serde_json::to_value(authorizer.is_authorized(call).unwrap()).unwrap();
+    assert_eq!(answer["decision"], "Allow");
+    assert_eq!(answer["diagnostics"]["reason"], json!(["admins-policy"]));
+
+    let invalid = serde_json::from_str(r#"{"principal": "User::"}"#).unwrap();
+    assert!(authorizer.is_authorized(invalid).is_err());
+
+    assert!(
+        OfflineAuthorizer::load(PathBuf::from("examples/missing.json"), None)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn answer_lines_tests() {
+    let authorizer = authorizer().await;
+    let input = format!(
+        "{}\n\n{{\"principal\": \n{}\n",
+        request_line("allowed_authorization_query.json"),
+        request_line("denied_authorization_query.json")
+    );
+    let (mut output, mut errors) = (Vec::new(), Vec::new());
+    let answered = authorizer
+        .answer_lines(input.as_bytes(), &mut output, &mut errors)
+        .unwrap();
+    assert!(!answered);
+    let decisions: Vec<Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap()["decision"].clone())
+        .collect();
+    assert_eq!(decisions, vec![json!("Allow"), json!("Deny")]);
+    assert!(String::from_utf8(errors).unwrap().starts_with("line 3: "));
+}
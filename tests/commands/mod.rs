@@ -0,0 +1,2 @@
+mod authorize_tests;
+mod validate_tests;
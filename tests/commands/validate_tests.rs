@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::PathBuf;
+
+use cedar_agent::commands::validate::{validate, Diagnostic, ValidateArgs};
+
+/// Write the file in a fresh temporary directory
+fn write(name: &str, content: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("cedar-agent-validate-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join(name);
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn args(policies: Option<PathBuf>, data: Option<PathBuf>, schema: Option<PathBuf>) -> ValidateArgs {
+    ValidateArgs {
+        policies,
+        data,
+        schema,
+    }
+}
+
+#[tokio::test]
+async fn validate_examples_tests() {
+    let diagnostics = validate(&args(
+        Some(PathBuf::from("examples/policies.json")),
+        Some(PathBuf::from("examples/data.json")),
+        None,
+    ))
+    .await;
+    assert_eq!(diagnostics, vec![]);
+}
+
+#[tokio::test]
+async fn validate_errors_tests() {
+    let schema = write(
+        "schema.json",
+        r#"{"": {
+  "entityTypes": {
+    "User": {"shape": {"type": "Record", "attributes": {"age": {"type": "Long"}}}},
+    "Document": {}
+  },
+  "actions": {"view": {"appliesTo": {"principalTypes": ["User"], "resourceTypes": ["Document"]}}}
+}}"#,
+    );
+    let policies = write(
+        "policies.json",
+        r#"[
+  {"id": "valid", "content": "permit(principal == User::\"a\", action == Action::\"view\", resource);"},
+  {"id": "unparsable", "content": "permit(principal,"},
+  {
+    "id": "unknown-type",
+    "content": "permit(principal == Admin::\"b\", action == Action::\"view\", resource);"
+  },
+  {"id": "valid", "content": "permit(principal, action, resource);"}
+]"#,
+    );
+    let data = write(
+        "data.json",
+        r#"[
+  {"uid": {"type": "User", "id": "a"}, "attrs": {"age": 3}, "parents": []},
+  {"uid": {"type": "User", "id": "b"}, "attrs": {"age": "old"}, "parents": []}
+]"#,
+    );
+    let diagnostics = validate(&args(
+        Some(policies.clone()),
+        Some(data.clone()),
+        Some(schema.clone()),
+    ))
+    .await;
+    let located: Vec<(PathBuf, Option<usize>)> = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.path.clone(), diagnostic.line))
+        .collect();
+    assert_eq!(
+        located,
+        vec![
+            (policies.clone(), Some(3)),
+            (policies.clone(), Some(8)),
+            (policies.clone(), Some(4)),
+            (policies.clone(), Some(4)),
+            (data.clone(), Some(3)),
+        ]
+    );
+    assert!(diagnostics[0].message.starts_with("policy unparsable: "));
+    assert_eq!(diagnostics[1].message, "policy valid: the id is not unique");
+    assert!(diagnostics[2..4]
+        .iter()
+        .any(|diagnostic| diagnostic.message.contains("Admin")));
+    assert!(diagnostics[4]
+        .to_string()
+        .starts_with(&format!("{}:3: entity", data.display())));
+
+    let malformed = write("policies.json", "[{\"id\":\n");
+    let diagnostics: Vec<Diagnostic> = validate(&args(Some(malformed.clone()), None, None)).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("line 2"));
+    for path in [schema, policies, data, malformed] {
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use std::error::Error;
+
+use rocket::serde::json::serde_json::from_str;
+
+use cedar_agent::admin::{AdminAction, AdminAuthorizer, AdminResource};
+use cedar_agent::changes::stores::ObservedPolicyStore;
+use cedar_agent::changes::ChangeFeed;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::schemas::data::Entities;
+use cedar_agent::schemas::policies::{Policy, PolicyUpdate};
+use cedar_agent::PolicyStore;
+
+fn policy(id: &str, content: &str) -> Policy {
+    Policy {
+        id: id.to_string(),
+        content: content.to_string(),
+    }
+}
+
+fn admin_authorizer() -> AdminAuthorizer {
+    let policies = vec![
+        policy(
+            "admins",
+            r#"permit(principal in Group::"admins", action, resource);"#,
+        ),
+        policy(
+            "team-a-owned-policies",
+            r#"permit(
+                principal in Group::"team-a",
+                action in [Action::"GetPolicy", Action::"UpdatePolicy", Action::"DeletePolicy"],
+                resource
+            ) when { resource has owner && resource.owner == "team-a" };"#,
+        ),
+        policy(
+            "team-a-documents",
+            r#"permit(
+                principal in Group::"team-a",
+                action == Action::"UpdateEntities",
+                resource == EntityType::"Document"
+            );"#,
+        ),
+    ];
+    let entities: Entities = from_str(
+        r#"[
+          {"uid": {"type": "Caller", "id": "root"}, "attrs": {}, "parents": [{"type": "Group", "id": "admins"}]},
+          {"uid": {"type": "Caller", "id": "alice"}, "attrs": {}, "parents": [{"type": "Group", "id": "team-a"}]},
+          {"uid": {"type": "Group", "id": "admins"}, "attrs": {}, "parents": []},
+          {"uid": {"type": "Group", "id": "team-a"}, "attrs": {}, "parents": []}
+        ]"#,
+    )
+    .unwrap();
+    AdminAuthorizer::new(policies, Some(entities)).unwrap()
+}
+
+#[test]
+fn disabled_tests() {
+    let admin_authorizer = AdminAuthorizer::disabled();
+    assert!(!admin_authorizer.is_enabled());
+    assert!(admin_authorizer.is_allowed(
+        None,
+        AdminAction::DeleteEntities,
+        &AdminResource::Store("data")
+    ));
+}
+
+#[test]
+fn policies_tests() {
+    let admin_authorizer = admin_authorizer();
+    assert!(admin_authorizer.is_enabled());
+
+    let owned = AdminResource::policy(&policy(
+        "owned",
+        r#"@owner("team-a") permit(principal, action, resource);"#,
+    ));
+    let foreign = AdminResource::policy(&policy(
+        "foreign",
+        r#"@owner("team-b") permit(principal, action, resource);"#,
+    ));
+    let store = AdminResource::Store("policies");
+
+    assert!(admin_authorizer.is_allowed(Some("root"), AdminAction::ReplacePolicies, &store));
+    assert!(admin_authorizer.is_allowed(Some("root"), AdminAction::DeletePolicy, &foreign));
+
+    assert!(admin_authorizer.is_allowed(Some("alice"), AdminAction::UpdatePolicy, &owned));
+    assert!(!admin_authorizer.is_allowed(Some("alice"), AdminAction::UpdatePolicy, &foreign));
+    assert!(!admin_authorizer.is_allowed(Some("alice"), AdminAction::ReplacePolicies, &store));
+
+    assert!(!admin_authorizer.is_allowed(Some("mallory"), AdminAction::GetPolicy, &owned));
+    assert!(!admin_authorizer.is_allowed(None, AdminAction::ListPolicies, &store));
+}
+
+#[test]
+fn data_tests() {
+    let admin_authorizer = admin_authorizer();
+    let entities: Entities = from_str(
+        r#"[
+          {"uid": {"type": "Document", "id": "cedar-agent.pdf"}, "attrs": {}, "parents": []},
+          {"uid": {"type": "Document", "id": "readme.md"}, "attrs": {}, "parents": []}
+        ]"#,
+    )
+    .unwrap();
+    let resources = AdminResource::entity_types(&entities);
+    assert_eq!(resources.len(), 1);
+    assert_eq!(resources[0].to_string(), "EntityType::\"Document\"");
+
+    assert!(admin_authorizer.is_allowed(Some("alice"), AdminAction::UpdateEntities, &resources[0]));
+    assert!(!admin_authorizer.is_allowed(
+        Some("alice"),
+        AdminAction::UpdateEntities,
+        &AdminResource::EntityType("User".to_string())
+    ));
+    assert!(!admin_authorizer.is_allowed(
+        Some("alice"),
+        AdminAction::DeleteEntities,
+        &AdminResource::Store("data")
+    ));
+}
+
+#[tokio::test]
+async fn checked_changes_tests() {
+    let admin_authorizer = &admin_authorizer();
+    let feed = ChangeFeed::new();
+    let policy_store = ObservedPolicyStore::new(Box::new(MemoryPolicyStore::new()), feed.clone());
+    let owned = policy(
+        "owned",
+        r#"@owner("team-a") permit(principal, action, resource);"#,
+    );
+    let foreign = policy(
+        "foreign",
+        r#"@owner("team-b") permit(principal, action, resource);"#,
+    );
+    policy_store.create_policy(&owned).await.unwrap();
+    policy_store.create_policy(&foreign).await.unwrap();
+    // The check of the routes, run against the stored policy or the id of a missing one
+    let check = |caller: &'static str, action: AdminAction| {
+        move |stored: Option<&Policy>| -> Result<(), Box<dyn Error>> {
+            let resource = match stored {
+                Some(stored) => AdminResource::policy(stored),
+                None => AdminResource::missing_policy("missing"),
+            };
+            match admin_authorizer.is_allowed(Some(caller), action, &resource) {
+                true => Ok(()),
+                false => Err("forbidden".into()),
+            }
+        }
+    };
+    let update = || PolicyUpdate {
+        content: r#"@owner("team-a") forbid(principal, action, resource);"#.to_string(),
+    };
+
+    let alice_deletes = check("alice", AdminAction::DeletePolicy);
+    let err = policy_store
+        .delete_policy_checked("foreign", &alice_deletes)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "forbidden");
+    assert!(policy_store.get_policy("foreign").await.is_ok());
+    let alice_updates = check("alice", AdminAction::UpdatePolicy);
+    assert!(policy_store
+        .update_policy_checked("foreign".to_string(), update(), &alice_updates)
+        .await
+        .is_err());
+    assert_eq!(feed.revision(), 2);
+    policy_store
+        .update_policy_checked("owned".to_string(), update(), &alice_updates)
+        .await
+        .unwrap();
+    assert_eq!(feed.revision(), 3);
+
+    // A missing policy is refused to the callers denied access rather than reported missing
+    let err = policy_store
+        .delete_policy_checked("missing", &alice_deletes)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "forbidden");
+    let root_deletes = check("root", AdminAction::DeletePolicy);
+    let err = policy_store
+        .delete_policy_checked("missing", &root_deletes)
+        .await
+        .unwrap_err();
+    assert_ne!(err.to_string(), "forbidden");
+}
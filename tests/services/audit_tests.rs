@@ -0,0 +1,189 @@
+use std::fs;
+use std::sync::Arc;
+
+use rocket::serde::json::serde_json::{self, json};
+use rocket::serde::json::Value;
+
+use cedar_agent::admin::{AdminAction, AdminResource};
+use cedar_agent::audit::stores::{AuditedDataStore, AuditedPolicyStore};
+use cedar_agent::audit::{verify, AuditLog, AuditQuery, AGENT_CALLER};
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::schemas::policies::PolicyUpdate;
+use cedar_agent::{DataStore, PolicyStore};
+
+use crate::services::utils::*;
+
+#[tokio::test]
+async fn audit_log_tests() {
+    let path = std::env::temp_dir().join(format!("audit-{}.log", uuid::Uuid::new_v4()));
+    let audit_log = AuditLog::new(path.clone(), true).unwrap();
+    assert!(audit_log.is_enabled());
+    audit_log
+        .record(
+            Some("admin"),
+            AdminAction::CreatePolicy,
+            &AdminResource::Policy {
+                id: "admins-policy".to_string(),
+                annotations: Default::default(),
+            },
+            None,
+            Some(json!({"id": "admins-policy", "content": "permit(principal, action, resource);"})),
+        )
+        .await;
+    audit_log
+        .record(
+            None,
+            AdminAction::DeleteEntities,
+            &AdminResource::Store("data"),
+            Some(json!([])),
+            None,
+        )
+        .await;
+    drop(audit_log);
+
+    // Reopening the log resumes the sequence and the hash chain
+    let audit_log = AuditLog::new(path.clone(), true).unwrap();
+    audit_log
+        .record(
+            Some("admin"),
+            AdminAction::DeleteEntities,
+            &AdminResource::Store("data"),
+            Some(json!([])),
+            None,
+        )
+        .await;
+    assert_eq!(verify(&path).unwrap(), 3);
+
+    let records = audit_log.query(&AuditQuery::default()).await.unwrap();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].caller, "admin");
+    assert_eq!(records[0].object, "Policy::\"admins-policy\"");
+    assert_eq!(records[1].caller, "anonymous");
+    assert_eq!(records[2].previous_hash, records[1].hash);
+
+    let records = audit_log
+        .query(&AuditQuery {
+            since: Some(1),
+            caller: Some("admin".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].sequence, 3);
+    assert_eq!(records[0].action, "DeleteEntities");
+
+    // Any change to a record breaks the chain
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::write(&path, contents.replacen("\"admin\"", "\"mallory\"", 1)).unwrap();
+    assert!(verify(&path).is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn audited_stores_tests() {
+    let path = std::env::temp_dir().join(format!("audit-{}.log", uuid::Uuid::new_v4()));
+    let audit_log = Arc::new(AuditLog::new(path.clone(), true).unwrap());
+    let policy_store =
+        AuditedPolicyStore::new(Box::new(MemoryPolicyStore::new()), audit_log.clone());
+    let data_store = AuditedDataStore::new(Box::new(MemoryDataStore::new()), audit_log.clone());
+
+    policy_store
+        .update_policies(vec![approve_all_policy(None)])
+        .await
+        .unwrap();
+    policy_store
+        .create_policy(&approve_admin_policy(Some("admin".to_string())))
+        .await
+        .unwrap();
+    // Rejected changes are not recorded
+    assert!(policy_store
+        .create_policy(&parse_error_policy())
+        .await
+        .is_err());
+    policy_store
+        .update_policy(
+            "admin".to_string(),
+            PolicyUpdate {
+                content: approve_all_policy(None).content,
+            },
+        )
+        .await
+        .unwrap();
+    policy_store.delete_policy("test").await.unwrap();
+    data_store.update_entities(entities()).await.unwrap();
+    assert!(data_store
+        .update_entities(parse_error_entities())
+        .await
+        .is_err());
+    data_store.delete_entities().await.unwrap();
+
+    let records = audit_log.query(&AuditQuery::default()).await.unwrap();
+    let expected = [
+        ("ReplacePolicies", "Store::\"policies\""),
+        ("CreatePolicy", "Policy::\"admin\""),
+        ("UpdatePolicy", "Policy::\"admin\""),
+        ("DeletePolicy", "Policy::\"test\""),
+        ("UpdateEntities", "Store::\"data\""),
+        ("DeleteEntities", "Store::\"data\""),
+    ];
+    assert_eq!(records.len(), expected.len());
+    for (record, (action, object)) in records.iter().zip(expected) {
+        assert_eq!(record.action, action);
+        assert_eq!(record.object, object);
+        // Changes made outside of a request are recorded as made by the agent
+        assert_eq!(record.caller, AGENT_CALLER);
+    }
+    assert_eq!(records[0].before, Some(json!([])));
+    assert_eq!(records[2].before, records[1].after);
+    assert_eq!(records[3].after, None);
+    assert_eq!(records[5].before, records[4].after);
+    assert_eq!(verify(&path).unwrap(), 6);
+
+    // Replacing the entities records only those removed, added or changed
+    let stored = data_store.update_entities(entities()).await.unwrap();
+    let Value::Array(mut stored) = serde_json::to_value(stored).unwrap() else {
+        panic!("the entities are not an array");
+    };
+    let removed = stored.pop().unwrap();
+    let mut changed = stored[0].clone();
+    changed["attrs"] = json!({"changed": true});
+    let unchanged = stored[1..].to_vec();
+    stored[0] = changed.clone();
+    data_store
+        .update_entities(serde_json::from_value(Value::Array(stored)).unwrap())
+        .await
+        .unwrap();
+    let records = audit_log
+        .query(&AuditQuery {
+            since: Some(7),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    // The entities are compared by uid, as their parents are serialized in any order
+    let uids = |entities: &Option<Value>| -> Vec<Value> {
+        let Some(Value::Array(entities)) = entities else {
+            panic!("the entities are not an array");
+        };
+        entities
+            .iter()
+            .map(|entity| entity["uid"].clone())
+            .collect()
+    };
+    let before = uids(&records[0].before);
+    assert_eq!(before.len(), 2);
+    assert!(before.contains(&removed["uid"]));
+    assert!(before.contains(&changed["uid"]));
+    assert_eq!(uids(&records[0].after), vec![changed["uid"].clone()]);
+    assert_eq!(
+        records[0].after.as_ref().unwrap()[0]["attrs"],
+        changed["attrs"]
+    );
+    assert!(unchanged
+        .iter()
+        .all(|entity| !before.contains(&entity["uid"])));
+    assert_eq!(verify(&path).unwrap(), 8);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,228 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cedar_policy::PolicySet;
+
+use cedar_agent::changes::stores::{ObservedDataStore, ObservedPolicyStore};
+use cedar_agent::changes::{ChangeFeed, ChangeOperation};
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::schemas::policies::{Policy, PolicyUpdate};
+use cedar_agent::{DataStore, PolicyStore};
+
+use crate::services::utils::*;
+
+#[tokio::test]
+async fn observed_stores_tests() {
+    let feed = ChangeFeed::new();
+    let mut live = feed.subscribe(None);
+    let policy_store = ObservedPolicyStore::new(Box::new(MemoryPolicyStore::new()), feed.clone());
+    let data_store = ObservedDataStore::new(Box::new(MemoryDataStore::new()), feed.clone());
+
+    policy_store
+        .update_policies(vec![approve_all_policy(None)])
+        .await
+        .unwrap();
+    policy_store
+        .create_policy(&approve_admin_policy(Some("admin".to_string())))
+        .await
+        .unwrap();
+    // Rejected changes are not published
+    assert!(policy_store
+        .create_policy(&parse_error_policy())
+        .await
+        .is_err());
+    policy_store.delete_policy("test").await.unwrap();
+    data_store.update_entities(entities()).await.unwrap();
+    assert!(data_store
+        .update_entities(parse_error_entities())
+        .await
+        .is_err());
+    data_store.delete_entities().await.unwrap();
+    assert_eq!(feed.revision(), 5);
+
+    let expected = [
+        ("policies", ChangeOperation::Replace, None),
+        ("policies", ChangeOperation::Create, Some("admin")),
+        ("policies", ChangeOperation::Delete, Some("test")),
+        ("data", ChangeOperation::Replace, None),
+        ("data", ChangeOperation::Delete, None),
+    ];
+    for (revision, (store, operation, policy_id)) in expected.into_iter().enumerate() {
+        let change = live.receiver.recv().await.unwrap();
+        assert_eq!(change.revision, revision as u64 + 1);
+        assert_eq!(change.store, store);
+        assert_eq!(change.operation, operation);
+        assert_eq!(change.policy_id.as_deref(), policy_id);
+    }
+}
+
+#[tokio::test]
+async fn resume_tests() {
+    let feed = ChangeFeed::new();
+    for _ in 0..3 {
+        feed.publish("data", ChangeOperation::Replace, None);
+    }
+
+    let mut resumed = feed.subscribe(Some(1));
+    assert_eq!(resumed.reset, None);
+    let missed: Vec<u64> = resumed
+        .missed
+        .iter()
+        .map(|change| change.revision)
+        .collect();
+    assert_eq!(missed, vec![2, 3]);
+    feed.publish("data", ChangeOperation::Delete, None);
+    assert_eq!(resumed.receiver.recv().await.unwrap().revision, 4);
+
+    assert!(feed.subscribe(Some(4)).missed.is_empty());
+    assert_eq!(feed.subscribe(Some(0)).missed.len(), 4);
+    // A revision the feed never reached, e.g. from before a restart, requires a reload
+    let unknown = feed.subscribe(Some(10));
+    assert_eq!(unknown.reset, Some(4));
+    assert!(unknown.missed.is_empty());
+
+    // Changes older than the history require a reload as well
+    for _ in 0..1000 {
+        feed.publish("data", ChangeOperation::Replace, None);
+    }
+    assert_eq!(feed.subscribe(Some(2)).reset, Some(1004));
+    assert_eq!(feed.subscribe(Some(4)).missed.len(), 1000);
+}
+
+#[tokio::test]
+async fn epoch_tests() {
+    let feed = ChangeFeed::new();
+    let change = feed.publish("data", ChangeOperation::Replace, None);
+    assert_eq!(change.epoch, feed.epoch());
+    assert_ne!(ChangeFeed::new().epoch(), feed.epoch());
+    feed.publish("data", ChangeOperation::Delete, None);
+
+    let resumed = feed.resume(1, feed.epoch());
+    assert_eq!(resumed.revision, 2);
+    assert_eq!(resumed.reset, None);
+    assert_eq!(resumed.missed.len(), 1);
+    // The revisions of another epoch, e.g. from before a restart, require a reload
+    let restarted = feed.resume(1, "another-epoch");
+    assert_eq!(restarted.reset, Some(2));
+    assert!(restarted.missed.is_empty());
+}
+
+#[tokio::test]
+async fn restart_tests() {
+    let feed = ChangeFeed::new();
+    let first = feed.publish("data", ChangeOperation::Replace, None);
+    feed.publish("data", ChangeOperation::Delete, None);
+    assert_eq!(first.event_id(), format!("{}:1", feed.epoch()));
+
+    // A client reconnecting with the id of the last event it received gets the next changes
+    let resumed = feed.resume_after(&first.event_id());
+    assert_eq!(resumed.reset, None);
+    assert_eq!(resumed.missed.len(), 1);
+    assert_eq!(resumed.missed[0].revision, 2);
+
+    // After a restart the feed numbers its changes anew under a new epoch,
+    // so the ids of the previous run require a reload rather than skipping changes
+    let restarted = ChangeFeed::new();
+    for _ in 0..3 {
+        restarted.publish("policies", ChangeOperation::Replace, None);
+    }
+    let resumed = restarted.resume_after(&first.event_id());
+    assert_eq!(resumed.reset, Some(3));
+    assert!(resumed.missed.is_empty());
+    assert_eq!(restarted.resume(1, feed.epoch()).reset, Some(3));
+    // As does a revision whose epoch is unknown
+    assert_eq!(restarted.reset().reset, Some(3));
+    assert_eq!(restarted.resume_after("1").reset, Some(3));
+    assert_eq!(restarted.resume_after("not-an-id").reset, Some(3));
+}
+
+/// Records the order in which the policies are created, returning later for the first ones
+struct SlowPolicyStore {
+    store: MemoryPolicyStore,
+    created: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl PolicyStore for SlowPolicyStore {
+    async fn policy_set(&self) -> PolicySet {
+        self.store.policy_set().await
+    }
+
+    async fn revised_policy_set(&self) -> (PolicySet, u64) {
+        self.store.revised_policy_set().await
+    }
+
+    async fn get_policies(&self) -> Vec<Policy> {
+        self.store.get_policies().await
+    }
+
+    async fn get_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        self.store.get_policy(id).await
+    }
+
+    async fn create_policy(&self, policy: &Policy) -> Result<Policy, Box<dyn Error>> {
+        let created = self.store.create_policy(policy).await?;
+        let position = {
+            let mut order = self.created.lock().unwrap();
+            order.push(created.id.clone());
+            order.len() as u64
+        };
+        tokio::time::sleep(Duration::from_millis(50 / position)).await;
+        Ok(created)
+    }
+
+    async fn update_policies(&self, policies: Vec<Policy>) -> Result<Vec<Policy>, Box<dyn Error>> {
+        self.store.update_policies(policies).await
+    }
+
+    async fn update_policy(
+        &self,
+        id: String,
+        policy: PolicyUpdate,
+    ) -> Result<Policy, Box<dyn Error>> {
+        self.store.update_policy(id, policy).await
+    }
+
+    async fn delete_policy(&self, id: &str) -> Result<Policy, Box<dyn Error>> {
+        self.store.delete_policy(id).await
+    }
+}
+
+#[tokio::test]
+async fn revision_order_tests() {
+    let feed = ChangeFeed::new();
+    let created = Arc::new(Mutex::new(Vec::new()));
+    let policy_store = Arc::new(ObservedPolicyStore::new(
+        Box::new(SlowPolicyStore {
+            store: MemoryPolicyStore::new(),
+            created: created.clone(),
+        }),
+        feed.clone(),
+    ));
+    let writes: Vec<_> = (0..5)
+        .map(|index| {
+            let policy_store = policy_store.clone();
+            tokio::spawn(async move {
+                policy_store
+                    .create_policy(&approve_all_policy(Some(format!("policy-{}", index))))
+                    .await
+                    .unwrap();
+            })
+        })
+        .collect();
+    for write in writes {
+        write.await.unwrap();
+    }
+
+    // The revisions follow the order in which the store applied the changes
+    let published: Vec<String> = feed
+        .subscribe(Some(0))
+        .missed
+        .into_iter()
+        .map(|change| change.policy_id.unwrap())
+        .collect();
+    assert_eq!(published, *created.lock().unwrap());
+}
@@ -0,0 +1,362 @@
+use std::path::PathBuf;
+
+use log::LevelFilter;
+
+use cedar_agent::config::{legacy_env_vars, load, Config, ConfigError};
+
+fn env(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+    vars.iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// The validation errors of a configuration given in TOML
+fn errors(toml: &str) -> Vec<String> {
+    let config: Config = toml::from_str(toml).unwrap();
+    match config.validate() {
+        Err(ConfigError::Invalid(errors)) => errors,
+        other => panic!("expected validation errors for {}, got {:?}", toml, other),
+    }
+}
+
+#[test]
+fn precedence_tests() {
+    let path = std::env::temp_dir().join(format!("config-{}.toml", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "port = 1\naddr = \"file\"\nlog_level = \"warn\"\n").unwrap();
+    let config_path = path.to_str().unwrap();
+
+    // The environment takes precedence over the arguments, both over the file
+    let mut args = Config::new();
+    args.port = Some(3);
+    args.addr = Some("args".to_string());
+    let config = load(
+        args,
+        env(&[
+            ("CEDAR_AGENT_CONFIG", config_path),
+            ("CEDAR_AGENT_PORT", "2"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(config.port, Some(2));
+    assert_eq!(config.addr.as_deref(), Some("args"));
+    assert_eq!(config.log_level, Some(LevelFilter::Warn));
+
+    let mut args = Config::new();
+    args.config = Some(path.clone());
+    let config = load(args, env(&[])).unwrap();
+    assert_eq!(config.port, Some(1));
+    assert_eq!(config.addr.as_deref(), Some("file"));
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(
+        load(Config::new(), env(&[("CEDAR_AGENT_PORT", "port")])),
+        Err(ConfigError::Env(_))
+    ));
+}
+
+#[test]
+fn legacy_env_tests() {
+    // The unprefixed variables of earlier versions are still read
+    let vars = [
+        ("AUTHENTICATION", "secret"),
+        ("ADDR", "0.0.0.0"),
+        ("PORT", "2"),
+        ("LOG_LEVEL", "warn"),
+        ("DATA", "examples/data.json"),
+        ("POLICIES", "examples/policies.json"),
+    ];
+    let config = load(Config::new(), env(&vars)).unwrap();
+    assert_eq!(
+        config.authentication.as_ref().map(|key| key.expose()),
+        Some("secret")
+    );
+    assert_eq!(config.addr.as_deref(), Some("0.0.0.0"));
+    assert_eq!(config.port, Some(2));
+    assert_eq!(config.log_level, Some(LevelFilter::Warn));
+    assert_eq!(config.data, Some(PathBuf::from("examples/data.json")));
+    assert_eq!(
+        config.policies,
+        Some(PathBuf::from("examples/policies.json"))
+    );
+    assert_eq!(legacy_env_vars(env(&vars)).len(), vars.len());
+
+    // Over the arguments, under the prefixed variables
+    let mut args = Config::new();
+    args.port = Some(3);
+    let config = load(args, env(&[("PORT", "2")])).unwrap();
+    assert_eq!(config.port, Some(2));
+    let config = load(
+        Config::new(),
+        env(&[("PORT", "2"), ("CEDAR_AGENT_PORT", "4")]),
+    )
+    .unwrap();
+    assert_eq!(config.port, Some(4));
+
+    // Only the options of earlier versions are read unprefixed
+    let config = load(
+        Config::new(),
+        env(&[("LOG_FORMAT", "json"), ("HOME", "/root")]),
+    )
+    .unwrap();
+    assert_eq!(config.log_format, None);
+    assert!(legacy_env_vars(env(&[("LOG_FORMAT", "json")])).is_empty());
+}
+
+#[test]
+fn file_tests() {
+    let path = std::env::temp_dir().join(format!("config-{}.yaml", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "port: 1\nlog_levle: warn\n").unwrap();
+    match Config::from_file(&path) {
+        Err(ConfigError::UnknownKeys { keys, .. }) => assert_eq!(keys, vec!["log_levle"]),
+        other => panic!("expected unknown keys, got {:?}", other),
+    }
+    std::fs::write(&path, "port: port\n").unwrap();
+    assert!(matches!(
+        Config::from_file(&path),
+        Err(ConfigError::File { .. })
+    ));
+    std::fs::remove_file(&path).unwrap();
+
+    let path = std::env::temp_dir().join(format!("config-{}.json", uuid::Uuid::new_v4()));
+    assert!(matches!(
+        Config::from_file(&path),
+        Err(ConfigError::File { .. })
+    ));
+}
+
+#[test]
+fn validate_tests() {
+    let cases = [
+        (
+            r#"api_keys = [{ name = "a", key = "k", scopes = [] }, { name = "a", key = "l", scopes = [] }]"#,
+            "api key name a is used more than once",
+        ),
+        (
+            r#"api_keys = [{ name = "a", key = "", scopes = [] }]"#,
+            "api key a has an empty key",
+        ),
+        (
+            r#"api_keys = [{ name = "a", key = "k", key_hash = "h", scopes = [] }]"#,
+            "api key a requires exactly one of key and key_hash",
+        ),
+        (
+            r#"api_keys = [{ name = "a", scopes = [] }]"#,
+            "api key a requires exactly one of key and key_hash",
+        ),
+        (
+            "authentication = \"k\"\nauthentication_hash = \"h\"",
+            "authentication and authentication_hash are mutually exclusive",
+        ),
+        ("jwt = {}", "jwt requires jwks_files or public_keys"),
+        (
+            r#"jwt = { public_keys = ["missing.pem"] }"#,
+            "jwt key file missing.pem does not exist",
+        ),
+        (
+            r#"tls_cert = "Cargo.toml""#,
+            "tls_cert and tls_key must be configured together",
+        ),
+        (
+            r#"tls_client_ca = "Cargo.toml""#,
+            "tls_client_ca requires tls_cert and tls_key",
+        ),
+        (
+            r#"client_certs = [{ subject = "CN=client", scopes = [] }]"#,
+            "client_certs requires tls_client_ca",
+        ),
+        (
+            r#"admin_data = "Cargo.toml""#,
+            "admin_data requires admin_policies",
+        ),
+        (
+            r#"data = "missing.json""#,
+            "data file missing.json does not exist",
+        ),
+        (
+            "decision_log_sample_rate = 1.5",
+            "decision_log_sample_rate must be between 0.0 and 1.0, got 1.5",
+        ),
+        (
+            "decision_log_max_files = 0",
+            "decision_log_max_files must be greater than 0",
+        ),
+        (
+            "log_file_max_files = 0",
+            "log_file_max_files must be greater than 0",
+        ),
+        (
+            r#"policy_store = "nowhere""#,
+            "invalid store nowhere, expected <scheme>://<location> such as memory:// or file:///var/lib/cedar",
+        ),
+        (
+            "audit_log_hash_chain = true",
+            "audit_log_hash_chain requires audit_log",
+        ),
+        (
+            r#"webhooks = [{ url = "ftp://hooks" }]"#,
+            "webhook url ftp://hooks must be an http or https url",
+        ),
+        (
+            r#"webhooks = [{ url = "https://hooks", stores = ["other"] }]"#,
+            "unknown webhook store other, expected policies or data",
+        ),
+        (
+            "webhook_max_attempts = 0",
+            "webhook_max_attempts must be greater than 0",
+        ),
+        (
+            r#"webhook_dead_letter_log = "dead-letters.log""#,
+            "webhook_dead_letter_log requires webhooks",
+        ),
+        (
+            "bundle_url = \"https://bundles\"\npolicies = \"examples/policies.json\"",
+            "bundle_url cannot be combined with policies or data",
+        ),
+        (
+            r#"bundle_url = "ftp://bundles""#,
+            "bundle_url ftp://bundles must be an http or https url",
+        ),
+        (
+            r#"bundle_url_authentication = "k""#,
+            "bundle_url_authentication requires bundle_url",
+        ),
+        (
+            "bundle_poll_interval = 5",
+            "bundle_poll_interval requires bundle_url",
+        ),
+        (
+            "bundle_url = \"https://bundles\"\nbundle_poll_interval = 0",
+            "bundle_poll_interval must be greater than 0",
+        ),
+        (
+            "git_repository = \"repository\"\nbundle_url = \"https://bundles\"",
+            "git_repository cannot be combined with bundle or bundle_url",
+        ),
+        (r#"git_ref = "main""#, "git_ref requires git_repository"),
+        (r#"git_path = "agent""#, "git_path requires git_repository"),
+        (
+            "git_poll_interval = 5",
+            "git_poll_interval requires git_repository",
+        ),
+        (
+            "git_repository = \"repository\"\ngit_poll_interval = 0",
+            "git_poll_interval must be greater than 0",
+        ),
+        (
+            r#"git_repository = "--upload-pack=touch""#,
+            "git_repository must not start with '-'",
+        ),
+        (
+            "git_repository = \"repository\"\ngit_ref = \"-main\"",
+            "git_ref must not start with '-'",
+        ),
+        (
+            "git_repository = \"repository\"\ngit_ref = \"main:other\"",
+            "git_ref must not contain ':'",
+        ),
+        (
+            "snapshot = \"Cargo.toml\"\nbundle = \"Cargo.toml\"",
+            "snapshot cannot be combined with bundle",
+        ),
+        (
+            r#"snapshot = "missing.snapshot""#,
+            "snapshot file missing.snapshot does not exist",
+        ),
+        (
+            r#"bundle_signing_algorithm = "RS256""#,
+            "bundle_signing_algorithm requires bundle_public_key",
+        ),
+        (
+            "bundle_public_key = \"Cargo.toml\"\nbundle_signing_algorithm = \"HS256\"",
+            "bundle_signing_algorithm HS256 is not a public key algorithm",
+        ),
+        (
+            r#"leader = "ftp://leader""#,
+            "leader ftp://leader must be an http or https url",
+        ),
+        (
+            "leader = \"https://leader\"\ngit_repository = \"repository\"",
+            "leader cannot be combined with policies, data, bundle, bundle_url, git_repository or snapshot",
+        ),
+        (
+            r#"leader_authentication = "k""#,
+            "leader_authentication requires leader",
+        ),
+        (
+            "rate_limit_burst = 5",
+            "rate_limit_burst requires rate_limit",
+        ),
+        ("rate_limit = 0", "rate_limit must be greater than 0"),
+        (
+            "rate_limit = 5\nrate_limit_burst = 0",
+            "rate_limit_burst must be greater than 0",
+        ),
+        (
+            "decision_log_max_size = 0",
+            "decision_log_max_size must be greater than 0",
+        ),
+        (
+            "log_file_max_size = 0",
+            "log_file_max_size must be greater than 0",
+        ),
+        (
+            "data_body_limit = 0",
+            "data_body_limit must be greater than 0",
+        ),
+        (
+            "policies_body_limit = 0",
+            "policies_body_limit must be greater than 0",
+        ),
+        (
+            "authorization_body_limit = 0",
+            "authorization_body_limit must be greater than 0",
+        ),
+        (
+            "bundle_body_limit = 0",
+            "bundle_body_limit must be greater than 0",
+        ),
+    ];
+    for (toml, expected) in cases {
+        let errors = errors(toml);
+        assert!(
+            errors.iter().any(|error| error == expected),
+            "expected {:?} for {}, got {:?}",
+            expected,
+            toml,
+            errors
+        );
+    }
+}
+
+#[test]
+fn validate_accepts_tests() {
+    for toml in [
+        "",
+        "policies = \"examples/policies.json\"\ndata = \"examples/data.json\"",
+        "snapshot = \"agent.snapshot\"\nsnapshot_on_shutdown = \"agent.snapshot\"",
+        "git_repository = \"repository\"\ngit_ref = \"main\"\ngit_poll_interval = 5",
+        r#"api_keys = [{ name = "a", key = "k", scopes = ["authorize"] }]"#,
+    ] {
+        let config: Config = toml::from_str(toml).unwrap();
+        assert!(config.validate().is_ok(), "{}", toml);
+    }
+}
+
+#[test]
+fn mutual_tls_tests() {
+    let config: Config = toml::from_str(
+        "tls_cert = \"cert.pem\"\ntls_key = \"key.pem\"\ntls_client_ca = \"ca.pem\"",
+    )
+    .unwrap();
+    let figment: rocket::figment::Figment = (&config).into();
+    assert!(figment
+        .extract_inner::<bool>("tls.mutual.mandatory")
+        .unwrap());
+    assert_eq!(
+        figment
+            .extract_inner::<PathBuf>("tls.mutual.ca_certs")
+            .unwrap(),
+        PathBuf::from("ca.pem")
+    );
+}
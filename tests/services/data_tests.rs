@@ -1,8 +1,16 @@
 use std::path::PathBuf;
 
+use rocket::serde::json::serde_json;
+
 use crate::services::utils;
 
+#[cfg(feature = "file")]
+use cedar_agent::data::file::FileDataStore;
 use cedar_agent::data::memory::MemoryDataStore;
+#[cfg(feature = "redis")]
+use cedar_agent::data::redis::{entity_fields, RedisDataStore};
+#[cfg(feature = "sqlite")]
+use cedar_agent::data::sqlite::SqliteDataStore;
 use cedar_agent::data::load_from_file::load_entities_from_file;
 use cedar_agent::DataStore;
 
@@ -17,11 +25,121 @@ async fn memory_tests() {
 
     let error_entities = store.update_entities(utils::parse_error_entities()).await;
     assert!(error_entities.is_err());
-    store.delete_entities().await;
+    store.delete_entities().await.unwrap();
     let entities = store.get_entities().await;
     assert_eq!(entities.len(), 0);
 }
 
+#[cfg(feature = "file")]
+#[tokio::test]
+async fn file_tests() {
+    let dir = std::env::temp_dir().join(format!("data-{}", uuid::Uuid::new_v4()));
+    let store = FileDataStore::open(&dir).await.unwrap();
+    store.update_entities(utils::entities()).await.unwrap();
+    assert!(store
+        .update_entities(utils::parse_error_entities())
+        .await
+        .is_err());
+    drop(store);
+
+    let store = FileDataStore::open(&dir).await.unwrap();
+    assert_eq!(store.get_entities().await.len(), 8);
+    store.delete_entities().await.unwrap();
+    drop(store);
+
+    let store = FileDataStore::open(&dir).await.unwrap();
+    assert_eq!(store.get_entities().await.len(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn sqlite_tests() {
+    let path = std::env::temp_dir().join(format!("data-{}.db", uuid::Uuid::new_v4()));
+    let store = SqliteDataStore::open(&path).await.unwrap();
+    let entities = store.update_entities(utils::entities()).await.unwrap();
+    assert!(store
+        .update_entities(utils::parse_error_entities())
+        .await
+        .is_err());
+    drop(store);
+
+    // The attributes and parents of every entity survive a restart
+    let store = SqliteDataStore::open(&path).await.unwrap();
+    let mut expected = serde_json::to_value(&entities).unwrap();
+    let mut stored = serde_json::to_value(store.get_entities().await).unwrap();
+    for entities in [&mut expected, &mut stored] {
+        let entities = entities.as_array_mut().unwrap();
+        entities.sort_by_key(|entity| entity["uid"].to_string());
+        for entity in entities.iter_mut() {
+            let parents = entity["parents"].as_array_mut().unwrap();
+            parents.sort_by_key(|parent| parent.to_string());
+        }
+    }
+    assert_eq!(stored, expected);
+    store.delete_entities().await.unwrap();
+    drop(store);
+
+    let store = SqliteDataStore::open(&path).await.unwrap();
+    assert_eq!(store.get_entities().await.len(), 0);
+
+    // A change the database fails to write leaves the cached entities as is
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    connection.execute("DROP TABLE entity_parents", []).unwrap();
+    drop(connection);
+    assert!(store.update_entities(utils::entities()).await.is_err());
+    assert_eq!(store.get_entities().await.len(), 0);
+    drop(store);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_entity_fields_tests() {
+    let fields = entity_fields(&utils::entities()).unwrap();
+    assert_eq!(fields.len(), utils::entities().len());
+    assert!(fields
+        .iter()
+        .any(|(uid, _)| uid == "User::\"editor-1@domain.com\""));
+
+    // An entity whose uid cannot be read is refused rather than left out of the hash
+    let entities = serde_json::from_value(serde_json::json!([
+        { "uid": { "type": "User", "id": "somebody" }, "attrs": {}, "parents": [] },
+        { "uid": "User::\"nobody\"", "attrs": {}, "parents": [] },
+    ]))
+    .unwrap();
+    let err = entity_fields(&entities).unwrap_err();
+    assert!(err.to_string().contains("nobody"));
+}
+
+#[cfg(feature = "redis")]
+#[tokio::test]
+#[ignore = "requires a Redis server given by REDIS_URL, e.g. redis://127.0.0.1:6379"]
+async fn redis_tests() {
+    let url = std::env::var("REDIS_URL").expect("REDIS_URL is not set");
+    let first = RedisDataStore::open(&url).await.unwrap();
+    let second = RedisDataStore::open(&url).await.unwrap();
+    first.delete_entities().await.unwrap();
+    first.update_entities(utils::entities()).await.unwrap();
+    assert!(first
+        .update_entities(utils::parse_error_entities())
+        .await
+        .is_err());
+
+    // The other replica refreshes its entities once notified of the change
+    for _ in 0..50 {
+        if second.get_entities().await.len() == 8 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(second.get_entities().await.len(), 8);
+
+    let third = RedisDataStore::open(&url).await.unwrap();
+    assert_eq!(third.get_entities().await.len(), 8);
+    third.delete_entities().await.unwrap();
+}
+
 #[tokio::test]
 async fn test_load_entities_from_file() {
     let entities = load_entities_from_file(PathBuf::from("./examples/data.json")).await.unwrap();
@@ -0,0 +1,82 @@
+use std::fs;
+use std::time::Duration;
+
+use rocket::serde::json::serde_json::{from_str, json, Value};
+
+use cedar_agent::decision_log::{DecisionLogSink, DecisionLogger, DecisionRecord};
+use cedar_agent::schemas::authorization::{AuthorizationAnswer, AuthorizationCall};
+
+#[tokio::test]
+async fn file_sink_tests() {
+    let path = std::env::temp_dir().join(format!("decisions-{}.log", uuid::Uuid::new_v4()));
+    let logger = DecisionLogger::new(
+        DecisionLogSink::File {
+            path: path.clone(),
+            max_size: 1024 * 1024,
+            max_files: 1,
+        },
+        1.0,
+        vec!["ip".to_string(), "session.token".to_string()],
+    )
+    .unwrap();
+    assert!(logger.sample());
+
+    let call: AuthorizationCall = from_str(
+        r#"{
+          "principal": "User::\"admin.1@domain.com\"",
+          "action": "Action::\"create\"",
+          "resource": "Document::\"cedar-agent.pdf\"",
+          "context": {"ip": "10.0.0.1", "session": {"token": "secret", "age": 3}}
+        }"#,
+    )
+    .unwrap();
+    let answer: AuthorizationAnswer = from_str(
+        r#"{"decision": "Allow", "diagnostics": {"reason": ["admins-policy"], "errors": []}}"#,
+    )
+    .unwrap();
+    logger.log(
+        DecisionRecord::new("request-1".to_string(), "first-epoch", &call)
+            .with_answer(&answer, 7, Duration::from_micros(42))
+            .with_bundle_revision(Some("2023-06-01.1".to_string())),
+    );
+    logger.log(
+        DecisionRecord::new("request-2".to_string(), "first-epoch", &call).with_rejection(
+            "invalid principal",
+            7,
+            Duration::from_micros(5),
+        ),
+    );
+
+    let contents = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    let record: Value = from_str(lines[0]).unwrap();
+    assert_eq!(record["request_id"], "request-1");
+    assert_eq!(record["principal"], "User::\"admin.1@domain.com\"");
+    assert_eq!(record["decision"], "Allow");
+    assert_eq!(record["reasons"], json!(["admins-policy"]));
+    assert_eq!(record["policy_set_revision"], 7);
+    assert_eq!(record["epoch"], "first-epoch");
+    assert_eq!(record["bundle_revision"], "2023-06-01.1");
+    assert_eq!(record["latency_us"], 42);
+    assert_eq!(
+        record["context"],
+        json!({"ip": "****", "session": {"token": "****", "age": 3}})
+    );
+
+    // A rejected request is recorded without a decision
+    let rejected: Value = from_str(lines[1]).unwrap();
+    assert_eq!(rejected["request_id"], "request-2");
+    assert_eq!(rejected["decision"], Value::Null);
+    assert_eq!(rejected["errors"], json!(["invalid principal"]));
+    assert_eq!(rejected["policy_set_revision"], 7);
+    fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn sampling_tests() {
+    assert!(!DecisionLogger::disabled().sample());
+    let logger = DecisionLogger::new(DecisionLogSink::Stdout, 0.0, Vec::new()).unwrap();
+    assert!(!logger.sample());
+    assert!(DecisionLogger::new(DecisionLogSink::Stdout, 1.5, Vec::new()).is_err());
+}
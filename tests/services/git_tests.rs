@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cedar_agent::bundles::polling::PollOutcome;
+use cedar_agent::bundles::{Bundles, Verifier};
+use cedar_agent::changes::ChangeFeed;
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::git::{GitSource, GitSync};
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::{DataStore, PolicyStore};
+
+/// Run a Git command in the directory, with a fixed author
+fn git(directory: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_owned()
+}
+
+/// A bare repository, and a clone of it where the files are committed then pushed
+struct Repository {
+    root: PathBuf,
+    bare: PathBuf,
+    work: PathBuf,
+}
+
+impl Repository {
+    fn new() -> Self {
+        let root =
+            std::env::temp_dir().join(format!("cedar-agent-git-test-{}", uuid::Uuid::new_v4()));
+        let bare = root.join("policies.git");
+        let work = root.join("work");
+        fs::create_dir_all(&root).unwrap();
+        git(
+            &root,
+            &[
+                "init",
+                "--quiet",
+                "--bare",
+                "--initial-branch=main",
+                "policies.git",
+            ],
+        );
+        git(&root, &["clone", "--quiet", "policies.git", "work"]);
+        git(&work, &["checkout", "--quiet", "-b", "main"]);
+        Self { root, bare, work }
+    }
+
+    /// Write the files, then commit and push them, returning the SHA of the commit
+    fn commit(&self, files: &[(&str, &str)]) -> String {
+        for (name, content) in files {
+            let path = self.work.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        git(&self.work, &["add", "--all"]);
+        git(
+            &self.work,
+            &["commit", "--quiet", "--message", "Update the policies"],
+        );
+        git(&self.work, &["push", "--quiet", "origin", "main"]);
+        git(&self.work, &["rev-parse", "HEAD"])
+    }
+}
+
+impl Drop for Repository {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[tokio::test]
+async fn git_sync_tests() {
+    let repository = Repository::new();
+    let first = repository.commit(&[
+        (
+            "policies/documents.cedar",
+            r#"permit(principal == User::"alice", action, resource);
+@id("admins")
+permit(principal in Role::"admin", action, resource);"#,
+        ),
+        (
+            "policies/view.json",
+            r#"[{"id": "view", "content": "permit(principal, action == Action::\"view\", resource);"}]"#,
+        ),
+        (
+            "policies/admin/users.cedar",
+            r#"forbid(principal, action == Action::"delete", resource);"#,
+        ),
+        (
+            "data/users.json",
+            r#"[{"uid": {"type": "User", "id": "alice"}, "attrs": {}, "parents": []}]"#,
+        ),
+        ("README.md", "Ignored"),
+    ]);
+
+    let policy_store = Arc::new(MemoryPolicyStore::new());
+    let data_store = Arc::new(MemoryDataStore::new());
+    let bundles = Arc::new(Bundles::new(
+        Verifier::unverified(),
+        policy_store.clone(),
+        data_store.clone(),
+        ChangeFeed::new(),
+    ));
+    let source = GitSource::new(&repository.bare.to_string_lossy(), "main");
+    let mut sync = GitSync::new(
+        source.clone(),
+        &repository.bare.to_string_lossy(),
+        "main",
+        None,
+        Duration::from_secs(60),
+        bundles.clone(),
+    )
+    .unwrap();
+    assert!(GitSource::disabled().status().is_none());
+    assert_eq!(source.status().unwrap().commit, None);
+
+    match sync.sync().await.unwrap() {
+        PollOutcome::Activated(manifest) => assert_eq!(manifest.revision, first),
+        outcome => panic!("unexpected outcome {:?}", outcome),
+    }
+    let mut ids: Vec<String> = policy_store
+        .get_policies()
+        .await
+        .into_iter()
+        .map(|policy| policy.id)
+        .collect();
+    ids.sort();
+    assert_eq!(ids, vec!["admin.users", "admins", "documents.0", "view"]);
+    assert_eq!(data_store.get_entities().await.len(), 1);
+    assert_eq!(source.status().unwrap().commit, Some(first.clone()));
+
+    // The commit is only activated once
+    assert_eq!(sync.sync().await.unwrap(), PollOutcome::Unchanged);
+
+    let second = repository.commit(&[(
+        "policies/documents.cedar",
+        "permit(principal, action, resource);",
+    )]);
+    match sync.sync().await.unwrap() {
+        PollOutcome::Activated(manifest) => assert_eq!(manifest.revision, second),
+        outcome => panic!("unexpected outcome {:?}", outcome),
+    }
+    assert_eq!(policy_store.get_policies().await.len(), 3);
+    assert!(policy_store
+        .get_policies()
+        .await
+        .iter()
+        .any(|policy| policy.id == "documents"));
+
+    // An invalid commit is reported and the active one is kept
+    repository.commit(&[("policies/broken.cedar", "permit(principal,")]);
+    let err = sync.sync().await.unwrap_err().to_string();
+    assert!(err.contains("policies/broken.cedar"), "{}", err);
+    let status = source.status().unwrap();
+    assert_eq!(status.commit, Some(second.clone()));
+    assert_eq!(status.error, Some(err));
+    assert_eq!(
+        bundles.active().await.as_ref().unwrap().manifest.revision,
+        second
+    );
+}
+
+#[tokio::test]
+async fn git_sync_rejects_option_like_arguments() {
+    let bundles = Arc::new(Bundles::new(
+        Verifier::unverified(),
+        Arc::new(MemoryPolicyStore::new()),
+        Arc::new(MemoryDataStore::new()),
+        ChangeFeed::new(),
+    ));
+    for (repository, reference) in [
+        ("--upload-pack=touch /tmp/pwned", "main"),
+        (
+            "https://example.com/policies.git",
+            "--upload-pack=touch /tmp/pwned",
+        ),
+        ("https://example.com/policies.git", "main:refs/heads/other"),
+    ] {
+        let result = GitSync::new(
+            GitSource::new(repository, reference),
+            repository,
+            reference,
+            None,
+            Duration::from_secs(60),
+            bundles.clone(),
+        );
+        assert!(result.is_err(), "{} {}", repository, reference);
+    }
+}
@@ -0,0 +1,136 @@
+use std::time::{Duration, Instant};
+
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+
+use cedar_agent::limits::{BodyLimits, RateLimiter};
+
+/// Routes reading a limited JSON body
+mod routes {
+    // The routes generated by Rocket import their handler
+    #![allow(unused_imports)]
+
+    use rocket::post;
+
+    use cedar_agent::limits::LimitedJson;
+
+    #[post("/v1/data", data = "<body>")]
+    pub fn post_data(body: LimitedJson<Vec<u32>>) -> String {
+        body.len().to_string()
+    }
+
+    #[post("/v1/policies", data = "<body>")]
+    pub fn post_policies(body: LimitedJson<Vec<u32>>) -> String {
+        body.len().to_string()
+    }
+}
+
+#[test]
+fn rate_limiter_tests() {
+    let rate_limiter = RateLimiter::new(2, 3);
+    let start = Instant::now();
+
+    for _ in 0..3 {
+        assert!(rate_limiter.check_at("key:a", start).is_ok());
+    }
+    let wait = rate_limiter.check_at("key:a", start).unwrap_err();
+    assert_eq!(wait, Duration::from_millis(500));
+
+    // Other clients have their own bucket
+    assert!(rate_limiter.check_at("ip:127.0.0.1", start).is_ok());
+
+    // Tokens are refilled at the configured rate, up to the burst
+    assert!(rate_limiter
+        .check_at("key:a", start + Duration::from_millis(500))
+        .is_ok());
+    assert!(rate_limiter
+        .check_at("key:a", start + Duration::from_millis(500))
+        .is_err());
+    for _ in 0..3 {
+        assert!(rate_limiter
+            .check_at("key:a", start + Duration::from_secs(60))
+            .is_ok());
+    }
+    assert!(rate_limiter
+        .check_at("key:a", start + Duration::from_secs(60))
+        .is_err());
+}
+
+#[test]
+fn tracked_clients_tests() {
+    let rate_limiter = RateLimiter::new(2, 3);
+    let start = Instant::now();
+    for index in 0..10_000 {
+        assert!(rate_limiter
+            .check_at(&format!("ip:{}", index), start)
+            .is_ok());
+    }
+
+    // While the tracked clients are all active, the new ones share a bucket
+    for index in 0..3 {
+        assert!(rate_limiter
+            .check_at(&format!("ip:new-{}", index), start)
+            .is_ok());
+    }
+    assert!(rate_limiter.check_at("ip:new-3", start).is_err());
+    assert!(rate_limiter.check_at("ip:0", start).is_ok());
+
+    // Once their buckets are refilled, the idle clients are dropped
+    let later = start + Duration::from_secs(2);
+    for _ in 0..3 {
+        assert!(rate_limiter.check_at("ip:new-3", later).is_ok());
+    }
+    assert!(rate_limiter.check_at("ip:new-3", later).is_err());
+    assert!(rate_limiter.check_at("ip:new-4", later).is_ok());
+}
+
+#[test]
+fn body_limits_tests() {
+    let body_limits = BodyLimits {
+        data: Some(1024),
+        policies: Some(2048),
+        authorization: None,
+        bundle: Some(4096),
+    };
+    assert_eq!(body_limits.limit_for("/v1/data"), Some(1024));
+    assert_eq!(
+        body_limits.limit_for("/v1/policies/admins-policy"),
+        Some(2048)
+    );
+    assert_eq!(body_limits.limit_for("/v1/is_authorized"), None);
+    assert_eq!(body_limits.limit_for("/v1/bundle"), Some(4096));
+    assert_eq!(body_limits.limit_for("/swagger-ui/index.html"), None);
+}
+
+#[tokio::test]
+async fn limited_json_tests() {
+    let figment = rocket::Config::figment()
+        .merge(("limits.json", 64))
+        .merge(("limits.json/data", 16));
+    let rocket =
+        rocket::custom(figment).mount("/", routes![routes::post_data, routes::post_policies]);
+    let client = Client::untracked(rocket).await.unwrap();
+
+    let response = client.post("/v1/data").body("[1, 2, 3]").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), "3");
+
+    // The limit of the route family applies to the body read, whatever its declared size
+    let body = "[1, 2, 3, 4, 5, 6, 7, 8]";
+    let response = client.post("/v1/data").body(body).dispatch().await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+    let response = client.post("/v1/policies").body(body).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .post("/v1/policies")
+        .body(format!("[{}]", vec!["1"; 40].join(",")))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    let response = client.post("/v1/data").body("[\"a\"]").dispatch().await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let response = client.post("/v1/data").body("[1,").dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
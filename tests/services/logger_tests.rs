@@ -0,0 +1,164 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{Level, Log, Record};
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use rocket::serde::json::serde_json::{self, Value};
+
+use cedar_agent::common::{with_request_id, RequestIdFairing, REQUEST_ID_HEADER};
+use cedar_agent::config::{Config, LogFormat};
+use cedar_agent::logger;
+
+/// Routes logging a record while handling a request
+mod routes {
+    // The routes generated by Rocket import their handler
+    #![allow(unused_imports)]
+
+    use log::{Level, Log, Record};
+    use rocket::{get, State};
+
+    #[get("/logged")]
+    pub fn logged(logger: &State<Box<dyn Log>>) -> &'static str {
+        logger.log(
+            &Record::builder()
+                .level(Level::Info)
+                .target("cedar_agent::routes")
+                .args(format_args!("Handled the request"))
+                .build(),
+        );
+        logger.flush();
+        "logged"
+    }
+}
+
+fn log_path() -> PathBuf {
+    std::env::temp_dir().join(format!("cedar-agent-{}.log", uuid::Uuid::new_v4()))
+}
+
+fn config(path: &Path, format: LogFormat) -> Config {
+    Config {
+        log_file: Some(path.to_path_buf()),
+        log_format: Some(format),
+        ..Config::new()
+    }
+}
+
+/// Log a record holding structured fields, as `info!(path = ..., count = ...; ...)` does
+fn log_loaded(logger: &dyn Log) {
+    let fields: &[(&str, &dyn log::kv::ToValue)] = &[("path", &"policies.json"), ("count", &3)];
+    logger.log(
+        &Record::builder()
+            .level(Level::Info)
+            .target("cedar_agent::policies")
+            .args(format_args!("Loaded the policies"))
+            .key_values(&fields)
+            .build(),
+    );
+    logger.flush();
+}
+
+fn lines(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(str::to_owned)
+        .collect()
+}
+
+#[test]
+fn json_format_tests() {
+    let path = log_path();
+    let logger = logger::logger(&config(&path, LogFormat::Json)).unwrap();
+    log_loaded(logger.as_ref());
+
+    let lines = lines(&path);
+    assert_eq!(lines.len(), 1);
+    let line: Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(line["message"], "Loaded the policies");
+    assert_eq!(line["level"], "INFO");
+    assert_eq!(line["target"], "cedar_agent::policies");
+    assert!(line["time"].is_string());
+    assert_eq!(line["attributes"]["path"], "policies.json");
+    // The values of the fields are written as strings
+    assert_eq!(line["attributes"]["count"], "3");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn text_format_tests() {
+    let path = log_path();
+    let logger = logger::logger(&config(&path, LogFormat::Text)).unwrap();
+    log_loaded(logger.as_ref());
+
+    let lines = lines(&path);
+    assert_eq!(lines.len(), 1);
+    let (prefix, message) = lines[0].split_once(" - ").unwrap();
+    assert!(
+        prefix.ends_with(" INFO cedar_agent::policies"),
+        "{}",
+        prefix
+    );
+    assert_eq!(message, "Loaded the policies path=policies.json count=3");
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn request_id_tests() {
+    let path = log_path();
+    let logger = logger::logger(&config(&path, LogFormat::Json)).unwrap();
+    let rocket = rocket::build()
+        .attach(RequestIdFairing)
+        .manage(logger)
+        .mount("/v1", with_request_id(routes![routes::logged]));
+    let client = Client::untracked(rocket).await.unwrap();
+    let response = client
+        .get("/v1/logged")
+        .header(Header::new(REQUEST_ID_HEADER, "caller-id-3"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // The records written while handling a request hold its id
+    let lines = lines(&path);
+    let line = lines
+        .iter()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .find(|line| line["message"] == "Handled the request")
+        .unwrap();
+    assert_eq!(line["attributes"]["request_id"], "caller-id-3");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rolling_file_tests() {
+    let path = log_path();
+    let logger = logger::logger(&Config {
+        log_file_max_size: Some(200),
+        log_file_max_files: Some(2),
+        ..config(&path, LogFormat::Json)
+    })
+    .unwrap();
+    for _ in 0..20 {
+        log_loaded(logger.as_ref());
+    }
+
+    // The file is rotated once over the size, keeping the given number of rotated files,
+    // and created again by the next record
+    let rotated = |index: u32| PathBuf::from(format!("{}.{}", path.display(), index));
+    assert!(rotated(0).exists());
+    assert!(rotated(1).exists());
+    assert!(!rotated(2).exists());
+    for file in [path.clone(), rotated(0), rotated(1)] {
+        if !file.exists() {
+            continue;
+        }
+        assert!(fs::metadata(&file).unwrap().len() <= 400);
+        for line in lines(&file) {
+            let line: Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(line["attributes"]["path"], "policies.json");
+        }
+        fs::remove_file(&file).unwrap();
+    }
+}
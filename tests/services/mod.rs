@@ -1,3 +1,18 @@
+mod admin_tests;
+mod audit_tests;
+mod bundles_tests;
+mod changes_tests;
+mod config_tests;
 mod data_tests;
+mod decision_log_tests;
+mod git_tests;
+mod limits_tests;
+mod logger_tests;
 mod policies_tests;
+mod registry_tests;
+mod replication_tests;
+mod request_id_tests;
+mod snapshots_tests;
+mod telemetry_tests;
 mod utils;
+mod webhooks_tests;
@@ -5,7 +5,13 @@ use cedar_policy::PolicyId;
 
 use crate::services::utils::*;
 
+#[cfg(feature = "file")]
+use cedar_agent::policies::file::FilePolicyStore;
 use cedar_agent::policies::memory::MemoryPolicyStore;
+#[cfg(feature = "redis")]
+use cedar_agent::policies::redis::RedisPolicyStore;
+#[cfg(feature = "sqlite")]
+use cedar_agent::policies::sqlite::SqlitePolicyStore;
 use cedar_agent::schemas::policies::PolicyUpdate;
 use cedar_agent::PolicyStore;
 use cedar_agent::policies::load_from_file::load_policies_from_file;
@@ -14,11 +20,13 @@ use cedar_agent::policies::load_from_file::load_policies_from_file;
 async fn memory_tests() {
     let store = MemoryPolicyStore::new();
 
+    assert_eq!(store.revised_policy_set().await.1, 0);
     let policies = store
         .update_policies(vec![approve_all_policy(None)])
         .await
         .unwrap();
     assert_eq!(policies.len(), 1);
+    assert_eq!(store.revised_policy_set().await.1, 1);
     let duplicate_policies = store
         .update_policies(vec![approve_all_policy(None), approve_all_policy(None)])
         .await;
@@ -86,6 +94,212 @@ async fn memory_tests() {
         .is_none());
 }
 
+#[cfg(feature = "file")]
+#[tokio::test]
+async fn file_tests() {
+    let dir = std::env::temp_dir().join(format!("policies-{}", uuid::Uuid::new_v4()));
+    let store = FilePolicyStore::open(&dir).await.unwrap();
+    store
+        .update_policies(vec![approve_all_policy(None)])
+        .await
+        .unwrap();
+    store
+        .create_policy(&approve_admin_policy(Some("admin".to_string())))
+        .await
+        .unwrap();
+    assert!(store.create_policy(&parse_error_policy()).await.is_err());
+    store
+        .update_policy(
+            "admin".to_string(),
+            PolicyUpdate {
+                content: approve_all_policy(None).content,
+            },
+        )
+        .await
+        .unwrap();
+    store.delete_policy("test").await.unwrap();
+    let policies = store.get_policies().await;
+    drop(store);
+
+    let store = FilePolicyStore::open(&dir).await.unwrap();
+    assert_eq!(store.get_policies().await.len(), 1);
+    assert_eq!(policies.len(), 1);
+    assert_eq!(
+        store.get_policy("admin").await.unwrap().content,
+        policies[0].content
+    );
+
+    // Compacting the log into a snapshot keeps the latest policies
+    for index in 0..150 {
+        store
+            .create_policy(&approve_all_policy(Some(format!("policy-{}", index))))
+            .await
+            .unwrap();
+    }
+    drop(store);
+    assert!(dir.join("policies.snapshot.json").is_file());
+    let store = FilePolicyStore::open(&dir).await.unwrap();
+    assert_eq!(store.get_policies().await.len(), 151);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "file")]
+#[tokio::test]
+async fn file_recovery_tests() {
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join(format!("policies-{}", uuid::Uuid::new_v4()));
+    let store = FilePolicyStore::open(&dir).await.unwrap();
+    store
+        .create_policy(&approve_all_policy(None))
+        .await
+        .unwrap();
+    // A rejected change is not written to the log
+    assert!(store
+        .create_policy(&approve_all_policy(None))
+        .await
+        .is_err());
+    assert!(store.delete_policy("missing").await.is_err());
+    drop(store);
+
+    // A crash while appending leaves a partial last line
+    let wal = dir.join("policies.wal");
+    let mut file = std::fs::OpenOptions::new().append(true).open(&wal).unwrap();
+    file.write_all(br#"{"sequence":2,"change":{"op":"del"#)
+        .unwrap();
+    drop(file);
+
+    let store = FilePolicyStore::open(&dir).await.unwrap();
+    assert_eq!(store.get_policies().await.len(), 1);
+    store
+        .create_policy(&approve_admin_policy(Some("admin".to_string())))
+        .await
+        .unwrap();
+    drop(store);
+
+    // The change appended after the recovery starts on a line of its own
+    let store = FilePolicyStore::open(&dir).await.unwrap();
+    assert_eq!(store.get_policies().await.len(), 2);
+    assert_eq!(std::fs::read_to_string(&wal).unwrap().lines().count(), 2);
+    drop(store);
+
+    // A complete last line that cannot be read is a corruption, refused rather than discarded
+    let mut file = std::fs::OpenOptions::new().append(true).open(&wal).unwrap();
+    file.write_all(b"{\"sequence\":4,\"change\":{\"op\":\"del\n")
+        .unwrap();
+    drop(file);
+    let err = FilePolicyStore::open(&dir).await.err().unwrap();
+    assert!(err.to_string().contains("line 3"), "{}", err);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn sqlite_tests() {
+    let path = std::env::temp_dir().join(format!("policies-{}.db", uuid::Uuid::new_v4()));
+    let store = SqlitePolicyStore::open(&path).await.unwrap();
+    store
+        .update_policies(vec![approve_all_policy(None)])
+        .await
+        .unwrap();
+    store
+        .create_policy(&approve_admin_policy(Some("admin".to_string())))
+        .await
+        .unwrap();
+    assert!(store.create_policy(&parse_error_policy()).await.is_err());
+    store
+        .update_policy(
+            "admin".to_string(),
+            PolicyUpdate {
+                content: approve_all_policy(None).content,
+            },
+        )
+        .await
+        .unwrap();
+    store.delete_policy("test").await.unwrap();
+    let policies = store.get_policies().await;
+    drop(store);
+
+    let store = SqlitePolicyStore::open(&path).await.unwrap();
+    assert_eq!(store.get_policies().await.len(), 1);
+    assert_eq!(policies.len(), 1);
+    assert_eq!(
+        store.get_policy("admin").await.unwrap().content,
+        policies[0].content
+    );
+    assert_eq!(store.policy_set().await.policies().count(), 1);
+
+    // A change the database fails to write leaves the cached policies and their revision as is
+    let (_, revision) = store.revised_policy_set().await;
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    connection.execute("DROP TABLE policies", []).unwrap();
+    drop(connection);
+    assert!(store
+        .create_policy(&approve_all_policy(None))
+        .await
+        .is_err());
+    assert!(store.delete_policy("admin").await.is_err());
+    assert_eq!(store.get_policies().await.len(), 1);
+    assert_eq!(store.revised_policy_set().await.1, revision);
+    drop(store);
+
+    // A database migrated by a newer agent is refused
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    connection
+        .pragma_update(None, "user_version", 1000)
+        .unwrap();
+    drop(connection);
+    assert!(SqlitePolicyStore::open(&path).await.is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "redis")]
+#[tokio::test]
+#[ignore = "requires a Redis server given by REDIS_URL, e.g. redis://127.0.0.1:6379"]
+async fn redis_tests() {
+    let url = std::env::var("REDIS_URL").expect("REDIS_URL is not set");
+    let first = RedisPolicyStore::open(&url).await.unwrap();
+    let second = RedisPolicyStore::open(&url).await.unwrap();
+    first
+        .update_policies(vec![approve_all_policy(None)])
+        .await
+        .unwrap();
+    first
+        .create_policy(&approve_admin_policy(Some("admin".to_string())))
+        .await
+        .unwrap();
+    assert!(first.create_policy(&parse_error_policy()).await.is_err());
+    first.delete_policy("test").await.unwrap();
+
+    // The other replica refreshes its policies once notified of the change
+    for _ in 0..50 {
+        if second.get_policy("test").await.is_err() && second.get_policy("admin").await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(second.get_policies().await.len(), 1);
+    assert_eq!(second.policy_set().await.policies().count(), 1);
+
+    let third = RedisPolicyStore::open(&url).await.unwrap();
+    assert_eq!(third.get_policies().await.len(), 1);
+
+    // A replica that has not seen the last write yet checks its change again before writing it
+    let fourth = RedisPolicyStore::open(&url).await.unwrap();
+    third
+        .create_policy(&approve_all_policy(None))
+        .await
+        .unwrap();
+    assert!(fourth
+        .create_policy(&approve_all_policy(None))
+        .await
+        .is_err());
+    fourth.delete_policy("test").await.unwrap();
+    assert!(third.delete_policy("test").await.is_err());
+    assert_eq!(fourth.get_policies().await.len(), 1);
+    third.update_policies(vec![]).await.unwrap();
+}
+
 #[tokio::test]
 async fn test_load_policies_from_file() {
     let policies = load_policies_from_file(PathBuf::from("./examples/policies.json")).await.unwrap();
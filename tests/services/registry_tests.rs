@@ -0,0 +1,43 @@
+use std::str::FromStr;
+
+use cedar_agent::registry::{Registry, StoreUri};
+
+use crate::services::utils::*;
+
+#[tokio::test]
+async fn builtin_backends_tests() {
+    let registry = Registry::new();
+    let memory = StoreUri::from_str("memory://").unwrap();
+    let policy_store = registry.open_policy_store(&memory).await.unwrap();
+    policy_store
+        .update_policies(vec![approve_all_policy(None)])
+        .await
+        .unwrap();
+    assert_eq!(policy_store.get_policies().await.len(), 1);
+    let data_store = registry.open_data_store(&memory).await.unwrap();
+    assert_eq!(data_store.get_entities().await.len(), 0);
+
+    let unknown = StoreUri::from_str("etcd://localhost:2379").unwrap();
+    let err = registry.open_policy_store(&unknown).await.err().unwrap();
+    assert!(err.to_string().contains("memory"));
+    assert!(registry.open_data_store(&unknown).await.is_err());
+    assert!(StoreUri::from_str("memory").is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn sqlite_backend_tests() {
+    let path = std::env::temp_dir().join(format!("registry-{}.db", uuid::Uuid::new_v4()));
+    let uri = StoreUri::from_str(&format!("sqlite://{}", path.display())).unwrap();
+    assert_eq!(uri.location, path.display().to_string());
+    let registry = Registry::new();
+    let policy_store = registry.open_policy_store(&uri).await.unwrap();
+    policy_store
+        .update_policies(vec![approve_all_policy(None)])
+        .await
+        .unwrap();
+    drop(policy_store);
+    let policy_store = registry.open_policy_store(&uri).await.unwrap();
+    assert_eq!(policy_store.get_policies().await.len(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
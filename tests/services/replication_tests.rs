@@ -0,0 +1,178 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use rocket::serde::json::serde_json;
+use tokio::sync::broadcast;
+
+use crate::services::utils::*;
+
+use cedar_agent::changes::Position;
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::replication::{
+    EventParser, Follower, Replication, ReplicationState, ServerSentEvent,
+};
+use cedar_agent::schemas::data::Entities;
+use cedar_agent::schemas::policies::Policy;
+use cedar_agent::snapshots::Snapshot;
+use cedar_agent::{DataStore, PolicyStore};
+
+const EPOCH: &str = "first-epoch";
+
+/// The snapshot of the leader at a position of its change feed
+fn snapshot(policies: Vec<Policy>, revision: u64, epoch: &str) -> Snapshot {
+    Snapshot::new(
+        format!("local.{}", revision),
+        policies,
+        serde_json::from_str::<Entities>("[]").unwrap(),
+        None,
+    )
+    .at(Position {
+        revision,
+        epoch: epoch.to_string(),
+    })
+}
+
+/// The snapshot served by a fake leader, the number of times it was served,
+/// and the sender of its change stream starting with a `subscribed` event
+struct Leader {
+    url: String,
+    snapshot: Arc<Mutex<Snapshot>>,
+    loads: Arc<AtomicUsize>,
+    events: broadcast::Sender<String>,
+}
+
+impl Leader {
+    async fn start(policies: Vec<Policy>) -> Self {
+        let snapshot = Arc::new(Mutex::new(self::snapshot(policies, 0, EPOCH)));
+        let loads = Arc::new(AtomicUsize::new(0));
+        let (events, _) = broadcast::channel::<String>(16);
+        let (state, served, sender) = (snapshot.clone(), loads.clone(), events.clone());
+        let url = serve_http(move |request| match request.path.as_str() {
+            path if path.starts_with("/v1/changes") => {
+                let receiver = sender.subscribe();
+                let position = serde_json::json!({ "revision": 0, "epoch": EPOCH });
+                sender
+                    .send(format!("event:subscribed\ndata:{}\n\n", position))
+                    .unwrap();
+                TestResponse::Stream(receiver)
+            }
+            "/v1/snapshot" => {
+                served.fetch_add(1, Ordering::SeqCst);
+                TestResponse::ok(serde_json::to_vec(&*state.lock().unwrap()).unwrap())
+            }
+            _ => TestResponse::status(404),
+        })
+        .await;
+        Self {
+            url,
+            snapshot,
+            loads,
+            events,
+        }
+    }
+
+    fn update(&self, policies: Vec<Policy>, revision: u64, epoch: &str) {
+        *self.snapshot.lock().unwrap() = snapshot(policies, revision, epoch);
+    }
+
+    fn send(&self, event: &str, data: serde_json::Value) {
+        self.events
+            .send(format!("event:{}\ndata:{}\n\n", event, data))
+            .unwrap();
+    }
+
+    fn send_change(&self, revision: u64, epoch: &str) {
+        self.send(
+            "change",
+            serde_json::json!({
+                "revision": revision,
+                "epoch": epoch,
+                "timestamp": Utc::now().to_rfc3339(),
+                "store": "policies",
+                "operation": "replace",
+            }),
+        );
+    }
+}
+
+#[test]
+fn event_parser_tests() {
+    let mut parser = EventParser::default();
+    assert!(parser
+        .feed(b": heartbeat\n\nevent:change\nid:1\n")
+        .is_empty());
+    let events = parser.feed(b"data:{\"revision\":1}\n\ndata: first\r\ndata: second\r\n\r\n");
+    assert_eq!(
+        events,
+        vec![
+            ServerSentEvent {
+                id: Some("1".to_string()),
+                event: "change".to_string(),
+                data: "{\"revision\":1}".to_string(),
+            },
+            ServerSentEvent {
+                id: None,
+                event: "message".to_string(),
+                data: "first\nsecond".to_string(),
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn follower_tests() {
+    let leader = Leader::start(vec![approve_all_policy(None)]).await;
+    let replication = Replication::follower(&format!("{}/", leader.url));
+    assert!(Replication::standalone().status().is_none());
+    let policy_store = Arc::new(MemoryPolicyStore::new());
+    let data_store = Arc::new(MemoryDataStore::new());
+    Follower::new(
+        replication.clone(),
+        None,
+        policy_store.clone(),
+        data_store.clone(),
+    )
+    .unwrap()
+    .spawn();
+
+    // Bootstrapped from the snapshot of the leader
+    wait_for(|| replication.status().unwrap().state == ReplicationState::Following).await;
+    let status = replication.status().unwrap();
+    assert_eq!(status.leader, leader.url);
+    assert_eq!(status.revision, Some(0));
+    assert_eq!(policy_store.get_policies().await.len(), 1);
+    assert_eq!(data_store.get_entities().await.len(), 0);
+
+    // A change reloads the snapshot, at the revision of the snapshot rather than of the change
+    let admin = approve_admin_policy(Some("admin".to_string()));
+    leader.update(vec![approve_all_policy(None), admin.clone()], 2, EPOCH);
+    leader.send_change(1, EPOCH);
+    wait_for(|| replication.status().unwrap().revision == Some(2)).await;
+    assert_eq!(policy_store.get_policies().await.len(), 2);
+    assert!(replication.status().unwrap().lag_seconds.unwrap() < 5.0);
+
+    // A change already in the snapshot loaded is skipped
+    leader.send_change(2, EPOCH);
+
+    // A change of another epoch, from the restarted leader, reloads the whole state
+    leader.update(vec![admin], 5, "second-epoch");
+    leader.send_change(3, "second-epoch");
+    wait_for(|| replication.status().unwrap().revision == Some(5)).await;
+    let policies = policy_store.get_policies().await;
+    assert_eq!(policies.len(), 1);
+    assert_eq!(policies[0].id, "admin");
+
+    // A reset reloads the whole state
+    leader.update(vec![approve_all_policy(None)], 7, "third-epoch");
+    leader.send(
+        "reset",
+        serde_json::json!({ "revision": 7, "epoch": "third-epoch" }),
+    );
+    wait_for(|| replication.status().unwrap().revision == Some(7)).await;
+    let policies = policy_store.get_policies().await;
+    assert_eq!(policies.len(), 1);
+    assert_eq!(policies[0].id, "test");
+    assert_eq!(leader.loads.load(Ordering::SeqCst), 4);
+}
@@ -0,0 +1,151 @@
+use rocket::catchers;
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::{Client, LocalRequest, LocalResponse};
+use rocket::routes;
+use rocket::serde::json::{serde_json, Value};
+
+use cedar_agent::common::{with_request_id, RequestIdFairing, REQUEST_ID_HEADER};
+use cedar_agent::errors::catchers;
+
+/// Routes answering with each kind of response
+mod routes {
+    // The routes generated by Rocket import their handler
+    #![allow(unused_imports)]
+
+    use rocket::http::Status;
+    use rocket::{get, post};
+
+    use cedar_agent::errors::response::AgentError;
+    use cedar_agent::limits::LimitedJson;
+
+    #[get("/found")]
+    pub fn found() -> &'static str {
+        "found"
+    }
+
+    #[get("/unauthenticated")]
+    pub fn unauthenticated() -> Status {
+        Status::Unauthorized
+    }
+
+    #[get("/missing")]
+    pub fn missing() -> Result<&'static str, AgentError> {
+        Err(AgentError::NotFound {
+            object: "policy",
+            id: "missing".to_owned(),
+        })
+    }
+
+    #[post("/data", data = "<body>")]
+    pub fn post_data(body: LimitedJson<Vec<u32>>) -> String {
+        body.len().to_string()
+    }
+}
+
+async fn client() -> Client {
+    let rocket = rocket::build()
+        .attach(RequestIdFairing)
+        .register(
+            "/",
+            catchers![
+                catchers::handle_401,
+                catchers::handle_404,
+                catchers::handle_422
+            ],
+        )
+        .mount(
+            "/v1",
+            with_request_id(routes![
+                routes::found,
+                routes::unauthenticated,
+                routes::missing,
+                routes::post_data
+            ]),
+        );
+    Client::untracked(rocket).await.unwrap()
+}
+
+fn request_id(response: &LocalResponse<'_>) -> String {
+    response
+        .headers()
+        .get_one(REQUEST_ID_HEADER)
+        .expect("the response has no request id")
+        .to_owned()
+}
+
+/// The request id of the header and of the body of an error response, which must match
+async fn error_request_id(response: LocalResponse<'_>, status: Status) -> String {
+    assert_eq!(response.status(), status);
+    let header = request_id(&response);
+    let body: Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], status.code);
+    assert_eq!(body["request_id"], header.as_str());
+    header
+}
+
+#[tokio::test]
+async fn request_id_header_tests() {
+    let client = client().await;
+
+    // The id given by the caller is echoed
+    let response = client
+        .get("/v1/found")
+        .header(Header::new(REQUEST_ID_HEADER, "caller-id-1"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(request_id(&response), "caller-id-1");
+
+    // Otherwise an id is generated for each request
+    let first = request_id(&client.get("/v1/found").dispatch().await);
+    let second = request_id(&client.get("/v1/found").dispatch().await);
+    assert!(uuid::Uuid::parse_str(&first).is_ok(), "{}", first);
+    assert_ne!(first, second);
+
+    // An invalid id is replaced
+    for invalid in ["", "with space", &"a".repeat(129)] {
+        let response = client
+            .get("/v1/found")
+            .header(Header::new(REQUEST_ID_HEADER, invalid.to_owned()))
+            .dispatch()
+            .await;
+        assert!(uuid::Uuid::parse_str(&request_id(&response)).is_ok());
+    }
+}
+
+#[tokio::test]
+async fn error_request_id_tests() {
+    let client = client().await;
+    fn with_id(request: LocalRequest<'_>) -> LocalRequest<'_> {
+        request.header(Header::new(REQUEST_ID_HEADER, "caller-id-2"))
+    }
+
+    // Errors of the routes
+    let response = with_id(client.get("/v1/missing")).dispatch().await;
+    assert_eq!(
+        error_request_id(response, Status::NotFound).await,
+        "caller-id-2"
+    );
+
+    // Errors answered by the catchers, with an id whether given or generated
+    let response = with_id(client.get("/v1/unknown")).dispatch().await;
+    assert_eq!(
+        error_request_id(response, Status::NotFound).await,
+        "caller-id-2"
+    );
+    let response = with_id(client.get("/v1/unauthenticated")).dispatch().await;
+    assert_eq!(
+        error_request_id(response, Status::Unauthorized).await,
+        "caller-id-2"
+    );
+    let response = with_id(client.post("/v1/data").body(r#"["a"]"#))
+        .dispatch()
+        .await;
+    assert_eq!(
+        error_request_id(response, Status::UnprocessableEntity).await,
+        "caller-id-2"
+    );
+    let response = client.get("/v1/unknown").dispatch().await;
+    let generated = error_request_id(response, Status::NotFound).await;
+    assert!(uuid::Uuid::parse_str(&generated).is_ok());
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::sync::Arc;
+
+use rocket::serde::json::serde_json;
+
+use crate::services::utils::*;
+
+use cedar_agent::bundles::{Bundles, Verifier};
+use cedar_agent::changes::ChangeFeed;
+use cedar_agent::data::memory::MemoryDataStore;
+use cedar_agent::policies::memory::MemoryPolicyStore;
+use cedar_agent::snapshots::Snapshot;
+use cedar_agent::{DataStore, PolicyStore};
+
+fn bundles(change_feed: ChangeFeed) -> Bundles {
+    Bundles::new(
+        Verifier::unverified(),
+        Arc::new(MemoryPolicyStore::new()),
+        Arc::new(MemoryDataStore::new()),
+        change_feed,
+    )
+}
+
+#[tokio::test]
+async fn snapshot_tests() {
+    let change_feed = ChangeFeed::new();
+    let source = bundles(change_feed.clone());
+    let policy_store = Arc::new(MemoryPolicyStore::new());
+    policy_store
+        .update_policies(vec![approve_all_policy(None)])
+        .await
+        .unwrap();
+    let data_store = Arc::new(MemoryDataStore::new());
+    data_store.update_entities(entities()).await.unwrap();
+    let exported = Bundles::new(
+        Verifier::unverified(),
+        policy_store,
+        data_store,
+        ChangeFeed::new(),
+    )
+    .export()
+    .await;
+    let snapshot = Snapshot::from(exported);
+    assert_eq!(snapshot.revision, "local.0");
+    assert_eq!(snapshot.policies.len(), 1);
+
+    // Written and read back unchanged
+    let path = std::env::temp_dir().join(format!(
+        "cedar-agent-snapshot-{}.json",
+        uuid::Uuid::new_v4()
+    ));
+    snapshot.write(&path).unwrap();
+    let read = Snapshot::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(read.checksum, snapshot.checksum);
+
+    let manifest = source
+        .activate(read.clone().into_bundle().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(manifest.revision, "local.0");
+    let restored = Snapshot::from(source.export().await);
+    assert_eq!(restored.revision, "local.0");
+    assert_eq!(restored.policies.len(), 1);
+    assert_eq!(restored.entities.len(), entities().len());
+
+    // Exported along with the position of the change feed
+    let (bundle, position) = source.export_at().await;
+    let positioned = serde_json::to_value(Snapshot::from(bundle).at(position)).unwrap();
+    assert_eq!(positioned["position"]["revision"], 0);
+    assert_eq!(positioned["position"]["epoch"], change_feed.epoch());
+
+    // A snapshot changed after it was taken is rejected
+    let mut tampered: serde_json::Value = serde_json::to_value(&read).unwrap();
+    tampered["policies"] = serde_json::json!([]);
+    let tampered: Snapshot = serde_json::from_value(tampered).unwrap();
+    assert!(tampered.into_bundle().is_err());
+}
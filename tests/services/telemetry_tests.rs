@@ -0,0 +1,96 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+
+use opentelemetry::global;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use rocket::http::{Header, HeaderMap};
+
+use cedar_agent::telemetry::{tracer_provider, TraceContext};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+/// Accept OTLP/HTTP export requests and forward their bodies
+fn local_collector() -> (String, mpsc::Receiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            let body = loop {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_lowercase();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length: usize = text
+                        .lines()
+                        .find_map(|l| l.strip_prefix("content-length:"))
+                        .map(|l| l.trim().parse().unwrap())
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break request[end + 4..end + 4 + length].to_vec();
+                    }
+                }
+            };
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            if sender.send(body).is_err() {
+                return;
+            }
+        }
+    });
+    (format!("http://{}/v1/traces", addr), receiver)
+}
+
+#[tokio::test]
+async fn otlp_export_tests() {
+    let (endpoint, receiver) = local_collector();
+    let provider = tracer_provider(&endpoint, "cedar-agent-test").unwrap();
+    global::set_tracer_provider(provider.clone());
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let mut headers = HeaderMap::new();
+    headers.add(Header::new(
+        "traceparent",
+        format!("00-{}-00f067aa0ba902b7-01", TRACE_ID),
+    ));
+    let trace_context = TraceContext::extract(&headers);
+    assert_eq!(
+        trace_context
+            .context()
+            .span()
+            .span_context()
+            .trace_id()
+            .to_string(),
+        TRACE_ID
+    );
+
+    let value = trace_context
+        .in_span("PolicyStore::policy_set", async { 42 })
+        .await;
+    assert_eq!(value, 42);
+    let decision = trace_context.in_span_sync("Authorizer::is_authorized", |cx| {
+        assert!(cx.span().span_context().is_valid());
+        "Allow"
+    });
+    assert_eq!(decision, "Allow");
+    provider.force_flush().unwrap();
+
+    let body = receiver.recv().unwrap();
+    let contains = |needle: &[u8]| body.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"PolicyStore::policy_set"));
+    assert!(contains(b"Authorizer::is_authorized"));
+    assert!(contains(b"cedar-agent-test"));
+    let trace_id: Vec<u8> = (0..TRACE_ID.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&TRACE_ID[i..i + 2], 16).unwrap())
+        .collect();
+    assert!(contains(&trace_id));
+    provider.shutdown().unwrap();
+}
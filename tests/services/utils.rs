@@ -1,4 +1,11 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
 use rocket::serde::json::serde_json::from_str;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
 
 use cedar_agent::schemas::data::Entities;
 use cedar_agent::schemas::policies::Policy;
@@ -186,3 +193,130 @@ pub(crate) fn parse_error_entities() -> Entities {
     "#;
     from_str(entities_json).unwrap()
 }
+
+/// A request received by the test HTTP server, with its header names in lowercase
+pub(crate) struct TestRequest {
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// The answer of the test HTTP server to a request
+pub(crate) enum TestResponse {
+    Complete {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    },
+    /// A `text/event-stream` response writing every message of the channel
+    Stream(broadcast::Receiver<String>),
+}
+
+impl TestResponse {
+    pub(crate) fn status(status: u16) -> Self {
+        Self::Complete {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub(crate) fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::Complete {
+            status: 200,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub(crate) fn header(mut self, name: &str, value: &str) -> Self {
+        if let Self::Complete { headers, .. } = &mut self {
+            headers.push((name.to_owned(), value.to_owned()));
+        }
+        self
+    }
+}
+
+/// Serve HTTP/1.1 on a local port, a request per connection, answering with the handler,
+/// and return the base URL of the server
+pub(crate) async fn serve_http(
+    handler: impl Fn(TestRequest) -> TestResponse + Send + Sync + 'static,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                let path = line.split(' ').nth(1).unwrap_or("/").to_owned();
+                let mut headers = HashMap::new();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).await.unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((name, value)) => {
+                            headers.insert(name.to_lowercase(), value.trim().to_owned())
+                        }
+                        None => break,
+                    };
+                }
+                let length = headers
+                    .get("content-length")
+                    .map_or(0, |length| length.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).await.unwrap();
+                let mut stream = reader.into_inner();
+                match handler(TestRequest {
+                    path,
+                    headers,
+                    body,
+                }) {
+                    TestResponse::Complete {
+                        status,
+                        headers,
+                        body,
+                    } => {
+                        let mut head = format!(
+                            "HTTP/1.1 {} Status\r\ncontent-length: {}\r\nconnection: close\r\n",
+                            status,
+                            body.len()
+                        );
+                        for (name, value) in headers {
+                            head.push_str(&format!("{}: {}\r\n", name, value));
+                        }
+                        head.push_str("\r\n");
+                        let _ = stream.write_all(head.as_bytes()).await;
+                        let _ = stream.write_all(&body).await;
+                    }
+                    TestResponse::Stream(mut messages) => {
+                        let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n";
+                        if stream.write_all(head.as_bytes()).await.is_err() {
+                            return;
+                        }
+                        while let Ok(message) = messages.recv().await {
+                            if stream.write_all(message.as_bytes()).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    });
+    url
+}
+
+/// Wait up to 2 seconds for the condition to hold
+pub(crate) async fn wait_for(condition: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
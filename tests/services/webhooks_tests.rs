@@ -0,0 +1,147 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rocket::serde::json::serde_json;
+
+use crate::services::utils::*;
+
+use cedar_agent::changes::{ChangeFeed, ChangeOperation};
+use cedar_agent::retry::RetryPolicy;
+use cedar_agent::webhooks::{
+    sign, DeadLetter, DeadLetterLog, Dispatcher, RevisionRange, Webhook, DELIVERY_HEADER,
+    SIGNATURE_HEADER,
+};
+
+/// Listen for webhook requests, responding with the given statuses then with 200
+async fn listen(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<TestRequest>>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let requests = received.clone();
+    let statuses = Mutex::new(statuses.into_iter());
+    let url = serve_http(move |request| {
+        requests.lock().unwrap().push(request);
+        TestResponse::status(statuses.lock().unwrap().next().unwrap_or(200))
+    })
+    .await;
+    (format!("{}/hook", url), received)
+}
+
+fn retry_policy(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(50),
+    }
+}
+
+#[tokio::test]
+async fn delivery_tests() {
+    let (url, received) = listen(vec![500]).await;
+    let feed = ChangeFeed::new();
+    let webhook = Webhook::new(&url, Some("secret"), Some(vec!["policies".to_string()]));
+    Dispatcher::new(
+        webhook,
+        retry_policy(3),
+        Arc::new(DeadLetterLog::disabled()),
+    )
+    .unwrap()
+    .spawn(feed.clone());
+
+    // Changes of the other stores are not sent
+    feed.publish("data", ChangeOperation::Replace, None);
+    feed.publish(
+        "policies",
+        ChangeOperation::Create,
+        Some("admin".to_string()),
+    );
+    wait_for(|| received.lock().unwrap().len() == 2).await;
+
+    // The first attempt fails and is retried with the same delivery id and signed payload
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 2);
+    assert_eq!(
+        received[0].headers[&DELIVERY_HEADER.to_lowercase()],
+        received[1].headers[&DELIVERY_HEADER.to_lowercase()]
+    );
+    for request in received.iter() {
+        assert_eq!(
+            request.headers[&SIGNATURE_HEADER.to_lowercase()],
+            sign("secret", &request.body)
+        );
+        let change: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(change["revision"], 2);
+        assert_eq!(change["policy_id"], "admin");
+    }
+}
+
+#[tokio::test]
+async fn dead_letter_tests() {
+    let (url, received) = listen(vec![500; 10]).await;
+    let path = std::env::temp_dir().join(format!("dead-letters-{}.log", uuid::Uuid::new_v4()));
+    let feed = ChangeFeed::new();
+    let dead_letters = Arc::new(DeadLetterLog::new(path.clone()));
+    Dispatcher::new(
+        Webhook::new(&url, None, None),
+        retry_policy(2),
+        dead_letters,
+    )
+    .unwrap()
+    .spawn(feed.clone());
+
+    feed.publish("data", ChangeOperation::Delete, None);
+    wait_for(|| path.exists()).await;
+    assert_eq!(received.lock().unwrap().len(), 2);
+    assert!(!received.lock().unwrap()[0]
+        .headers
+        .contains_key(&SIGNATURE_HEADER.to_lowercase()));
+    let content = std::fs::read_to_string(&path).unwrap();
+    let letters: Vec<DeadLetter> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].url, url);
+    assert_eq!(letters[0].attempts, 2);
+    assert_eq!(letters[0].change.as_ref().unwrap().revision, 1);
+    assert_eq!(letters[0].skipped, None);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn skipped_changes_tests() {
+    let (url, received) = listen(Vec::new()).await;
+    let path = std::env::temp_dir().join(format!("dead-letters-{}.log", uuid::Uuid::new_v4()));
+    let feed = ChangeFeed::new();
+    Dispatcher::new(
+        Webhook::new(&url, None, None),
+        retry_policy(2),
+        Arc::new(DeadLetterLog::new(path.clone())),
+    )
+    .unwrap()
+    .spawn(feed.clone());
+
+    // More changes than the history of the feed are published before the dispatcher starts
+    for _ in 0..1200 {
+        feed.publish("data", ChangeOperation::Replace, None);
+    }
+    wait_for(|| path.exists()).await;
+    let content = std::fs::read_to_string(&path).unwrap();
+    let letters: Vec<DeadLetter> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].attempts, 0);
+    assert_eq!(letters[0].change, None);
+    assert_eq!(
+        letters[0].skipped,
+        Some(RevisionRange { from: 1, to: 1200 })
+    );
+
+    // The following changes are delivered
+    feed.publish("data", ChangeOperation::Delete, None);
+    wait_for(|| received.lock().unwrap().len() == 1).await;
+    let change: serde_json::Value =
+        serde_json::from_slice(&received.lock().unwrap()[0].body).unwrap();
+    assert_eq!(change["revision"], 1201);
+    std::fs::remove_file(&path).unwrap();
+}